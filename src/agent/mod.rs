@@ -0,0 +1,508 @@
+// A lot of magic numbers here
+
+pub mod window;
+pub mod size;
+pub mod priority;
+pub use window::{CongestionControl, Window};
+pub use size::{PacketSize, SizeSampler};
+pub use priority::{PriorityMix, PrioritySampler};
+
+use crate::network::{Address, Ecn, Feedback, Packet, PacketId, Protocol};
+use crate::network::transport::Sender;
+use crate::metrics::MetricsCollector;
+use crate::simulation::clock;
+use tokio::sync::Notify;
+use tokio::time::Duration;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Poll;
+use std::sync::atomic::{AtomicU64, Ordering};
+use parking_lot::Mutex;
+use tracing::{info, warn, debug};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rand_distr::{Distribution, Exp};
+use serde::{Deserialize, Serialize};
+
+pub struct Agent {
+    id: u32,
+    server_addrs: Vec<Address>,
+    packet_counter: AtomicU64,
+    metrics: MetricsCollector,
+    // Stream i is flows[i], there's always at least one
+    flows: Vec<Flow>,
+    // One per server, shared by the flows. Async so a flow waits for the one sending instead of dialing again
+    connections: Vec<tokio::sync::Mutex<Option<Sender>>>,
+    rng: Mutex<StdRng>,
+    ecn: Ecn,
+    // Next sequence number per server, None when the run doesn't use them
+    sequence: Option<Vec<AtomicU64>>,
+}
+
+/// One of an agent's concurrent flows. Own schedule, sizes, priority and congestion state,
+/// the agent's connections and server choice are shared
+#[derive(Debug)]
+struct Flow {
+    traffic_pattern: TrafficPattern,
+    traffic_rng: StdRng,
+    packet_size: SizeSampler,
+    priority: PrioritySampler,
+    congestion: Arc<Congestion>,
+}
+
+impl Flow {
+    fn new(traffic_pattern: TrafficPattern, rng: &mut StdRng, metrics: MetricsCollector) -> Self {
+        // Split off a separate stream for inter-arrival times so routing and timing don't interleave
+        let traffic_rng = StdRng::from_rng(rng).expect("StdRng never fails to seed");
+        let window = match traffic_pattern {
+            TrafficPattern::Window { congestion_control } => Some(Mutex::new(Window::new(congestion_control))),
+            _ => None,
+        };
+        Self {
+            traffic_pattern,
+            traffic_rng,
+            packet_size: SizeSampler::default(),
+            priority: PrioritySampler::default(),
+            congestion: Arc::new(Congestion {
+                metrics,
+                backoff: Mutex::new(EcnBackoff::new()),
+                window,
+                opened: Notify::new(),
+            }),
+        }
+    }
+
+    /// Prague flows always send ECT(1), that's what gets them into an L4S queue. The rest go by the agent's setting
+    fn ecn(&self, agent: Ecn) -> Ecn {
+        match self.traffic_pattern {
+            TrafficPattern::Window { congestion_control: CongestionControl::Prague } => Ecn::Ect1,
+            _ => agent,
+        }
+    }
+    
+    fn protocol(&self) -> Protocol {
+        match self.congestion.window {
+            Some(_) => Protocol::Windowed,
+            None => Protocol::Datagram,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "kind")]
+pub enum TrafficPattern {
+    Constant { rate_pps: f64 },
+    Bursty { avg_rate_pps: f64, burst_size: u32 },
+    Poisson { lambda: f64 },
+    PeakTraffic { base_rate: f64, peak_rate: f64, peak_duration_s: f64 },
+    // Closed loop, sends whenever the congestion window has room
+    Window { congestion_control: CongestionControl },
+}
+
+impl TrafficPattern {
+    /// `path` is where the pattern sits in the config (traffic_pattern, groups.0.traffic_pattern, ...), for the error.
+    /// A rate of 0 would mean an endless gap, the schedulers can't add that to a clock
+    pub fn validate(&self, path: &str) -> anyhow::Result<()> {
+        let positive = |name: &str, value: f64| {
+            anyhow::ensure!(value > 0.0 && value.is_finite(), "{}.{} must be > 0, got {}", path, name, value);
+            Ok(())
+        };
+
+        match *self {
+            TrafficPattern::Constant { rate_pps } => positive("rate_pps", rate_pps)?,
+            TrafficPattern::Bursty { avg_rate_pps, burst_size } => {
+                positive("avg_rate_pps", avg_rate_pps)?;
+                anyhow::ensure!(burst_size > 0, "{}.burst_size must be at least 1", path);
+            }
+            TrafficPattern::Poisson { lambda } => positive("lambda", lambda)?,
+            TrafficPattern::PeakTraffic { base_rate, peak_rate, peak_duration_s } => {
+                positive("base_rate", base_rate)?;
+                positive("peak_rate", peak_rate)?;
+                anyhow::ensure!(
+                    peak_duration_s >= 0.0 && peak_duration_s.is_finite(),
+                    "{}.peak_duration_s must be >= 0, got {}",
+                    path,
+                    peak_duration_s
+                );
+            }
+            TrafficPattern::Window { .. } => {}
+        }
+        Ok(())
+    }
+}
+
+// Spacing between packets inside a burst, magic number
+const BURST_SPACING: Duration = Duration::from_micros(100);
+
+// ECN reaction for open-loop patterns: halve the rate on an echo, creep back up per packet sent.
+// At most one cut per window, there's no RTT estimate here to do better. Magic numbers again
+const ECN_CUT_WINDOW: Duration = Duration::from_millis(100);
+const ECN_MIN_RATE_SCALE: f64 = 1.0 / 16.0;
+const ECN_RECOVERY_STEP: f64 = 0.01;
+
+/// How far an ECN-capable agent has slowed down its schedule in response to CE echoes
+#[derive(Debug)]
+struct EcnBackoff {
+    rate_scale: f64,
+    last_cut: Option<std::time::Instant>,
+}
+
+impl EcnBackoff {
+    fn new() -> Self {
+        Self {
+            rate_scale: 1.0,
+            last_cut: None,
+        }
+    }
+    
+    fn on_feedback(&mut self, feedback: &Feedback) {
+        if !feedback.ce() {
+            return;
+        }
+        
+        let now = clock::now();
+        if self.last_cut.is_some_and(|t| now.duration_since(t) < ECN_CUT_WINDOW) {
+            return;
+        }
+        
+        self.rate_scale = (self.rate_scale * 0.5).max(ECN_MIN_RATE_SCALE);
+        self.last_cut = Some(now);
+    }
+    
+    fn on_send(&mut self) {
+        self.rate_scale = (self.rate_scale + ECN_RECOVERY_STEP).min(1.0);
+    }
+}
+
+/// Everything that reacts to server feedback. Shared with the connection reader tasks.
+/// Open-loop patterns only use the ECN backoff, window agents only the window.
+#[derive(Debug)]
+struct Congestion {
+    metrics: MetricsCollector,
+    backoff: Mutex<EcnBackoff>,
+    window: Option<Mutex<Window>>,
+    // Wakes a window agent blocked on a full window
+    opened: Notify,
+}
+
+impl Congestion {
+    fn on_feedback(&self, feedback: &Feedback) {
+        self.metrics.packet_acked(feedback);
+        
+        match &self.window {
+            Some(window) => {
+                let mut window = window.lock();
+                window.on_feedback(feedback, clock::now());
+                self.metrics.record_window(feedback.source_agent, window.cwnd(), window.srtt());
+                if window.can_send() {
+                    self.opened.notify_one();
+                }
+            }
+            None => self.backoff.lock().on_feedback(feedback),
+        }
+    }
+}
+
+/// Departure schedule of a traffic pattern.
+/// Both backends walk the same schedule, real-time sleeps between departures, discrete-event queues them.
+#[derive(Debug, Clone)]
+pub struct TrafficSchedule {
+    pattern: TrafficPattern,
+    started: bool,
+    burst_left: u32,
+    // Poisson only, built once from the validated lambda
+    exp: Option<Exp<f64>>,
+    rng: StdRng,
+}
+
+impl TrafficSchedule {
+    pub fn new(pattern: TrafficPattern, rng: StdRng) -> Self {
+        let exp = match pattern {
+            TrafficPattern::Poisson { lambda } => {
+                Some(Exp::new(lambda).expect("lambda is checked by TrafficPattern::validate"))
+            }
+            _ => None,
+        };
+        Self {
+            pattern,
+            started: false,
+            burst_left: 0,
+            exp,
+            rng,
+        }
+    }
+    
+    /// Time until the next packet leaves, `elapsed` is the time since the agent started
+    pub fn next_gap(&mut self, elapsed: Duration) -> Duration {
+        let first = !self.started;
+        self.started = true;
+        
+        match self.pattern {
+            TrafficPattern::Constant { rate_pps } => {
+                if first {
+                    return Duration::ZERO;
+                }
+                Duration::from_millis((1000.0 / rate_pps).max(1.0) as u64)
+            }
+            TrafficPattern::Bursty { avg_rate_pps, burst_size } => {
+                if self.burst_left > 0 {
+                    self.burst_left -= 1;
+                    return BURST_SPACING;
+                }
+                
+                self.burst_left = burst_size.saturating_sub(1);
+                if first {
+                    return Duration::ZERO;
+                }
+                
+                // Bursts start on a fixed grid, the previous burst already used part of the interval
+                let burst_interval = Duration::from_millis((burst_size as f64 / avg_rate_pps * 1000.0) as u64);
+                burst_interval.saturating_sub(BURST_SPACING * burst_size.saturating_sub(1))
+            }
+            TrafficPattern::Poisson { .. } => match self.exp {
+                Some(exp) => Duration::from_secs_f64(exp.sample(&mut self.rng)),
+                None => unreachable!("Poisson schedules always have their distribution"),
+            },
+            TrafficPattern::PeakTraffic { base_rate, peak_rate, peak_duration_s } => {
+                let rate = if elapsed < Duration::from_secs_f64(peak_duration_s) {
+                    peak_rate
+                } else {
+                    base_rate
+                };
+                Duration::from_millis((1000.0 / rate).max(1.0) as u64)
+            }
+            // Back to back, the window is what holds the agent back
+            TrafficPattern::Window { .. } => Duration::ZERO,
+        }
+    }
+}
+
+impl Agent {
+    pub fn new(
+        id: u32,
+        server_addrs: Vec<Address>,
+        metrics: MetricsCollector,
+        traffic_pattern: TrafficPattern,
+        mut rng: StdRng,
+    ) -> Self {
+        let num_servers = server_addrs.len();
+        let flow = Flow::new(traffic_pattern, &mut rng, metrics.clone());
+        Self {
+            id,
+            server_addrs,
+            packet_counter: AtomicU64::new(0),
+            metrics,
+            flows: vec![flow],
+            connections: (0..num_servers).map(|_| tokio::sync::Mutex::new(None)).collect(),
+            rng: Mutex::new(rng),
+            ecn: Ecn::NotEct,
+            sequence: None,
+        }
+    }
+    
+    /// Sends ECT(0) packets and backs off when servers echo CE marks
+    pub fn with_ecn(mut self, enabled: bool) -> Self {
+        self.ecn = if enabled { Ecn::Ect0 } else { Ecn::NotEct };
+        self
+    }
+    
+    /// For the first flow, the ones added with `with_flow` bring their own
+    pub fn with_packet_size(mut self, sampler: SizeSampler) -> Self {
+        self.flows[0].packet_size = sampler;
+        self
+    }
+    
+    /// For the first flow, same as the packet size
+    pub fn with_priority(mut self, sampler: PrioritySampler) -> Self {
+        self.flows[0].priority = sampler;
+        self
+    }
+    
+    /// Another flow next to the ones the agent has, running at the same time with its own pattern
+    pub fn with_flow(mut self, traffic_pattern: TrafficPattern, packet_size: SizeSampler, priority: PrioritySampler) -> Self {
+        let mut flow = Flow::new(traffic_pattern, self.rng.get_mut(), self.metrics.clone());
+        flow.packet_size = packet_size;
+        flow.priority = priority;
+        self.flows.push(flow);
+        self
+    }
+    
+    /// Numbers packets per server so the servers can spot loss and reordering on the way
+    pub fn with_sequence_numbers(mut self, enabled: bool) -> Self {
+        self.sequence = enabled.then(|| self.server_addrs.iter().map(|_| AtomicU64::new(0)).collect());
+        self
+    }
+    
+    pub fn on_feedback(&self, feedback: &Feedback) {
+        if let Some(flow) = self.flows.get(feedback.stream as usize) {
+            flow.congestion.on_feedback(feedback);
+        }
+    }
+    
+    /// Stretches a gap from `flow`'s schedule by its current ECN backoff
+    pub(crate) fn pace(&self, flow: usize, gap: Duration) -> Duration {
+        gap.div_f64(self.flows[flow].congestion.backoff.lock().rate_scale)
+    }
+    
+    /// None if `flow` may send right now. A window flow with a full window gets the time
+    /// until its oldest packet times out, it should check again then (or on feedback, whichever is first)
+    pub(crate) fn window_wait(&self, flow: usize) -> Option<Duration> {
+        let mut window = self.flows[flow].congestion.window.as_ref()?.lock();
+        let now = clock::now();
+        if window.expire(now) {
+            // Timeouts are what a blocked flow's window changes on, record it here or it goes stale
+            self.metrics.record_window(self.id, window.cwnd(), window.srtt());
+        }
+        if window.can_send() {
+            return None;
+        }
+        // Full window with nothing in flight can't happen, cwnd never drops below one
+        window.next_timeout(now)
+    }
+    
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+    
+    pub fn flow_count(&self) -> usize {
+        self.flows.len()
+    }
+    
+    /// Fresh departure schedule for `flow`'s pattern, always replays the same random stream
+    pub fn schedule(&self, flow: usize) -> TrafficSchedule {
+        let flow = &self.flows[flow];
+        TrafficSchedule::new(flow.traffic_pattern.clone(), flow.traffic_rng.clone())
+    }
+    
+    pub async fn run(self: Arc<Self>) -> anyhow::Result<()> {
+        // Every flow in this one task, so dropping it stops all of them right there
+        let mut flows: Vec<Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + '_>>> = (0..self.flows.len())
+            .map(|flow| Box::pin(self.run_flow(flow)) as Pin<Box<dyn Future<Output = _> + Send + '_>>)
+            .collect();
+        
+        std::future::poll_fn(|cx| {
+            // Flows only return on an error, the first one ends the agent
+            for flow in &mut flows {
+                if let Poll::Ready(result) = flow.as_mut().poll(cx) {
+                    return Poll::Ready(result);
+                }
+            }
+            Poll::Pending
+        })
+        .await
+    }
+    
+    async fn run_flow(&self, f: usize) -> anyhow::Result<()> {
+        let flow = &self.flows[f];
+        info!("Agent {} flow {} starting with pattern {:?}", self.id, f, flow.traffic_pattern);
+        
+        if flow.congestion.window.is_some() {
+            loop {
+                match self.window_wait(f) {
+                    None => self.send_packet(f).await,
+                    Some(timeout) => {
+                        let _ = tokio::time::timeout(timeout, flow.congestion.opened.notified()).await;
+                    }
+                }
+            }
+        }
+        
+        let mut schedule = self.schedule(f);
+        let start = tokio::time::Instant::now();
+        let mut next_departure = start;
+        
+        // Sleep until absolute deadlines so the schedule doesn't drift with send time
+        loop {
+            next_departure += self.pace(f, schedule.next_gap(next_departure - start));
+            tokio::time::sleep_until(next_departure).await;
+            self.send_packet(f).await;
+        }
+    }
+    
+    /// Builds the next packet of `flow` and picks the server it goes to
+    pub(crate) fn next_packet(&self, f: usize) -> (usize, Packet) {
+        let flow = &self.flows[f];
+        let packet_id = self.packet_counter.fetch_add(1, Ordering::Relaxed);
+        let (server_idx, size, priority) = {
+            let mut rng = self.rng.lock();
+            let server_idx = rng.gen_range(0..self.server_addrs.len());
+            let size = flow.packet_size.sample(&mut *rng);
+            (server_idx, size, flow.priority.sample(&mut *rng))
+        };
+        
+        // Discrete-event addresses may be a subset of the servers (a topology's sinks), the id is what routes go by
+        let destination = match self.server_addrs[server_idx] {
+            Address::Virtual(id) => id,
+            _ => server_idx as u32,
+        };
+        let packet = Packet::new(
+            PacketId::new(packet_id),
+            self.id,
+            destination,
+            size,
+            priority,
+        )
+        .with_ecn(flow.ecn(self.ecn))
+        .with_flow(f as u16, flow.protocol())
+        .with_seq(self.sequence.as_ref().map(|s| s[server_idx].fetch_add(1, Ordering::Relaxed)));
+        
+        match &flow.congestion.window {
+            Some(window) => {
+                let mut window = window.lock();
+                window.on_send(packet.id, clock::now());
+                self.metrics.record_window(self.id, window.cwnd(), window.srtt());
+            }
+            None if self.ecn.is_ect() => flow.congestion.backoff.lock().on_send(),
+            None => {}
+        }
+        
+        (server_idx, packet)
+    }
+    
+    async fn send_packet(&self, f: usize) {
+        let (server_idx, packet) = self.next_packet(f);
+        let (packet_id, flow, priority) = (packet.id, packet.flow, packet.priority);
+        
+        match self.send_w_connection(server_idx, packet).await {
+            Ok(_) => {
+                self.metrics.packet_sent(&flow, priority);
+                debug!("Agent {} sent packet {:?} to server {}", self.id, packet_id, server_idx);
+            }
+            Err(e) => {
+                warn!("Agent {} failed to send packet: {}", self.id, e);
+                // A window flow leaves the packet in flight. Its RTO frees the slot and cuts the window once,
+                // with the timeout's backoff, instead of redialing a dead server for every free slot
+                self.metrics.send_failed();
+            }
+        }
+    }
+    
+    async fn send_w_connection(&self, server_idx: usize, packet: Packet) -> anyhow::Result<()> {
+        // Persistent connection first, otherwise dial a new one
+        let mut conn = self.connections[server_idx].lock().await;
+        
+        let sender = match &mut *conn {
+            Some(sender) => sender,
+            None => {
+                // Feedback comes back over the same connection, its stream says which flow it's for
+                let flows: Vec<Arc<Congestion>> = self.flows.iter().map(|f| f.congestion.clone()).collect();
+                let sender = self.server_addrs[server_idx]
+                    .connect(move |feedback| {
+                        if let Some(flow) = flows.get(feedback.stream as usize) {
+                            flow.on_feedback(feedback);
+                        }
+                    })
+                    .await?;
+                conn.insert(sender)
+            }
+        };
+        
+        if let Err(e) = sender.send(packet).await {
+            // Clean slate, the next packet dials again
+            *conn = None;
+            return Err(e);
+        }
+        Ok(())
+    }
+}
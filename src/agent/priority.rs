@@ -0,0 +1,123 @@
+// Which priority an agent's packets carry. A class of agents can all send at one priority (command and
+// control at critical, telemetry at low), or every packet rolls its own from a weighted mix.
+
+use crate::network::Priority;
+use anyhow::Result;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "kind")]
+pub enum PriorityMix {
+    Fixed { priority: Priority },
+    // Per packet, the weights don't have to add up to 1
+    Weighted {
+        #[serde(default)]
+        low: f64,
+        #[serde(default)]
+        normal: f64,
+        #[serde(default)]
+        high: f64,
+        #[serde(default)]
+        critical: f64,
+    },
+}
+
+impl Default for PriorityMix {
+    fn default() -> Self {
+        PriorityMix::Fixed { priority: Priority::Normal }
+    }
+}
+
+impl PriorityMix {
+    pub fn sampler(&self) -> Result<PrioritySampler> {
+        match *self {
+            PriorityMix::Fixed { priority } => Ok(PrioritySampler::Fixed(priority)),
+            PriorityMix::Weighted { low, normal, high, critical } => {
+                let weights = [low, normal, high, critical];
+                anyhow::ensure!(
+                    weights.iter().all(|w| *w >= 0.0 && w.is_finite()),
+                    "Priority weights must be >= 0, got {}",
+                    self
+                );
+                let total: f64 = weights.iter().sum();
+                anyhow::ensure!(total > 0.0, "Priority weights add up to 0");
+
+                let mut cumulative = [0.0; 4];
+                let mut sum = 0.0;
+                for (c, w) in cumulative.iter_mut().zip(weights) {
+                    sum += w;
+                    *c = sum / total;
+                }
+                Ok(PrioritySampler::Weighted(cumulative))
+            }
+        }
+    }
+}
+
+/// `critical` (every packet) or `low:0.8,normal:0.15,critical:0.05` (per packet)
+impl FromStr for PriorityMix {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.contains(':') {
+            return Ok(PriorityMix::Fixed { priority: s.parse()? });
+        }
+
+        let bad = || anyhow::anyhow!("Bad priority mix: {} (try critical or low:0.8,normal:0.15,critical:0.05)", s);
+        let mut weights = [0.0; 4];
+        for part in s.split(',') {
+            let (priority, weight) = part.split_once(':').ok_or_else(bad)?;
+            let priority: Priority = priority.parse().map_err(|_| bad())?;
+            weights[priority.index()] = weight.trim().parse().map_err(|_| bad())?;
+        }
+        let [low, normal, high, critical] = weights;
+        Ok(PriorityMix::Weighted { low, normal, high, critical })
+    }
+}
+
+impl fmt::Display for PriorityMix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            PriorityMix::Fixed { priority } => write!(f, "{}", priority),
+            PriorityMix::Weighted { low, normal, high, critical } => {
+                let parts: Vec<String> = Priority::ALL.iter()
+                    .zip([low, normal, high, critical])
+                    .filter(|(_, w)| *w > 0.0)
+                    .map(|(p, w)| format!("{}:{}", p, w))
+                    .collect();
+                write!(f, "{}", parts.join(","))
+            }
+        }
+    }
+}
+
+/// A validated PriorityMix
+#[derive(Debug, Clone, Copy)]
+pub enum PrioritySampler {
+    Fixed(Priority),
+    // Cumulative, low first, last entry is 1.0
+    Weighted([f64; 4]),
+}
+
+impl Default for PrioritySampler {
+    fn default() -> Self {
+        PrioritySampler::Fixed(Priority::Normal)
+    }
+}
+
+impl PrioritySampler {
+    pub fn sample(&self, rng: &mut impl Rng) -> Priority {
+        match self {
+            // No draw, same as fixed packet sizes
+            PrioritySampler::Fixed(priority) => *priority,
+            PrioritySampler::Weighted(cumulative) => {
+                let u = rng.r#gen::<f64>();
+                let idx = cumulative.partition_point(|&c| c < u).min(3);
+                Priority::ALL[idx]
+            }
+        }
+    }
+}
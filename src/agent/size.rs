@@ -0,0 +1,164 @@
+// Packet size distributions. Swarm telemetry is mostly tiny packets with the odd bulk transfer,
+// a fixed 1500 bytes doesn't capture that.
+
+use anyhow::{Context, Result};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+pub const DEFAULT_PACKET_SIZE: u32 = 1500;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "kind")]
+pub enum PacketSize {
+    Fixed { bytes: u32 },
+    // Inclusive on both ends
+    Uniform { min: u32, max: u32 },
+    // `large_fraction` of the packets are `large`, the rest `small`
+    Bimodal { small: u32, large: u32, large_fraction: f64 },
+    // One size per line, optionally followed by a weight: `64` or `1500,0.2`. # starts a comment
+    Empirical { path: String },
+}
+
+impl Default for PacketSize {
+    fn default() -> Self {
+        PacketSize::Fixed { bytes: DEFAULT_PACKET_SIZE }
+    }
+}
+
+impl PacketSize {
+    /// Checks the parameters and loads the file for empirical distributions
+    pub fn sampler(&self) -> Result<SizeSampler> {
+        match *self {
+            PacketSize::Fixed { bytes } => {
+                anyhow::ensure!(bytes > 0, "Packet size must be at least 1 byte");
+                Ok(SizeSampler::Fixed(bytes))
+            }
+            PacketSize::Uniform { min, max } => {
+                anyhow::ensure!(min > 0 && min <= max, "Uniform packet size needs 0 < min <= max, got {}-{}", min, max);
+                Ok(SizeSampler::Uniform(min, max))
+            }
+            PacketSize::Bimodal { small, large, large_fraction } => {
+                anyhow::ensure!(small > 0 && large > 0, "Bimodal packet sizes must be at least 1 byte");
+                anyhow::ensure!((0.0..=1.0).contains(&large_fraction), "Bimodal large_fraction must be in [0, 1]");
+                Ok(SizeSampler::Bimodal { small, large, large_fraction })
+            }
+            PacketSize::Empirical { ref path } => {
+                let content = std::fs::read_to_string(path)
+                    .with_context(|| format!("Reading packet size distribution {}", path))?;
+                Ok(SizeSampler::Empirical(Arc::new(parse_empirical(&content)?)))
+            }
+        }
+    }
+}
+
+fn parse_empirical(content: &str) -> Result<Vec<(u32, f64)>> {
+    let mut cumulative = Vec::new();
+    let mut total = 0.0;
+
+    for (n, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut fields = line.split([',', ' ', '\t']).filter(|f| !f.is_empty());
+        let size: u32 = fields.next()
+            .unwrap_or("")
+            .parse()
+            .with_context(|| format!("Line {}: bad packet size", n + 1))?;
+        let weight: f64 = match fields.next() {
+            Some(w) => w.parse().with_context(|| format!("Line {}: bad weight", n + 1))?,
+            None => 1.0,
+        };
+        anyhow::ensure!(size > 0, "Line {}: packet size must be at least 1 byte", n + 1);
+        anyhow::ensure!(weight >= 0.0, "Line {}: negative weight", n + 1);
+
+        total += weight;
+        cumulative.push((size, total));
+    }
+
+    anyhow::ensure!(total > 0.0, "Empirical packet size distribution is empty");
+    Ok(cumulative.into_iter().map(|(size, c)| (size, c / total)).collect())
+}
+
+/// `1500`, `fixed:1500`, `uniform:64-1500`, `bimodal:64,1500,0.1` or `empirical:sizes.txt`
+impl FromStr for PacketSize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, args) = s.split_once(':').unwrap_or(("fixed", s));
+        let bad = || anyhow::anyhow!("Bad packet size: {} (try 1500, uniform:64-1500, bimodal:64,1500,0.1 or empirical:<file>)", s);
+
+        match kind.to_lowercase().as_str() {
+            "fixed" => Ok(PacketSize::Fixed { bytes: args.trim().parse().map_err(|_| bad())? }),
+            "uniform" => {
+                let (min, max) = args.split_once('-').ok_or_else(bad)?;
+                Ok(PacketSize::Uniform {
+                    min: min.trim().parse().map_err(|_| bad())?,
+                    max: max.trim().parse().map_err(|_| bad())?,
+                })
+            }
+            "bimodal" => {
+                let parts: Vec<&str> = args.split(',').map(str::trim).collect();
+                let [small, large, fraction] = parts[..] else {
+                    return Err(bad());
+                };
+                Ok(PacketSize::Bimodal {
+                    small: small.parse().map_err(|_| bad())?,
+                    large: large.parse().map_err(|_| bad())?,
+                    large_fraction: fraction.parse().map_err(|_| bad())?,
+                })
+            }
+            "empirical" => Ok(PacketSize::Empirical { path: args.to_string() }),
+            _ => Err(bad()),
+        }
+    }
+}
+
+impl fmt::Display for PacketSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PacketSize::Fixed { bytes } => write!(f, "{}", bytes),
+            PacketSize::Uniform { min, max } => write!(f, "uniform:{}-{}", min, max),
+            PacketSize::Bimodal { small, large, large_fraction } => write!(f, "bimodal:{},{},{}", small, large, large_fraction),
+            PacketSize::Empirical { path } => write!(f, "empirical:{}", path),
+        }
+    }
+}
+
+/// A validated PacketSize, ready to draw from. Cheap to clone, empirical tables are shared
+#[derive(Debug, Clone)]
+pub enum SizeSampler {
+    Fixed(u32),
+    Uniform(u32, u32),
+    Bimodal { small: u32, large: u32, large_fraction: f64 },
+    // (size, cumulative probability), last entry is 1.0
+    Empirical(Arc<Vec<(u32, f64)>>),
+}
+
+impl Default for SizeSampler {
+    fn default() -> Self {
+        SizeSampler::Fixed(DEFAULT_PACKET_SIZE)
+    }
+}
+
+impl SizeSampler {
+    pub fn sample(&self, rng: &mut impl Rng) -> u32 {
+        match self {
+            // No draw, fixed sizes leave the RNG stream alone
+            SizeSampler::Fixed(bytes) => *bytes,
+            SizeSampler::Uniform(min, max) => rng.gen_range(*min..=*max),
+            SizeSampler::Bimodal { small, large, large_fraction } => {
+                if rng.r#gen::<f64>() < *large_fraction { *large } else { *small }
+            }
+            SizeSampler::Empirical(table) => {
+                let u = rng.r#gen::<f64>();
+                let idx = table.partition_point(|&(_, c)| c < u).min(table.len() - 1);
+                table[idx].0
+            }
+        }
+    }
+}
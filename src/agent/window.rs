@@ -0,0 +1,277 @@
+// Window-based sender for the closed-loop traffic mode. Roughly TCP without the bytes:
+// a congestion window counted in packets, slow start, one cut per RTT, RFC 6298 RTT estimate.
+// Everything is driven by the per-packet feedback servers send back. Prague is the scalable one for L4S,
+// it cuts in proportion to how many of its packets got marked instead of halving on every mark.
+
+use crate::network::{Feedback, Outcome, PacketId};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+const INITIAL_CWND: f64 = 10.0; // RFC 6928
+const MIN_CWND: f64 = 1.0;
+const MAX_CWND: f64 = 10_000.0; // Keeps slow start from running off when a strategy never drops
+
+// RFC 6298 wants a 1 second minimum, way too coarse for queues that drain in milliseconds. Magic numbers
+const INITIAL_RTO: Duration = Duration::from_millis(200);
+const MIN_RTO: Duration = Duration::from_millis(10);
+const MAX_RTO: Duration = Duration::from_secs(2);
+
+// RFC 9438 constants
+const CUBIC_C: f64 = 0.4;
+const CUBIC_BETA: f64 = 0.7;
+
+// DCTCP's gain for the marked fraction, RFC 8257
+const PRAGUE_G: f64 = 1.0 / 16.0;
+
+/// How the window grows and shrinks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CongestionControl {
+    /// Reno-like, +1 packet per RTT and halve on congestion
+    #[default]
+    Aimd,
+    /// Cubic growth around the last window that saw congestion, cut to 0.7
+    Cubic,
+    /// Scalable (DCTCP-like) for L4S: sends ECT(1), cuts by half the marked fraction once per RTT, halves on loss
+    Prague,
+}
+
+impl FromStr for CongestionControl {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "aimd" | "reno" => Ok(CongestionControl::Aimd),
+            "cubic" => Ok(CongestionControl::Cubic),
+            "prague" | "scalable" => Ok(CongestionControl::Prague),
+            _ => anyhow::bail!("Unknown congestion control: {} (use aimd, cubic or prague)", s),
+        }
+    }
+}
+
+impl fmt::Display for CongestionControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CongestionControl::Aimd => write!(f, "aimd"),
+            CongestionControl::Cubic => write!(f, "cubic"),
+            CongestionControl::Prague => write!(f, "prague"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Window {
+    cc: CongestionControl,
+    cwnd: f64,
+    ssthresh: f64,
+    in_flight: HashMap<PacketId, Instant>,
+    srtt: Option<Duration>,
+    rttvar: Duration,
+    rto: Duration,
+    // No further cuts until this passes, losses from the same window count once
+    recovery_until: Option<Instant>,
+    // Cubic state, window before the last cut and when the current epoch started
+    w_max: f64,
+    epoch_start: Option<Instant>,
+    // Prague state, moving average of the marked fraction and this round's counts
+    alpha: f64,
+    round_acked: u32,
+    round_marked: u32,
+    round_end: Option<Instant>,
+}
+
+impl Window {
+    pub fn new(cc: CongestionControl) -> Self {
+        Self {
+            cc,
+            cwnd: INITIAL_CWND,
+            ssthresh: f64::INFINITY,
+            in_flight: HashMap::new(),
+            srtt: None,
+            rttvar: Duration::ZERO,
+            rto: INITIAL_RTO,
+            recovery_until: None,
+            w_max: 0.0,
+            epoch_start: None,
+            alpha: 1.0,
+            round_acked: 0,
+            round_marked: 0,
+            round_end: None,
+        }
+    }
+
+    pub fn cwnd(&self) -> f64 {
+        self.cwnd
+    }
+
+    pub fn srtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    pub fn can_send(&self) -> bool {
+        (self.in_flight.len() as f64) < self.cwnd.floor()
+    }
+
+    pub fn on_send(&mut self, packet_id: PacketId, now: Instant) {
+        self.in_flight.insert(packet_id, now);
+    }
+
+    pub fn on_feedback(&mut self, feedback: &Feedback, now: Instant) {
+        // Already given up on it after a timeout
+        let Some(sent_at) = self.in_flight.remove(&feedback.packet_id) else {
+            return;
+        };
+
+        match feedback.outcome {
+            Outcome::Delivered => {
+                self.rtt_sample(now.duration_since(sent_at));
+                if self.cc == CongestionControl::Prague {
+                    self.count_marks(false, now);
+                }
+                self.grow(now);
+            }
+            Outcome::Marked if self.cc == CongestionControl::Prague => {
+                self.rtt_sample(now.duration_since(sent_at));
+                self.count_marks(true, now);
+                self.scalable_cut(now);
+            }
+            Outcome::Marked => {
+                self.rtt_sample(now.duration_since(sent_at));
+                self.congestion_event(now);
+            }
+            Outcome::Dropped => self.congestion_event(now),
+        }
+    }
+
+    /// Gives up on packets that have been out longer than the RTO. Returns true if any timed out
+    pub fn expire(&mut self, now: Instant) -> bool {
+        let rto = self.rto;
+        let before = self.in_flight.len();
+        self.in_flight.retain(|_, sent_at| now.duration_since(*sent_at) < rto);
+
+        if self.in_flight.len() == before {
+            return false;
+        }
+
+        // Timeout, back to slow start from one packet like Reno does
+        self.ssthresh = (self.cwnd / 2.0).max(2.0);
+        self.w_max = self.cwnd;
+        self.cwnd = MIN_CWND;
+        self.epoch_start = None;
+        self.rto = (self.rto * 2).min(MAX_RTO);
+        self.recovery_until = Some(now + self.srtt.unwrap_or(self.rto));
+        true
+    }
+
+    /// Time until the oldest packet in flight times out, None with nothing in flight
+    pub fn next_timeout(&self, now: Instant) -> Option<Duration> {
+        self.in_flight.values()
+            .min()
+            .map(|sent_at| (*sent_at + self.rto).saturating_duration_since(now))
+    }
+
+    fn rtt_sample(&mut self, rtt: Duration) {
+        match self.srtt {
+            None => {
+                self.srtt = Some(rtt);
+                self.rttvar = rtt / 2;
+            }
+            Some(srtt) => {
+                let delta = srtt.abs_diff(rtt);
+                self.rttvar = self.rttvar.mul_f64(0.75) + delta.mul_f64(0.25);
+                self.srtt = Some(srtt.mul_f64(0.875) + rtt.mul_f64(0.125));
+            }
+        }
+
+        let srtt = self.srtt.unwrap_or(rtt);
+        self.rto = (srtt + self.rttvar * 4).clamp(MIN_RTO, MAX_RTO);
+    }
+
+    fn grow(&mut self, now: Instant) {
+        if self.cwnd < self.ssthresh {
+            self.cwnd += 1.0;
+        } else {
+            match self.cc {
+                CongestionControl::Aimd | CongestionControl::Prague => self.cwnd += 1.0 / self.cwnd,
+                CongestionControl::Cubic => self.cubic_grow(now),
+            }
+        }
+        self.cwnd = self.cwnd.min(MAX_CWND);
+    }
+
+    fn cubic_grow(&mut self, now: Instant) {
+        let epoch_start = *self.epoch_start.get_or_insert(now);
+        if self.w_max < self.cwnd {
+            // Never saw congestion at this size, probe from here
+            self.w_max = self.cwnd;
+        }
+
+        let t = now.duration_since(epoch_start).as_secs_f64();
+        let k = (self.w_max * (1.0 - CUBIC_BETA) / CUBIC_C).cbrt();
+        let target = CUBIC_C * (t - k).powi(3) + self.w_max;
+
+        // Reno-friendly region so cubic is never slower than AIMD on short RTTs
+        let rtt = self.srtt.map_or(1.0, |s| s.as_secs_f64().max(1e-6));
+        let reno = self.w_max * CUBIC_BETA + 3.0 * (1.0 - CUBIC_BETA) / (1.0 + CUBIC_BETA) * t / rtt;
+
+        let target = target.max(reno);
+        if target > self.cwnd {
+            self.cwnd += (target - self.cwnd) / self.cwnd;
+        } else {
+            self.cwnd += 0.01 / self.cwnd;
+        }
+    }
+
+    fn congestion_event(&mut self, now: Instant) {
+        if self.recovery_until.is_some_and(|t| now < t) {
+            return;
+        }
+
+        // Loss is loss, Prague halves like Reno (RFC 9331)
+        match self.cc {
+            CongestionControl::Aimd | CongestionControl::Prague => {
+                self.cwnd = (self.cwnd / 2.0).max(MIN_CWND);
+            }
+            CongestionControl::Cubic => {
+                self.w_max = self.cwnd;
+                self.cwnd = (self.cwnd * CUBIC_BETA).max(MIN_CWND);
+                self.epoch_start = Some(now);
+            }
+        }
+        self.ssthresh = self.cwnd.max(2.0);
+        self.recovery_until = Some(now + self.srtt.unwrap_or(self.rto));
+    }
+
+    // Marked fraction over the last RTT folds into alpha at the end of every round, like DCTCP
+    fn count_marks(&mut self, marked: bool, now: Instant) {
+        self.round_acked += 1;
+        self.round_marked += marked as u32;
+        if self.round_end.is_some_and(|end| now < end) {
+            return;
+        }
+
+        let fraction = self.round_marked as f64 / self.round_acked as f64;
+        self.alpha = (1.0 - PRAGUE_G) * self.alpha + PRAGUE_G * fraction;
+        self.round_acked = 0;
+        self.round_marked = 0;
+        self.round_end = Some(now + self.srtt.unwrap_or(self.rto));
+    }
+
+    // A mark costs alpha/2 of the window, at most once per RTT. Few marks, small cut
+    fn scalable_cut(&mut self, now: Instant) {
+        if self.recovery_until.is_some_and(|t| now < t) {
+            return;
+        }
+
+        self.cwnd = (self.cwnd * (1.0 - self.alpha / 2.0)).max(MIN_CWND);
+        self.ssthresh = self.cwnd.max(2.0);
+        self.recovery_until = Some(now + self.srtt.unwrap_or(self.rto));
+    }
+}
@@ -14,25 +14,19 @@
 // The above copyright notice and this permission notice shall be included in all copies or substantial portions of the Software.
 // THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.                                                                          
 
-use flocknet::prelude::*;
-use flocknet::simulation::config::SimConfig;
-use flocknet::agent::TrafficPattern;
-use flocknet::strategies::StrategyRegistry;
+use flocknet::simulation::config::{Backend, ListenConfig, OutputConfig, PortRange, SimConfig};
+use flocknet::agent::{CongestionControl, PacketSize, PriorityMix, TrafficPattern};
+use flocknet::network::{Jitter, Loss, NetworkConfig, Priority, Transport};
+use flocknet::strategies::{ParamOverride, StrategyRegistry};
 use flocknet::metrics::analyzer;
-use flocknet::simulation::Simulation;
+use flocknet::simulation::{Scenario, Simulation, SweepSpec, batch, seed, sweep};
 
 use clap::{Parser, Subcommand};
 use anyhow::Result;
+use std::collections::BTreeMap;
 use std::time::{Duration, Instant};
 use tracing::{info, Level};
 
-use tracing_subscriber;
-
-// unused rn
-const MAX_REASONABLE_SOJOURN_MS: f64 = 30_000.0; 
-const BURST_PACKET_DELAY_MICROS: u64 = 100;
-const DEBUG_PACKET_COUNT: u64 = 10;
-
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
@@ -45,6 +39,7 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
+    /// Run a single simulation with one strategy
     Run {
         #[arg(short, long, default_value = "drop-tail")]
         strategy: String,
@@ -62,8 +57,37 @@ enum Commands {
         peak_rate: f64,
         #[arg(long, default_value_t = 10.0)]
         peak_duration: f64,
+        #[arg(long, default_value = "1500")]
+        packet_size: PacketSize,
+        /// Priority of every packet (critical) or a per-packet mix (low:0.8,normal:0.15,critical:0.05)
+        #[arg(long, default_value = "normal")]
+        priority: PriorityMix,
+        #[arg(short, long, default_value = "realtime")]
+        backend: Backend,
+        #[arg(long)]
+        seed: Option<u64>,
+        #[arg(long)]
+        ecn: bool,
+        /// Strategy parameter, e.g. red.max_p=0.2. Repeatable
+        #[arg(long = "param")]
+        params: Vec<ParamOverride>,
+        /// How realtime packets travel: tcp, udp (one datagram per packet) or channel (in-process)
+        #[arg(long, default_value = "tcp")]
+        transport: Transport,
+        /// Number packets so servers can count loss and reordering on the way
+        #[arg(long)]
+        sequence_numbers: bool,
+        /// Interface the realtime servers listen on
+        #[arg(long, default_value = "127.0.0.1")]
+        bind: String,
+        /// Ports for the realtime servers, 6000-6100 or 0 to let the OS pick
+        #[arg(long, default_value = "0")]
+        ports: PortRange,
+        #[command(flatten)]
+        link: LinkArgs,
     },
     
+    /// Run every strategy a few times on the same setup and compare the averages
     Compare {
         #[arg(short, long, default_value = "drop-tail,red,adaptive-red,blue,codel,pie,fq-codel")] // TODO: Make this read a global table
         strategies: String,
@@ -73,10 +97,51 @@ enum Commands {
         servers: u32,
         #[arg(short, long, default_value_t = 256)]
         duration: u64,
-        #[arg(short, long, default_value_t = 3)]
+        #[arg(short, long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
         repetitions: u32,
         #[arg(long)]
         latex: bool,
+        #[arg(long, default_value = "1500")]
+        packet_size: PacketSize,
+        /// Priority of every packet (critical) or a per-packet mix (low:0.8,normal:0.15,critical:0.05)
+        #[arg(long, default_value = "normal")]
+        priority: PriorityMix,
+        #[arg(short, long, default_value = "realtime")]
+        backend: Backend,
+        #[arg(long)]
+        seed: Option<u64>,
+        #[arg(long)]
+        ecn: bool,
+        /// Strategy parameter, e.g. red.max_p=0.2, only applies to that strategy. Repeatable
+        #[arg(long = "param")]
+        params: Vec<ParamOverride>,
+        /// Runs at the same time, defaults to the number of cores
+        #[arg(short, long, value_parser = clap::value_parser!(u64).range(1..))]
+        jobs: Option<u64>,
+        /// How realtime packets travel: tcp, udp (one datagram per packet) or channel (in-process)
+        #[arg(long, default_value = "tcp")]
+        transport: Transport,
+        /// Number packets so servers can count loss and reordering on the way
+        #[arg(long)]
+        sequence_numbers: bool,
+        /// Interface the realtime servers listen on
+        #[arg(long, default_value = "127.0.0.1")]
+        bind: String,
+        /// Ports for the realtime servers, 6000-6100 or 0 to let the OS pick
+        #[arg(long, default_value = "0")]
+        ports: PortRange,
+        #[command(flatten)]
+        link: LinkArgs,
+    },
+    
+    /// Run a scenario file (TOML or JSON), a [compare] section makes it a comparison
+    Scenario {
+        file: String,
+    },
+    
+    /// Run a parameter sweep file, picks up where an interrupted one stopped
+    Sweep {
+        file: String,
     },
     
     Export {
@@ -95,6 +160,39 @@ enum Commands {
     List,
 }
 
+// netem-like emulation of every agent→server link, same flags for run and compare.
+// Not a doc comment, clap would make it the about text of both subcommands
+#[derive(clap::Args)]
+struct LinkArgs {
+    /// One-way link delay in ms
+    #[arg(long, default_value_t = 0.0)]
+    latency: f64,
+    /// Delay jitter in ms: 2 (uniform), normal:2 or pareto:2
+    #[arg(long, default_value = "0")]
+    jitter: Jitter,
+    /// Random link loss: 0.01 (Bernoulli) or ge:p,r[,loss_bad[,loss_good]] (Gilbert-Elliott)
+    #[arg(long, default_value = "0")]
+    loss: Loss,
+    /// Chance a packet skips the delay and overtakes the ones underway, needs --latency
+    #[arg(long, default_value_t = 0.0)]
+    reorder: f64,
+    /// Chance a packet arrives twice
+    #[arg(long, default_value_t = 0.0)]
+    duplicate: f64,
+}
+
+impl From<LinkArgs> for NetworkConfig {
+    fn from(args: LinkArgs) -> Self {
+        NetworkConfig {
+            latency_ms: args.latency,
+            jitter: args.jitter,
+            loss: args.loss,
+            reorder: args.reorder,
+            duplicate: args.duplicate,
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let program_start = Instant::now(); // Global timer for end time.
@@ -117,7 +215,26 @@ async fn main() -> Result<()> {
             base_rate,
             peak_rate,
             peak_duration,
+            packet_size,
+            priority,
+            backend,
+            seed,
+            ecn,
+            params,
+            transport,
+            sequence_numbers,
+            bind,
+            ports,
+            link,
         } => {
+            let mut params = group_params(&params)?;
+            let strategy_params = StrategyRegistry::global().canonical(&strategy)
+                .and_then(|name| params.remove(name))
+                .unwrap_or_default();
+            if let Some(other) = params.keys().next() {
+                anyhow::bail!("--param for {} but the strategy is {}", other, strategy);
+            }
+            
             run_single_simulation(
                 strategy,
                 agents,
@@ -127,6 +244,16 @@ async fn main() -> Result<()> {
                 base_rate,
                 peak_rate,
                 peak_duration,
+                packet_size,
+                priority,
+                backend,
+                seed.unwrap_or_else(seed::random_seed),
+                ecn,
+                strategy_params,
+                transport,
+                sequence_numbers,
+                ListenConfig { host: bind, ports },
+                link.into(),
             ).await?;
         }
         
@@ -137,16 +264,86 @@ async fn main() -> Result<()> {
             duration,
             repetitions,
             latex,
+            packet_size,
+            priority,
+            backend,
+            seed,
+            ecn,
+            params,
+            jobs,
+            transport,
+            sequence_numbers,
+            bind,
+            ports,
+            link,
         } => {
-            compare_strategies(
-                strategies,
-                agents,
-                servers,
-                duration,
-                repetitions,
-                latex,
-                program_start,
-            ).await?;
+            let base = SimConfig {
+                name: String::new(),
+                strategy_name: String::new(),
+                strategy_params: BTreeMap::new(),
+                num_agents: agents,
+                num_servers: servers,
+                duration: Duration::from_secs(duration),
+                buffer_size: 1024,
+                bandwidth_bps: 100_000_000,
+                traffic_pattern: TrafficPattern::PeakTraffic {
+                    base_rate: 50.0,
+                    peak_rate: 500.0,
+                    peak_duration_s: 10.0,
+                },
+                groups: Vec::new(),
+                flows: Vec::new(),
+                packet_size,
+                priority,
+                backend,
+                seed: seed.unwrap_or_else(seed::random_seed),
+                ecn,
+                output: OutputConfig { latex, ..OutputConfig::default() },
+                transport,
+                sequence_numbers,
+                listen: ListenConfig { host: bind, ports },
+                network: link.into(),
+                topology: None,
+            };
+            let strategies: Vec<String> = strategies.split(',').map(|s| s.trim().to_string()).collect();
+            let params = group_params(&params)?;
+            for name in params.keys() {
+                anyhow::ensure!(
+                    strategies.iter().any(|s| StrategyRegistry::global().canonical(s) == Some(name.as_str())),
+                    "--param for {} which isn't in --strategies",
+                    name
+                );
+            }
+            
+            let jobs = jobs.map(|j| j as usize).unwrap_or_else(batch::default_jobs);
+            compare_strategies(base, strategies, &params, repetitions, jobs, program_start).await?;
+        }
+        
+        Commands::Scenario { file } => {
+            let scenario = Scenario::load(&file)?;
+            info!("FlockNet: Scenario {}", file);
+            
+            match scenario.compare {
+                Some(compare) => {
+                    compare_strategies(
+                        scenario.simulation,
+                        compare.strategies,
+                        &compare.params,
+                        compare.repetitions,
+                        compare.jobs.unwrap_or_else(batch::default_jobs),
+                        program_start,
+                    ).await?;
+                }
+                None => {
+                    Simulation::new(scenario.simulation).run().await?;
+                }
+            }
+        }
+        
+        Commands::Sweep { file } => {
+            let spec = SweepSpec::load(&file)?;
+            info!("FlockNet: Sweep {}", file);
+            sweep::run(&spec).await?;
         }
         
         Commands::Export { input, output, format } => {
@@ -160,10 +357,20 @@ async fn main() -> Result<()> {
         Commands::List => {
             println!("\nAvailable Buffer Strategies");
             
-            for strategy in StrategyRegistry::global().list() {
-                println!("  - {}", strategy);
+            let registry = StrategyRegistry::global();
+            for strategy in registry.list() {
+                let aliases = registry.aliases(&strategy);
+                if aliases.is_empty() {
+                    println!("  - {}", strategy);
+                } else {
+                    println!("  - {} (also: {})", strategy, aliases.join(", "));
+                }
+                for param in registry.params(&strategy).unwrap_or_default() {
+                    println!("      {}", param);
+                }
             }
             
+            println!("\nSet parameters with --param <strategy>.<name>=<value>, e.g. --param red.max_p=0.2");            
             println!("\nUsage: cargo run -- run --strategy <name>");
             println!("Example: cargo run -- run --strategy fq-codel\n");
         }
@@ -175,6 +382,7 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn run_single_simulation(
     strategy_name: String,
     agents: u32,
@@ -184,6 +392,16 @@ async fn run_single_simulation(
     base_rate: f64,
     peak_rate: f64,
     peak_duration: f64,
+    packet_size: PacketSize,
+    priority: PriorityMix,
+    backend: Backend,
+    seed: u64,
+    ecn: bool,
+    strategy_params: BTreeMap<String, f64>,
+    transport: Transport,
+    sequence_numbers: bool,
+    listen: ListenConfig,
+    network: NetworkConfig,
 ) -> Result<()> {
     let traffic_pattern = parse_traffic_pattern(
         &traffic,
@@ -195,12 +413,26 @@ async fn run_single_simulation(
     let config = SimConfig {
         name: format!("{}_{}", strategy_name, traffic),
         strategy_name,
+        strategy_params,
         num_agents: agents,
         num_servers: servers,
         duration: Duration::from_secs(duration),
         buffer_size: 1024,
         bandwidth_bps: 100_000_000,
         traffic_pattern,
+        groups: Vec::new(),
+        flows: Vec::new(),
+        packet_size,
+        priority,
+        backend,
+        seed,
+        ecn,
+        output: OutputConfig::default(),
+        transport,
+        sequence_numbers,
+        listen,
+        network,
+        topology: None,
     };
     
     info!("FlockNet: Single Run");
@@ -211,82 +443,77 @@ async fn run_single_simulation(
     Ok(())
 }
 
+/// Runs `base` once per strategy and repetition, only name, strategy and seed change between runs.
+/// Up to `jobs` runs go at the same time
 async fn compare_strategies(
-    strategies_str: String,
-    agents: u32,
-    servers: u32,
-    duration: u64,
+    base: SimConfig,
+    strategy_names: Vec<String>,
+    strategy_params: &BTreeMap<String, BTreeMap<String, f64>>,
     repetitions: u32,
-    export_latex: bool,
+    jobs: usize,
     global_start: Instant,
 ) -> Result<()> {
-    let strategy_names: Vec<&str> = strategies_str.split(',').map(|s| s.trim()).collect();
+    let base_seed = base.seed;
     
     info!("FlockNet: Comparison");
     info!("");
     info!("Strategies: {}", strategy_names.join(", "));
     info!("Repetitions: {}", repetitions);
-    info!("Duration per test: {}s", duration);
+    info!("Duration per test: {}s", base.duration.as_secs());
+    info!("Backend: {}", base.backend);
+    info!("Base seed: {} (repetition r uses base + r - 1)", base_seed);
+    info!("Parallel runs: {}", jobs);
     info!("");
     
-    let mut all_reports = Vec::new();
-    let total_tests = strategy_names.len() * repetitions as usize;
-    let mut completed = 0;
-    
-    for strategy_name in strategy_names {
-        info!("Testing: {}", strategy_name);
-        
-        let mut strategy_reports = Vec::new();
-        
+    let mut configs = Vec::new();
+    for strategy_name in &strategy_names {
         for rep in 1..=repetitions {
-            completed += 1;
-            let elapsed = global_start.elapsed();
-            info!("  [{}] Run {}/{} - Elapsed: {:.1}s", 
-                  format_time(elapsed), rep, repetitions, elapsed.as_secs_f64());
-            
-            let config = SimConfig {
+            configs.push(SimConfig {
                 name: format!("{}_{}", strategy_name, rep),
-                strategy_name: strategy_name.to_string(),
-                num_agents: agents,
-                num_servers: servers,
-                duration: Duration::from_secs(duration),
-                buffer_size: 1024,
-                bandwidth_bps: 100_000_000,
-                traffic_pattern: TrafficPattern::PeakTraffic {
-                    base_rate: 50.0,
-                    peak_rate: 500.0,
-                    peak_duration_s: 10.0,
-                },
-            };
-            
-            let mut sim = Simulation::new(config);
-            sim.run().await?;
-            
-            let snapshots = sim.metrics.get_snapshots();
-            let report = analyzer::analyze(&snapshots, strategy_name);
-            strategy_reports.push(report);
+                strategy_name: strategy_name.clone(),
+                strategy_params: StrategyRegistry::global().canonical(strategy_name)
+                    .and_then(|name| strategy_params.get(name))
+                    .cloned()
+                    .unwrap_or_default(),
+                seed: base_seed.wrapping_add(rep as u64 - 1),
+                ..base.clone()
+            });
         }
-        
-        let avg_report = average_reports(&strategy_reports);
-        all_reports.push(avg_report);
-        
-        info!("");
+    }
+    
+    let results = batch::run_all(configs, jobs).await?;
+    info!("All {} runs done in {}", results.len(), format_time(global_start.elapsed()));
+    info!("");
+    
+    // Strategy-major, so every `repetitions` results belong to the same strategy
+    let mut all_reports = Vec::new();
+    for (strategy_name, runs) in strategy_names.iter().zip(results.chunks(repetitions as usize)) {
+        let strategy_reports: Vec<_> = runs.iter()
+            .zip(1..)
+            .map(|(snapshots, rep)| {
+                let mut report = analyzer::analyze(snapshots, strategy_name);
+                report.seed = Some(base_seed.wrapping_add(rep - 1));
+                report
+            })
+            .collect();
+        all_reports.push(average_reports(&strategy_reports));
     }
     
     comparison_table(&all_reports);
     
     let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
-    let comparison_path = format!("results/comparison_{}.json", timestamp);
+    let dir = &base.output.dir;
+    let comparison_path = format!("{}/comparison_{}.json", dir, timestamp);
     std::fs::write(
         &comparison_path,
         serde_json::to_string_pretty(&all_reports)?
     )?;
     info!("Comparison saved to: {}", comparison_path);
     
-    if export_latex {
-        let latex_table_path = format!("results/comparison_{}_table.tex", timestamp);
-        let latex_detailed_path = format!("results/comparison_{}_detailed.tex", timestamp);
-        let latex_figure_path = format!("results/comparison_{}_figure.tex", timestamp);
+    if base.output.latex {
+        let latex_table_path = format!("{}/comparison_{}_table.tex", dir, timestamp);
+        let latex_detailed_path = format!("{}/comparison_{}_detailed.tex", dir, timestamp);
+        let latex_figure_path = format!("{}/comparison_{}_figure.tex", dir, timestamp);
         
         analyzer::export_latex_table(&all_reports, &latex_table_path)?;
         info!("LaTeX table exported to: {}", latex_table_path);
@@ -312,6 +539,17 @@ async fn compare_strategies(
     Ok(())
 }
 
+/// `--param` values per strategy, keyed by the registered name so aliases land in the same place
+fn group_params(overrides: &[ParamOverride]) -> Result<BTreeMap<String, BTreeMap<String, f64>>> {
+    let mut grouped: BTreeMap<String, BTreeMap<String, f64>> = BTreeMap::new();
+    for o in overrides {
+        let name = StrategyRegistry::global().canonical(&o.strategy)
+            .ok_or_else(|| anyhow::anyhow!("Unknown strategy in --param: {}", o.strategy))?;
+        grouped.entry(name.to_string()).or_default().insert(o.key.clone(), o.value);
+    }
+    Ok(grouped)
+}
+
 fn format_time(duration: Duration) -> String {
     let secs = duration.as_secs();
     let hours = secs / 3600;
@@ -460,6 +698,9 @@ fn parse_traffic_pattern(
             peak_rate,
             peak_duration_s: peak_duration,
         }),
+        "aimd" | "reno" => Ok(TrafficPattern::Window { congestion_control: CongestionControl::Aimd }),
+        "cubic" => Ok(TrafficPattern::Window { congestion_control: CongestionControl::Cubic }),
+        "prague" | "scalable" => Ok(TrafficPattern::Window { congestion_control: CongestionControl::Prague }),
         _ => anyhow::bail!("Unknown traffic pattern: {}", name),
     }
 }
@@ -470,36 +711,156 @@ fn average_reports(reports: &[analyzer::AnalysisReport]) -> analyzer::AnalysisRe
     analyzer::AnalysisReport {
         strategy_name: reports[0].strategy_name.clone(),
         avg_throughput_mbps: reports.iter().map(|r| r.avg_throughput_mbps).sum::<f64>() / n,
+        peak_throughput_mbps: reports.iter().map(|r| r.peak_throughput_mbps).sum::<f64>() / n,
         avg_latency_ms: reports.iter().map(|r| r.avg_latency_ms).sum::<f64>() / n,
         packet_loss_rate: reports.iter().map(|r| r.packet_loss_rate).sum::<f64>() / n,
         peak_queue_length: reports.iter().map(|r| r.peak_queue_length).max().unwrap_or(0),
         avg_queue_length: reports.iter().map(|r| r.avg_queue_length).sum::<f64>() / n,
+        avg_queue_bytes: reports.iter().map(|r| r.avg_queue_bytes).sum::<f64>() / n,
         jitter_ms: reports.iter().map(|r| r.jitter_ms).sum::<f64>() / n,
+        latency_p50_ms: reports.iter().map(|r| r.latency_p50_ms).sum::<f64>() / n,
+        latency_p90_ms: reports.iter().map(|r| r.latency_p90_ms).sum::<f64>() / n,
+        latency_p99_ms: reports.iter().map(|r| r.latency_p99_ms).sum::<f64>() / n,
+        latency_p999_ms: reports.iter().map(|r| r.latency_p999_ms).sum::<f64>() / n,
+        latency_max_ms: reports.iter().map(|r| r.latency_max_ms).fold(0.0, f64::max),
+        avg_rtt_ms: reports.iter().map(|r| r.avg_rtt_ms).sum::<f64>() / n,
+        // Counters are summed over the repetitions, an integer mean rounds a rare event (one mark in three runs) down to 0
+        frames_malformed: reports.iter().map(|r| r.frames_malformed).sum(),
+        send_errors: reports.iter().map(|r| r.send_errors).sum(),
+        packets_lost_in_transit: reports.iter().map(|r| r.packets_lost_in_transit).sum(),
+        packets_reordered: reports.iter().map(|r| r.packets_reordered).sum(),
+        link_lost: reports.iter().map(|r| r.link_lost).sum(),
+        link_duplicated: reports.iter().map(|r| r.link_duplicated).sum(),
+        link_reordered: reports.iter().map(|r| r.link_reordered).sum(),
+        packets_marked: reports.iter().map(|r| r.packets_marked).sum(),
+        mark_rate: reports.iter().map(|r| r.mark_rate).sum::<f64>() / n,
+        flows: reports[0].flows, // same config every repetition, so the same flows
+        flow_fairness: reports.iter().map(|r| r.flow_fairness).sum::<f64>() / n,
+        seed: reports[0].seed, // first repetition, the others follow from it
+        servers: average_servers(reports),
+        agents: average_agents(reports),
+        priorities: average_priorities(reports),
+        queues: average_queues(reports),
     }
 }
 
+// Repetitions run the same topology, so ids line up across reports
+fn average_servers(reports: &[analyzer::AnalysisReport]) -> Vec<analyzer::ServerReport> {
+    let mut by_id: BTreeMap<u32, Vec<&analyzer::ServerReport>> = BTreeMap::new();
+    for server in reports.iter().flat_map(|r| &r.servers) {
+        by_id.entry(server.id).or_default().push(server);
+    }
+
+    by_id.into_iter()
+        .map(|(id, runs)| {
+            let n = runs.len() as f64;
+            analyzer::ServerReport {
+                id,
+                avg_throughput_mbps: runs.iter().map(|s| s.avg_throughput_mbps).sum::<f64>() / n,
+                avg_latency_ms: runs.iter().map(|s| s.avg_latency_ms).sum::<f64>() / n,
+                packet_loss_rate: runs.iter().map(|s| s.packet_loss_rate).sum::<f64>() / n,
+                avg_queue_length: runs.iter().map(|s| s.avg_queue_length).sum::<f64>() / n,
+                peak_queue_length: runs.iter().map(|s| s.peak_queue_length).max().unwrap_or(0),
+                packets_marked: runs.iter().map(|s| s.packets_marked).sum(),
+            }
+        })
+        .collect()
+}
+
+// Same strategy in every repetition, so the same queues
+fn average_queues(reports: &[analyzer::AnalysisReport]) -> Vec<analyzer::QueueReport> {
+    let mut by_name: Vec<(String, Vec<&analyzer::QueueReport>)> = Vec::new();
+    for queue in reports.iter().flat_map(|r| &r.queues) {
+        match by_name.iter_mut().find(|(name, _)| *name == queue.name) {
+            Some((_, runs)) => runs.push(queue),
+            None => by_name.push((queue.name.clone(), vec![queue])),
+        }
+    }
+
+    by_name.into_iter()
+        .map(|(name, runs)| {
+            let n = runs.len() as f64;
+            analyzer::QueueReport {
+                name,
+                packets_dequeued: runs.iter().map(|q| q.packets_dequeued).sum(),
+                packets_marked: runs.iter().map(|q| q.packets_marked).sum(),
+                packets_dropped: runs.iter().map(|q| q.packets_dropped).sum(),
+                avg_sojourn_ms: runs.iter().map(|q| q.avg_sojourn_ms).sum::<f64>() / n,
+                mark_rate: runs.iter().map(|q| q.mark_rate).sum::<f64>() / n,
+            }
+        })
+        .collect()
+}
+
+fn average_agents(reports: &[analyzer::AnalysisReport]) -> Vec<analyzer::AgentReport> {
+    let mut by_id: BTreeMap<u32, Vec<&analyzer::AgentReport>> = BTreeMap::new();
+    for agent in reports.iter().flat_map(|r| &r.agents) {
+        by_id.entry(agent.id).or_default().push(agent);
+    }
+
+    by_id.into_iter()
+        .map(|(id, runs)| {
+            let n = runs.len() as f64;
+            analyzer::AgentReport {
+                id,
+                packets_sent: runs.iter().map(|a| a.packets_sent).sum(),
+                avg_throughput_mbps: runs.iter().map(|a| a.avg_throughput_mbps).sum::<f64>() / n,
+                avg_latency_ms: runs.iter().map(|a| a.avg_latency_ms).sum::<f64>() / n,
+                packet_loss_rate: runs.iter().map(|a| a.packet_loss_rate).sum::<f64>() / n,
+                avg_rtt_ms: runs.iter().map(|a| a.avg_rtt_ms).sum::<f64>() / n,
+                jitter_ms: runs.iter().map(|a| a.jitter_ms).sum::<f64>() / n,
+                packets_marked: runs.iter().map(|a| a.packets_marked).sum(),
+            }
+        })
+        .collect()
+}
+
+fn average_priorities(reports: &[analyzer::AnalysisReport]) -> Vec<analyzer::PriorityReport> {
+    let mut by_class: BTreeMap<Priority, Vec<&analyzer::PriorityReport>> = BTreeMap::new();
+    for class in reports.iter().flat_map(|r| &r.priorities) {
+        by_class.entry(class.priority).or_default().push(class);
+    }
+
+    by_class.into_iter()
+        .map(|(priority, runs)| {
+            let n = runs.len() as f64;
+            analyzer::PriorityReport {
+                priority,
+                packets_sent: runs.iter().map(|p| p.packets_sent).sum(),
+                packets_received: runs.iter().map(|p| p.packets_received).sum(),
+                avg_throughput_mbps: runs.iter().map(|p| p.avg_throughput_mbps).sum::<f64>() / n,
+                avg_latency_ms: runs.iter().map(|p| p.avg_latency_ms).sum::<f64>() / n,
+                latency_p99_ms: runs.iter().map(|p| p.latency_p99_ms).sum::<f64>() / n,
+                packet_loss_rate: runs.iter().map(|p| p.packet_loss_rate).sum::<f64>() / n,
+            }
+        })
+        .collect()
+}
+
 // TODO: Make this less prone to break
 fn comparison_table(reports: &[analyzer::AnalysisReport]) {
-    println!("\n╔═══════════════════════════════════════════════════════════════════════════════╗"); 
-    println!("║                          STRATEGY COMPARISON                                  ║");
-    println!("╠═══════════════╦═══════════╦═══════════╦════════════╦════════════╦═════════════╣");
-    println!("║ Strategy      ║ Throughput║ Latency   ║ Loss Rate  ║ Avg Queue  ║ Jitter      ║");
-    println!("║               ║ (mbps)    ║ (ms)      ║ (%)        ║ (packets)  ║ (ms)        ║");
-    println!("╠═══════════════╬═══════════╬═══════════╬════════════╬════════════╬═════════════╣");
+    println!("\n╔═════════════════════════════════════════════════════════════════════════════════════════════════════════╗"); 
+    println!("║                                      STRATEGY COMPARISON                                                ║");
+    println!("╠═══════════════╦═══════════╦═══════════╦════════════╦════════════╦════════════╦════════════╦═════════════╣");
+    println!("║ Strategy      ║ Throughput║ Latency   ║ P99        ║ Loss Rate  ║ Mark Rate  ║ Avg Queue  ║ Jitter      ║");
+    println!("║               ║ (mbps)    ║ (ms)      ║ (ms)       ║ (%)        ║ (%)        ║ (packets)  ║ (ms)        ║");
+    println!("╠═══════════════╬═══════════╬═══════════╬════════════╬════════════╬════════════╬════════════╬═════════════╣");
     
     for report in reports {
         println!(
-            "║ {:<13} ║ {:>9.2} ║ {:>9.2} ║ {:>9.2}% ║ {:>10.1} ║ {:>11.2} ║",
+            "║ {:<13} ║ {:>9.2} ║ {:>9.2} ║ {:>10.2} ║ {:>9.2}% ║ {:>9.2}% ║ {:>10.1} ║ {:>11.2} ║",
             report.strategy_name,
             report.avg_throughput_mbps,
             report.avg_latency_ms,
+            report.latency_p99_ms,
             report.packet_loss_rate * 100.0,
+            report.mark_rate * 100.0,
             report.avg_queue_length,
             report.jitter_ms,
         );
     }
     
-    println!("╚═══════════════╩═══════════╩═══════════╩════════════╩════════════╩═════════════╝\n");
+    println!("╚═══════════════╩═══════════╩═══════════╩════════════╩════════════╩════════════╩════════════╩═════════════╝\n");
     
     if let Some(best_throughput) = reports.iter().max_by(|a, b| {
         a.avg_throughput_mbps.partial_cmp(&b.avg_throughput_mbps).unwrap()
@@ -522,5 +883,30 @@ fn comparison_table(reports: &[analyzer::AnalysisReport]) {
             best_loss.strategy_name, best_loss.packet_loss_rate * 100.0);
     }
     
+    if let Some(fairest) = reports.iter().filter(|r| r.flows > 1).max_by(|a, b| {
+        a.flow_fairness.total_cmp(&b.flow_fairness)
+    }) {
+        println!("Fairest: {} (Jain {:.3} over {} flows)", 
+            fairest.strategy_name, fairest.flow_fairness, fairest.flows);
+    }
+    
+    // Only when the traffic actually had more than one class
+    if reports.iter().any(|r| r.priorities.len() > 1) {
+        println!("\nPer priority:");
+        for report in reports {
+            for class in &report.priorities {
+                println!(
+                    "  {:<15} {:<9} {:>9.2} ms  p99 {:>9.2} ms  {:>6.2}% loss  {:>8.2} Mbps",
+                    report.strategy_name,
+                    class.priority,
+                    class.avg_latency_ms,
+                    class.latency_p99_ms,
+                    class.packet_loss_rate * 100.0,
+                    class.avg_throughput_mbps
+                );
+            }
+        }
+    }
+    
     println!();
 }
\ No newline at end of file
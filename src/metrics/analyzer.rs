@@ -1,4 +1,5 @@
-use super::MetricsSnapshot;
+use super::{MetricsSnapshot, QueueStats};
+use crate::network::Priority;
 use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::Write;
@@ -9,11 +10,118 @@ use anyhow::Result;
 pub struct AnalysisReport {
     pub strategy_name: String,
     pub avg_throughput_mbps: f64,
+    // Best single snapshot interval, shows the peak-traffic transient the average smooths out
+    #[serde(default)]
+    pub peak_throughput_mbps: f64,
     pub avg_latency_ms: f64,
     pub packet_loss_rate: f64,
     pub peak_queue_length: usize,
     pub avg_queue_length: f64,
+    #[serde(default)]
+    pub avg_queue_bytes: f64,
+    // RFC 3550 interarrival jitter per agent, averaged. Older reports have the stddev of the per-second means here
     pub jitter_ms: f64,
+    // Over every delivered packet in the run, from the latency histogram
+    #[serde(default)]
+    pub latency_p50_ms: f64,
+    #[serde(default)]
+    pub latency_p90_ms: f64,
+    #[serde(default)]
+    pub latency_p99_ms: f64,
+    #[serde(default)]
+    pub latency_p999_ms: f64,
+    #[serde(default)]
+    pub latency_max_ms: f64,
+    // End-to-end as measured by the agents, includes the return path
+    #[serde(default)]
+    pub avg_rtt_ms: f64,
+    #[serde(default)]
+    pub frames_malformed: u64,
+    #[serde(default)]
+    pub send_errors: u64,
+    // Sequence number runs only. Lost or reordered before reaching a server, apart from the strategy's drops
+    #[serde(default)]
+    pub packets_lost_in_transit: u64,
+    #[serde(default)]
+    pub packets_reordered: u64,
+    // Link emulation, apart from the strategy's drops as well
+    #[serde(default)]
+    pub link_lost: u64,
+    #[serde(default)]
+    pub link_duplicated: u64,
+    #[serde(default)]
+    pub link_reordered: u64,
+    // ECN marks, the packets were delivered so these don't count towards loss
+    #[serde(default)]
+    pub packets_marked: u64,
+    #[serde(default)]
+    pub mark_rate: f64,
+    // Jain's index over what every flow got delivered, 1 is perfectly fair
+    #[serde(default)]
+    pub flows: u64,
+    #[serde(default)]
+    pub flow_fairness: f64,
+    // Seed the run was started with, None for reports from older versions
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub servers: Vec<ServerReport>,
+    #[serde(default)]
+    pub agents: Vec<AgentReport>,
+    // Per priority class that sent anything, lowest first
+    #[serde(default)]
+    pub priorities: Vec<PriorityReport>,
+    // The strategy's internal queues summed over servers, by name. Empty for single-queue strategies
+    #[serde(default)]
+    pub queues: Vec<QueueReport>,
+}
+
+/// Same averages as the report itself, for one server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerReport {
+    pub id: u32,
+    pub avg_throughput_mbps: f64,
+    pub avg_latency_ms: f64,
+    pub packet_loss_rate: f64,
+    pub avg_queue_length: f64,
+    pub peak_queue_length: usize,
+    pub packets_marked: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentReport {
+    pub id: u32,
+    pub packets_sent: u64,
+    pub avg_throughput_mbps: f64,
+    pub avg_latency_ms: f64,
+    pub packet_loss_rate: f64,
+    pub avg_rtt_ms: f64,
+    #[serde(default)]
+    pub jitter_ms: f64,
+    pub packets_marked: u64,
+}
+
+/// One priority class over the whole run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriorityReport {
+    pub priority: Priority,
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub avg_throughput_mbps: f64,
+    pub avg_latency_ms: f64,
+    pub latency_p99_ms: f64,
+    pub packet_loss_rate: f64,
+}
+
+/// One internal queue of the strategy (DualPI2's L or classic queue) over the whole run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueReport {
+    pub name: String,
+    pub packets_dequeued: u64,
+    pub packets_marked: u64,
+    pub packets_dropped: u64,
+    pub avg_sojourn_ms: f64,
+    pub mark_rate: f64,
 }
 
 pub fn analyze(snapshots: &[MetricsSnapshot], strategy_name: &str) -> AnalysisReport {
@@ -21,26 +129,90 @@ pub fn analyze(snapshots: &[MetricsSnapshot], strategy_name: &str) -> AnalysisRe
         return AnalysisReport {
             strategy_name: strategy_name.to_string(),
             avg_throughput_mbps: 0.0,
+            peak_throughput_mbps: 0.0,
             avg_latency_ms: 0.0,
             packet_loss_rate: 0.0,
             peak_queue_length: 0,
             avg_queue_length: 0.0,
+            avg_queue_bytes: 0.0,
             jitter_ms: 0.0,
+            latency_p50_ms: 0.0,
+            latency_p90_ms: 0.0,
+            latency_p99_ms: 0.0,
+            latency_p999_ms: 0.0,
+            latency_max_ms: 0.0,
+            avg_rtt_ms: 0.0,
+            frames_malformed: 0,
+            send_errors: 0,
+            packets_lost_in_transit: 0,
+            packets_reordered: 0,
+            link_lost: 0,
+            link_duplicated: 0,
+            link_reordered: 0,
+            packets_marked: 0,
+            mark_rate: 0.0,
+            flows: 0,
+            flow_fairness: 0.0,
+            seed: None,
+            servers: Vec::new(),
+            agents: Vec::new(),
+            priorities: Vec::new(),
+            queues: Vec::new(),
         };
     }
     
-    let avg_throughput_mbps = snapshots.iter()
-        .map(|s| s.throughput_bps / 1_000_000.0)
-        .sum::<f64>() / snapshots.len() as f64;
+    // Per interval, so every second weighs the same instead of the start dominating a running average
+    let avg_throughput_mbps = mean(snapshots.iter().map(|s| s.interval_throughput_bps / 1_000_000.0));
     
-    let avg_latency_ms = snapshots.iter()
-        .map(|s| s.avg_latency_ms)
-        .sum::<f64>() / snapshots.len() as f64;
+    let peak_throughput_mbps = snapshots.iter()
+        .map(|s| s.interval_throughput_bps / 1_000_000.0)
+        .fold(0.0, f64::max);
+    
+    // Intervals where nothing got through have no latency, skip them
+    let avg_latency_ms = mean(snapshots.iter()
+        .filter(|s| s.interval_received > 0)
+        .map(|s| s.interval_latency_ms));
     
     let packet_loss_rate = snapshots.last()
         .map(|s| s.packet_loss_rate)
         .unwrap_or(0.0);
     
+    // Cumulative already, the last snapshot covers the whole run
+    let avg_rtt_ms = snapshots.last()
+        .map(|s| s.avg_rtt_ms)
+        .unwrap_or(0.0);
+    
+    let frames_malformed = snapshots.last()
+        .map(|s| s.frames_malformed)
+        .unwrap_or(0);
+    
+    let send_errors = snapshots.last()
+        .map(|s| s.send_errors)
+        .unwrap_or(0);
+    
+    let (packets_lost_in_transit, packets_reordered) = snapshots.last()
+        .map(|s| (s.transit_lost, s.transit_reordered))
+        .unwrap_or((0, 0));
+    
+    let (link_lost, link_duplicated, link_reordered) = snapshots.last()
+        .map(|s| (s.link_lost, s.link_duplicated, s.link_reordered))
+        .unwrap_or((0, 0, 0));
+    
+    let (packets_marked, mark_rate) = snapshots.last()
+        .map(|s| {
+            let rate = if s.packets_sent > 0 {
+                s.packets_marked as f64 / s.packets_sent as f64
+            } else {
+                0.0
+            };
+            (s.packets_marked, rate)
+        })
+        .unwrap_or((0, 0.0));
+    
+    let (flows, flow_fairness) = snapshots.last()
+        .map(|s| (s.flows, s.flow_fairness))
+        .unwrap_or((0, 0.0));
+    
     let peak_queue_length = snapshots.iter()
         .map(|s| s.queue_length)
         .max()
@@ -50,28 +222,153 @@ pub fn analyze(snapshots: &[MetricsSnapshot], strategy_name: &str) -> AnalysisRe
         .map(|s| s.queue_length as f64)
         .sum::<f64>() / snapshots.len() as f64;
     
-    let latencies: Vec<f64> = snapshots.iter().map(|s| s.avg_latency_ms).collect();
-    let jitter_ms = if latencies.len() > 1 {
-        let mean = avg_latency_ms;
-        let variance = latencies.iter()
-            .map(|l| (l - mean).powi(2))
-            .sum::<f64>() / latencies.len() as f64;
-        variance.sqrt()
-    } else {
-        0.0
-    };
+    let avg_queue_bytes = snapshots.iter()
+        .map(|s| s.queue_bytes as f64)
+        .sum::<f64>() / snapshots.len() as f64;
+    
+    let jitter_ms = snapshots.iter()
+        .map(|s| s.jitter_ms)
+        .sum::<f64>() / snapshots.len() as f64;
+    
+    // The histogram is cumulative, the last one has every packet
+    let latency = &snapshots[snapshots.len() - 1].latency;
     
     AnalysisReport {
         strategy_name: strategy_name.to_string(),
         avg_throughput_mbps,
+        peak_throughput_mbps,
         avg_latency_ms,
         packet_loss_rate,
         peak_queue_length,
         avg_queue_length,
+        avg_queue_bytes,
         jitter_ms,
+        latency_p50_ms: latency.percentile_ms(0.50),
+        latency_p90_ms: latency.percentile_ms(0.90),
+        latency_p99_ms: latency.percentile_ms(0.99),
+        latency_p999_ms: latency.percentile_ms(0.999),
+        latency_max_ms: latency.max_ms(),
+        avg_rtt_ms,
+        frames_malformed,
+        send_errors,
+        packets_lost_in_transit,
+        packets_reordered,
+        link_lost,
+        link_duplicated,
+        link_reordered,
+        packets_marked,
+        mark_rate,
+        flows,
+        flow_fairness,
+        seed: None,
+        servers: analyze_servers(snapshots),
+        agents: analyze_agents(snapshots),
+        priorities: analyze_priorities(snapshots),
+        queues: analyze_queues(snapshots),
     }
 }
 
+// Servers show up in a snapshot from their first packet on, average over the snapshots that have them
+fn mean(values: impl Iterator<Item = f64>) -> f64 {
+    let (sum, n) = values.fold((0.0, 0usize), |(sum, n), v| (sum + v, n + 1));
+    if n > 0 { sum / n as f64 } else { 0.0 }
+}
+
+fn analyze_servers(snapshots: &[MetricsSnapshot]) -> Vec<ServerReport> {
+    let Some(last) = snapshots.last() else {
+        return Vec::new();
+    };
+
+    last.servers.iter()
+        .map(|(&id, stats)| {
+            let history = || snapshots.iter().filter_map(move |s| s.servers.get(&id));
+            ServerReport {
+                id,
+                avg_throughput_mbps: mean(history().map(|s| s.interval_throughput_bps / 1_000_000.0)),
+                avg_latency_ms: mean(history()
+                    .filter(|s| s.interval_received > 0)
+                    .map(|s| s.interval_latency_ms)),
+                packet_loss_rate: stats.packet_loss_rate,
+                avg_queue_length: mean(history().map(|s| s.queue_length as f64)),
+                peak_queue_length: history().map(|s| s.queue_length).max().unwrap_or(0),
+                packets_marked: stats.marked,
+            }
+        })
+        .collect()
+}
+
+// Counters are cumulative, so the last snapshot has the whole run except for the throughput average
+fn analyze_agents(snapshots: &[MetricsSnapshot]) -> Vec<AgentReport> {
+    let Some(last) = snapshots.last() else {
+        return Vec::new();
+    };
+
+    last.agents.iter()
+        .map(|(&id, stats)| AgentReport {
+            id,
+            packets_sent: stats.sent,
+            avg_throughput_mbps: mean(snapshots.iter()
+                .filter_map(|s| s.agents.get(&id))
+                .map(|s| s.interval_throughput_bps / 1_000_000.0)),
+            avg_latency_ms: stats.avg_latency_ms,
+            packet_loss_rate: stats.packet_loss_rate,
+            avg_rtt_ms: stats.avg_rtt_ms,
+            jitter_ms: stats.jitter_ms,
+            packets_marked: stats.marked,
+        })
+        .collect()
+}
+
+// Cumulative like the agents, the last snapshot has it all
+fn analyze_priorities(snapshots: &[MetricsSnapshot]) -> Vec<PriorityReport> {
+    let Some(last) = snapshots.last() else {
+        return Vec::new();
+    };
+
+    last.priorities.iter()
+        .map(|(&priority, stats)| PriorityReport {
+            priority,
+            packets_sent: stats.sent,
+            packets_received: stats.received,
+            avg_throughput_mbps: stats.throughput_bps / 1_000_000.0,
+            avg_latency_ms: stats.avg_latency_ms,
+            latency_p99_ms: stats.latency.percentile_ms(0.99),
+            packet_loss_rate: stats.packet_loss_rate,
+        })
+        .collect()
+}
+
+// Cumulative, and every server runs the same strategy so the names line up
+fn analyze_queues(snapshots: &[MetricsSnapshot]) -> Vec<QueueReport> {
+    let Some(last) = snapshots.last() else {
+        return Vec::new();
+    };
+
+    let mut by_name: Vec<QueueStats> = Vec::new();
+    for queue in last.servers.values().flat_map(|s| &s.queues) {
+        match by_name.iter_mut().find(|q| q.name == queue.name) {
+            Some(total) => {
+                total.dequeued += queue.dequeued;
+                total.marked += queue.marked;
+                total.dropped += queue.dropped;
+                total.total_sojourn_ms += queue.total_sojourn_ms;
+            }
+            None => by_name.push(*queue),
+        }
+    }
+
+    by_name.iter()
+        .map(|queue| QueueReport {
+            name: queue.name.to_string(),
+            packets_dequeued: queue.dequeued,
+            packets_marked: queue.marked,
+            packets_dropped: queue.dropped,
+            avg_sojourn_ms: queue.avg_sojourn_ms(),
+            mark_rate: queue.mark_rate(),
+        })
+        .collect()
+}
+
 pub fn export_latex_table(reports: &[AnalysisReport], output_path: impl AsRef<Path>) -> Result<()> {
     let mut file = File::create(output_path)?;
     
@@ -155,10 +452,18 @@ pub fn export_latex_detailed(reports: &[AnalysisReport], output_path: impl AsRef
         writeln!(file, "\\begin{{itemize}}")?;
         writeln!(file, "\t\\item Gemiddelde doorvoer: {:.2} Mbps", report.avg_throughput_mbps)?;
         writeln!(file, "\t\\item Gemiddelde latentie: {:.2} ms", report.avg_latency_ms)?;
+        writeln!(file, "\t\\item Latentie p50 / p90 / p99 / p99.9: {:.2} / {:.2} / {:.2} / {:.2} ms",
+            report.latency_p50_ms, report.latency_p90_ms, report.latency_p99_ms, report.latency_p999_ms)?;
+        writeln!(file, "\t\\item Maximale latentie: {:.2} ms", report.latency_max_ms)?;
+        writeln!(file, "\t\\item Gemiddelde RTT: {:.2} ms", report.avg_rtt_ms)?;
         writeln!(file, "\t\\item Pakketverlies: {:.2}\\%", report.packet_loss_rate * 100.0)?;
-        writeln!(file, "\t\\item Gemiddelde wachtrijlengte: {:.1} pakketten", report.avg_queue_length)?;
+        writeln!(file, "\t\\item ECN-markeringen: {} ({:.2}\\%)", report.packets_marked, report.mark_rate * 100.0)?;
+        writeln!(file, "\t\\item Gemiddelde wachtrijlengte: {:.1} pakketten ({:.1} KB)", report.avg_queue_length, report.avg_queue_bytes / 1024.0)?;
         writeln!(file, "\t\\item Piek wachtrijlengte: {} pakketten", report.peak_queue_length)?;
         writeln!(file, "\t\\item Jitter: {:.2} ms", report.jitter_ms)?;
+        if report.flows > 1 {
+            writeln!(file, "\t\\item Eerlijkheid tussen flows (Jain): {:.3} over {} flows", report.flow_fairness, report.flows)?;
+        }
         writeln!(file, "\\end{{itemize}}")?;
         writeln!(file)?;
     }
@@ -170,17 +475,19 @@ pub fn export_latex_plot_data(snapshots: &[MetricsSnapshot], output_path: impl A
     let mut file = File::create(output_path)?;
     
     writeln!(file, "% Time-series data for pgfplots")?;
-    writeln!(file, "% Format: time throughput latency queue_length packet_loss\n")?;
+    writeln!(file, "% Per interval, not running averages")?;
+    writeln!(file, "% Format: time throughput latency queue_length packet_loss latency_p99\n")?;
     
     for snapshot in snapshots {
         writeln!(
             file,
-            "{:.2} {:.2} {:.2} {} {:.4}",
+            "{:.2} {:.2} {:.2} {} {:.4} {:.2}",
             snapshot.timestamp,
-            snapshot.throughput_bps / 1_000_000.0,
-            snapshot.avg_latency_ms,
+            snapshot.interval_throughput_bps / 1_000_000.0,
+            snapshot.interval_latency_ms,
             snapshot.queue_length,
-            snapshot.packet_loss_rate,
+            snapshot.interval_loss_rate,
+            snapshot.latency_p99_ms,
         )?;
     }
     
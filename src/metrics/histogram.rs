@@ -0,0 +1,102 @@
+// Log-bucketed latency histogram, HDR-style. Every power of two is split into 32 linear buckets,
+// so any recorded value is off by at most ~3%. Values are microseconds, 1us up to hours fits in < 1000 buckets.
+
+use std::time::Duration;
+
+const SUB_BUCKET_BITS: u32 = 5;
+const SUB_BUCKETS: u64 = 1 << SUB_BUCKET_BITS;
+
+#[derive(Debug, Clone, Default)]
+pub struct LatencyHistogram {
+    counts: Vec<u64>,
+    total: u64,
+    max_us: u64,
+}
+
+fn bucket_index(us: u64) -> usize {
+    if us < SUB_BUCKETS {
+        return us as usize;
+    }
+    let exp = 63 - us.leading_zeros();
+    let shift = exp - SUB_BUCKET_BITS;
+    let sub = (us >> shift) - SUB_BUCKETS;
+    ((shift as u64 + 1) * SUB_BUCKETS + sub) as usize
+}
+
+// Smallest value and width of a bucket
+fn bucket_range(index: usize) -> (u64, u64) {
+    let index = index as u64;
+    if index < SUB_BUCKETS {
+        return (index, 1);
+    }
+    let shift = index / SUB_BUCKETS - 1;
+    let sub = index % SUB_BUCKETS;
+    ((SUB_BUCKETS + sub) << shift, 1 << shift)
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        let us = latency.as_micros().min(u64::MAX as u128) as u64;
+        let index = bucket_index(us);
+        if index >= self.counts.len() {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += 1;
+        self.total += 1;
+        self.max_us = self.max_us.max(us);
+    }
+
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        if other.counts.len() > self.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.total += other.total;
+        self.max_us = self.max_us.max(other.max_us);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    /// `q` in [0, 1], e.g. 0.99. Bucket midpoint in ms, 0 for an empty histogram
+    pub fn percentile_ms(&self, q: f64) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * self.total as f64).ceil() as u64).max(1);
+
+        let mut seen = 0;
+        for (index, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let (low, width) = bucket_range(index);
+                // Never past the largest value we actually saw
+                let mid = (low + width / 2).min(self.max_us);
+                return mid as f64 / 1000.0;
+            }
+        }
+        self.max_ms()
+    }
+
+    /// Exact, not bucketed
+    pub fn max_ms(&self) -> f64 {
+        self.max_us as f64 / 1000.0
+    }
+
+    pub fn clear(&mut self) {
+        self.counts.clear();
+        self.total = 0;
+        self.max_us = 0;
+    }
+}
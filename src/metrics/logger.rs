@@ -20,6 +20,105 @@ impl MetricsLogger {
         Ok(())
     }
     
+    /// Long format, one row per agent per snapshot. cwnd/srtt_ms are empty for open-loop agents
+    pub fn log_agents(&mut self, snapshots: &[MetricsSnapshot]) -> Result<()> {
+        self.writer.write_record([
+            "timestamp", "agent", "sent", "received", "dropped", "marked", "throughput_bps",
+            "avg_latency_ms", "packet_loss_rate", "interval_sent", "interval_received", "interval_dropped",
+            "interval_throughput_bps", "interval_latency_ms", "avg_rtt_ms", "jitter_ms", "cwnd", "srtt_ms",
+        ])?;
+        for snapshot in snapshots {
+            for (agent, stats) in &snapshot.agents {
+                self.writer.write_record([
+                    snapshot.timestamp.to_string(),
+                    agent.to_string(),
+                    stats.sent.to_string(),
+                    stats.received.to_string(),
+                    stats.dropped.to_string(),
+                    stats.marked.to_string(),
+                    stats.throughput_bps.to_string(),
+                    stats.avg_latency_ms.to_string(),
+                    stats.packet_loss_rate.to_string(),
+                    stats.interval_sent.to_string(),
+                    stats.interval_received.to_string(),
+                    stats.interval_dropped.to_string(),
+                    stats.interval_throughput_bps.to_string(),
+                    stats.interval_latency_ms.to_string(),
+                    stats.avg_rtt_ms.to_string(),
+                    stats.jitter_ms.to_string(),
+                    stats.cwnd.map(|c| c.to_string()).unwrap_or_default(),
+                    stats.srtt_ms.map(|s| s.to_string()).unwrap_or_default(),
+                ])?;
+            }
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+    
+    /// Long format like log_agents, one row per server per snapshot
+    pub fn log_servers(&mut self, snapshots: &[MetricsSnapshot]) -> Result<()> {
+        self.writer.write_record([
+            "timestamp", "server", "received", "dropped", "marked", "throughput_bps",
+            "avg_latency_ms", "packet_loss_rate", "queue_length", "queue_bytes",
+            "interval_received", "interval_dropped", "interval_throughput_bps", "interval_latency_ms",
+            "transit_lost", "transit_reordered", "link_lost", "link_duplicated", "link_reordered",
+        ])?;
+        for snapshot in snapshots {
+            for (server, stats) in &snapshot.servers {
+                self.writer.write_record([
+                    snapshot.timestamp.to_string(),
+                    server.to_string(),
+                    stats.received.to_string(),
+                    stats.dropped.to_string(),
+                    stats.marked.to_string(),
+                    stats.throughput_bps.to_string(),
+                    stats.avg_latency_ms.to_string(),
+                    stats.packet_loss_rate.to_string(),
+                    stats.queue_length.to_string(),
+                    stats.queue_bytes.to_string(),
+                    stats.interval_received.to_string(),
+                    stats.interval_dropped.to_string(),
+                    stats.interval_throughput_bps.to_string(),
+                    stats.interval_latency_ms.to_string(),
+                    stats.transit_lost.to_string(),
+                    stats.transit_reordered.to_string(),
+                    stats.link_lost.to_string(),
+                    stats.link_duplicated.to_string(),
+                    stats.link_reordered.to_string(),
+                ])?;
+            }
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+    
+    /// Long format, one row per internal queue per server per snapshot. Only strategies with several queues have any
+    pub fn log_queues(&mut self, snapshots: &[MetricsSnapshot]) -> Result<()> {
+        self.writer.write_record([
+            "timestamp", "server", "queue", "length", "dequeued", "marked", "dropped",
+            "avg_sojourn_ms", "mark_rate",
+        ])?;
+        for snapshot in snapshots {
+            for (server, stats) in &snapshot.servers {
+                for queue in &stats.queues {
+                    self.writer.write_record([
+                        snapshot.timestamp.to_string(),
+                        server.to_string(),
+                        queue.name.to_string(),
+                        queue.length.to_string(),
+                        queue.dequeued.to_string(),
+                        queue.marked.to_string(),
+                        queue.dropped.to_string(),
+                        queue.avg_sojourn_ms().to_string(),
+                        queue.mark_rate().to_string(),
+                    ])?;
+                }
+            }
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+    
     pub fn log_batch(&mut self, snapshots: &[MetricsSnapshot]) -> Result<()> {
         for snapshot in snapshots {
             self.writer.serialize(snapshot)?;
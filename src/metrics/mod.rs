@@ -1,10 +1,16 @@
 pub mod logger;
 pub mod analyzer;
+pub mod histogram;
+
+pub use histogram::LatencyHistogram;
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use parking_lot::RwLock;
+use crate::network::{Feedback, FlowKey, Packet, Priority, Transit};
+use crate::simulation::clock;
 use tracing::warn;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,10 +19,290 @@ pub struct MetricsSnapshot {
     pub packets_sent: u64,
     pub packets_received: u64,
     pub packets_dropped: u64,
+    pub packets_marked: u64,
     pub throughput_bps: f64,
     pub avg_latency_ms: f64,
+    // Summed over all servers
     pub queue_length: usize,
+    #[serde(default)]
+    pub queue_bytes: u64,
+    pub packet_loss_rate: f64,
+    pub frames_malformed: u64,
+    // Packets the agents couldn't send (connection errors). Never sent, so neither drops nor part of the loss rate
+    #[serde(default)]
+    pub send_errors: u64,
+    // End-to-end, send to feedback arrival at the agent, averaged over every acked packet
+    #[serde(default)]
+    pub avg_rtt_ms: f64,
+    // Mean congestion window over window agents, 0 when there are none
+    #[serde(default)]
+    pub avg_cwnd: f64,
+    // Everything since the previous snapshot, the counters and averages above are cumulative
+    #[serde(default)]
+    pub interval_secs: f64,
+    #[serde(default)]
+    pub interval_sent: u64,
+    #[serde(default)]
+    pub interval_received: u64,
+    #[serde(default)]
+    pub interval_dropped: u64,
+    #[serde(default)]
+    pub interval_marked: u64,
+    #[serde(default)]
+    pub interval_bytes: u64,
+    #[serde(default)]
+    pub interval_throughput_bps: f64,
+    #[serde(default)]
+    pub interval_latency_ms: f64,
+    // Drops in the interval over sends in the interval, a drop can belong to an earlier send so this may pass 1
+    #[serde(default)]
+    pub interval_loss_rate: f64,
+    // Latency distribution over this snapshot's interval only
+    #[serde(default)]
+    pub latency_p50_ms: f64,
+    #[serde(default)]
+    pub latency_p90_ms: f64,
+    #[serde(default)]
+    pub latency_p99_ms: f64,
+    #[serde(default)]
+    pub latency_p999_ms: f64,
+    #[serde(default)]
+    pub latency_max_ms: f64,
+    // RFC 3550 interarrival jitter, averaged over agents
+    #[serde(default)]
+    pub jitter_ms: f64,
+    // From sequence numbers: packets that never reached a server and ones that got there out of order.
+    // Lost on the way, not dropped by a strategy, so not part of packets_dropped or the loss rate
+    #[serde(default)]
+    pub transit_lost: u64,
+    #[serde(default)]
+    pub transit_reordered: u64,
+    // What the emulated links did, also not part of packets_dropped. Duplicates do get delivered (or dropped) twice
+    #[serde(default)]
+    pub link_lost: u64,
+    #[serde(default)]
+    pub link_duplicated: u64,
+    #[serde(default)]
+    pub link_reordered: u64,
+    // Flows (agent, stream, destination, protocol) that sent anything so far, and Jain's fairness index over
+    // what each of them got delivered. 1 when every flow got the same, 1/flows when one got everything
+    #[serde(default)]
+    pub flows: u64,
+    #[serde(default)]
+    pub flow_fairness: f64,
+    // Whole run up to this snapshot
+    #[serde(skip)]
+    pub latency: LatencyHistogram,
+    // Per server and per agent, too nested for the flat CSV so they get their own files
+    #[serde(skip)]
+    pub servers: BTreeMap<u32, ServerStats>,
+    #[serde(skip)]
+    pub agents: BTreeMap<u32, AgentStats>,
+    // Only classes that sent something
+    #[serde(skip)]
+    pub priorities: BTreeMap<Priority, PriorityStats>,
+}
+
+/// One server's share of the totals, queue is its current length
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServerStats {
+    pub received: u64,
+    pub dropped: u64,
+    pub marked: u64,
+    pub throughput_bps: f64,
+    pub avg_latency_ms: f64,
+    pub packet_loss_rate: f64,
+    pub queue_length: usize,
+    pub queue_bytes: u64,
+    pub interval_received: u64,
+    pub interval_dropped: u64,
+    pub interval_throughput_bps: f64,
+    pub interval_latency_ms: f64,
+    pub transit_lost: u64,
+    pub transit_reordered: u64,
+    pub link_lost: u64,
+    pub link_duplicated: u64,
+    pub link_reordered: u64,
+    // Strategies with more than one internal queue (DualPI2), empty for the rest
+    #[serde(skip)]
+    pub queues: Vec<QueueStats>,
+}
+
+/// One of a strategy's internal queues, e.g. DualPI2's L and classic queue. Counters are for the whole run,
+/// length is the current one
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct QueueStats {
+    pub name: &'static str,
+    pub length: usize,
+    pub dequeued: u64,
+    pub marked: u64,
+    pub dropped: u64,
+    // Summed over every dequeued packet, time spent in this queue only
+    pub total_sojourn_ms: f64,
+}
+
+impl QueueStats {
+    pub fn avg_sojourn_ms(&self) -> f64 {
+        if self.dequeued > 0 { self.total_sojourn_ms / self.dequeued as f64 } else { 0.0 }
+    }
+
+    // Marked packets still leave the queue, so against what left it
+    pub fn mark_rate(&self) -> f64 {
+        if self.dequeued > 0 { self.marked as f64 / self.dequeued as f64 } else { 0.0 }
+    }
+}
+
+/// One agent's packets, wherever they ended up
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AgentStats {
+    pub sent: u64,
+    pub received: u64,
+    pub dropped: u64,
+    // From the feedback, CE echoes the agent got back
+    pub marked: u64,
+    pub throughput_bps: f64,
+    pub avg_latency_ms: f64,
     pub packet_loss_rate: f64,
+    pub interval_sent: u64,
+    pub interval_received: u64,
+    pub interval_dropped: u64,
+    pub interval_throughput_bps: f64,
+    pub interval_latency_ms: f64,
+    pub avg_rtt_ms: f64,
+    pub jitter_ms: f64,
+    // Only window agents have these
+    pub cwnd: Option<f64>,
+    pub srtt_ms: Option<f64>,
+}
+
+/// Everything sent at one priority, whole run
+#[derive(Debug, Clone, Default)]
+pub struct PriorityStats {
+    pub sent: u64,
+    pub received: u64,
+    pub dropped: u64,
+    pub throughput_bps: f64,
+    pub avg_latency_ms: f64,
+    pub packet_loss_rate: f64,
+    pub latency: LatencyHistogram,
+}
+
+/// Counters kept for the whole run and again per server, per agent and per priority
+#[derive(Debug, Clone, Default)]
+struct Counters {
+    sent: u64,
+    received: u64,
+    dropped: u64,
+    marked: u64,
+    bytes_received: u64,
+    total_latency_ms: f64,
+    latency_samples: u64,
+}
+
+impl Counters {
+    // What happened between `earlier` and now
+    fn since(&self, earlier: &Counters) -> Counters {
+        Counters {
+            sent: self.sent - earlier.sent,
+            received: self.received - earlier.received,
+            dropped: self.dropped - earlier.dropped,
+            marked: self.marked - earlier.marked,
+            bytes_received: self.bytes_received - earlier.bytes_received,
+            total_latency_ms: self.total_latency_ms - earlier.total_latency_ms,
+            latency_samples: self.latency_samples - earlier.latency_samples,
+        }
+    }
+
+    fn receive(&mut self, bytes: u32, latency_ms: Option<f64>) {
+        self.received += 1;
+        self.bytes_received += bytes as u64;
+        if let Some(latency_ms) = latency_ms {
+            self.total_latency_ms += latency_ms;
+            self.latency_samples += 1;
+        }
+    }
+
+    fn throughput_bps(&self, elapsed: f64) -> f64 {
+        if elapsed > 0.0 {
+            (self.bytes_received as f64 * 8.0) / elapsed
+        } else {
+            0.0
+        }
+    }
+
+    fn avg_latency_ms(&self) -> f64 {
+        if self.latency_samples > 0 {
+            self.total_latency_ms / self.latency_samples as f64
+        } else {
+            0.0
+        }
+    }
+
+    // Servers never see what was sent to them, so against what arrived there
+    fn loss_rate(&self, offered: u64) -> f64 {
+        if offered > 0 {
+            self.dropped as f64 / offered as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+/// What the emulated links did to packets on their way in
+#[derive(Debug, Clone, Copy, Default)]
+struct LinkCounters {
+    lost: u64,
+    duplicated: u64,
+    reordered: u64,
+}
+
+impl LinkCounters {
+    fn record(&mut self, transit: &Transit) {
+        match transit {
+            Transit::Lost => self.lost += 1,
+            Transit::Arrives { reordered, duplicate, .. } => {
+                self.reordered += *reordered as u64;
+                self.duplicated += duplicate.is_some() as u64;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ServerCounters {
+    counters: Counters,
+    // As of the last saved snapshot
+    saved: Counters,
+    queue_length: usize,
+    queue_bytes: u64,
+    transit_lost: u64,
+    transit_reordered: u64,
+    link: LinkCounters,
+    queues: Vec<QueueStats>,
+}
+
+#[derive(Debug, Default)]
+struct AgentCounters {
+    counters: Counters,
+    saved: Counters,
+    total_rtt_ms: f64,
+    rtt_samples: u64,
+    // RFC 3550 section 6.4.1, over the agent's packets in the order they left the servers
+    last_transit_ms: Option<f64>,
+    jitter_ms: f64,
+    window: Option<(f64, Option<f64>)>,
+}
+
+#[derive(Debug, Default)]
+struct FlowCounters {
+    sent: u64,
+    bytes_received: u64,
+}
+
+#[derive(Debug, Default)]
+struct PriorityCounters {
+    counters: Counters,
+    latency: LatencyHistogram,
 }
 
 #[derive(Debug, Clone)]
@@ -25,75 +311,190 @@ pub struct MetricsCollector {
     start_time: Instant,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 struct MetricsInner {
-    packets_sent: u64,
-    packets_received: u64,
-    packets_dropped: u64,
-    total_latency_ms: f64,
-    latency_samples: u64,
-    queue_lengths: Vec<usize>,
+    total: Counters,
+    saved: Counters,
+    saved_at: f64,
+    frames_malformed: u64,
+    send_errors: u64,
+    transit_lost: u64,
+    transit_reordered: u64,
+    link: LinkCounters,
+    latency: LatencyHistogram,
+    // Since the last saved snapshot
+    interval_latency: LatencyHistogram,
+    servers: BTreeMap<u32, ServerCounters>,
+    agents: BTreeMap<u32, AgentCounters>,
+    priorities: BTreeMap<Priority, PriorityCounters>,
+    flows: BTreeMap<FlowKey, FlowCounters>,
     snapshots: Vec<MetricsSnapshot>,
 }
 
+// Only reject truly impossible values (30s as always)
+fn valid_latency_ms(latency: Duration) -> Option<f64> {
+    let latency_ms = latency.as_secs_f64() * 1000.0;
+    if latency_ms > 30_000.0 {
+        warn!("Detected impossible latency: {:.2}ms - ignoring sample (likely timing bug)", latency_ms);
+        return None;
+    }
+    Some(latency_ms)
+}
+
+/// (n, Jain's index) over the values, (sum x)² / (n * sum x²). 0 when there's nothing to compare
+fn jain_index(values: impl Iterator<Item = f64>) -> (u64, f64) {
+    let (n, sum, sum_sq) = values.fold((0u64, 0.0, 0.0), |(n, sum, sum_sq), x| (n + 1, sum + x, sum_sq + x * x));
+    if sum_sq > 0.0 {
+        (n, sum * sum / (n as f64 * sum_sq))
+    } else {
+        (n, 0.0)
+    }
+}
+
 impl MetricsCollector {
     pub fn new() -> Self {
         Self {
-            inner: Arc::new(RwLock::new(MetricsInner {
-                packets_sent: 0,
-                packets_received: 0,
-                packets_dropped: 0,
-                total_latency_ms: 0.0,
-                latency_samples: 0,
-                queue_lengths: Vec::new(),
-                snapshots: Vec::new(),
-            })),
-            start_time: Instant::now(),
+            inner: Arc::new(RwLock::new(MetricsInner::default())),
+            start_time: clock::now(),
         }
     }
 
-    pub fn packet_sent(&self) {
-        self.inner.write().packets_sent += 1;
+    pub fn packet_sent(&self, flow: &FlowKey, priority: Priority) {
+        let mut inner = self.inner.write();
+        inner.total.sent += 1;
+        inner.agents.entry(flow.agent).or_default().counters.sent += 1;
+        inner.priorities.entry(priority).or_default().counters.sent += 1;
+        inner.flows.entry(*flow).or_default().sent += 1;
     }
 
-    pub fn packet_received(&self, latency: Duration) {
+    // Latency is the packet's sojourn time when it left the server
+    pub fn packet_received(&self, server_id: u32, packet: &Packet, latency: Duration) {
+        // Only count valid samples for average calculation
+        let latency_ms = valid_latency_ms(latency);
+
         let mut inner = self.inner.write();
-        inner.packets_received += 1;
-        
-        let latency_ms = latency.as_secs_f64() * 1000.0;
-        
-        // Only reject truly impossible values (30s as always)
-        if latency_ms > 30_000.0 {
-            warn!("Detected impossible latency: {:.2}ms - ignoring sample (likely timing bug)", latency_ms);
-            return;
+        inner.total.receive(packet.payload_size, latency_ms);
+        inner.servers.entry(server_id).or_default().counters.receive(packet.payload_size, latency_ms);
+        inner.flows.entry(packet.flow).or_default().bytes_received += packet.payload_size as u64;
+        let class = inner.priorities.entry(packet.priority).or_default();
+        class.counters.receive(packet.payload_size, latency_ms);
+        if latency_ms.is_some() {
+            class.latency.record(latency);
+            inner.latency.record(latency);
+            inner.interval_latency.record(latency);
         }
-        
-        // Only count valid samples for average calculation
-        inner.total_latency_ms += latency_ms;
-        inner.latency_samples += 1;
+
+        let agent = inner.agents.entry(packet.source_agent).or_default();
+        agent.counters.receive(packet.payload_size, latency_ms);
+        if let Some(transit) = latency_ms {
+            if let Some(last) = agent.last_transit_ms {
+                agent.jitter_ms += ((transit - last).abs() - agent.jitter_ms) / 16.0;
+            }
+            agent.last_transit_ms = Some(transit);
+        }
+    }
+
+    // Sent on by a router port, only the port's own counters. The sink counts it as received
+    pub fn packet_forwarded(&self, server_id: u32, packet: &Packet) {
+        self.inner.write().servers.entry(server_id).or_default().counters.receive(packet.payload_size, None);
     }
 
-    pub fn packet_dropped(&self) {
-        self.inner.write().packets_dropped += 1;
+    pub fn packet_dropped(&self, server_id: u32, packet: &Packet) {
+        let mut inner = self.inner.write();
+        inner.total.dropped += 1;
+        inner.servers.entry(server_id).or_default().counters.dropped += 1;
+        inner.agents.entry(packet.source_agent).or_default().counters.dropped += 1;
+        inner.priorities.entry(packet.priority).or_default().counters.dropped += 1;
+    }
+
+    // Never left the agent, kept apart from strategy drops so dropped/sent stays a loss rate
+    pub fn send_failed(&self) {
+        self.inner.write().send_errors += 1;
     }
 
-    pub fn record_queue_length(&self, len: usize) {
-        self.inner.write().queue_lengths.push(len);
+    // CE marks set by a strategy, these packets still get delivered
+    pub fn packet_marked(&self, server_id: u32) {
+        let mut inner = self.inner.write();
+        inner.total.marked += 1;
+        inner.servers.entry(server_id).or_default().counters.marked += 1;
+    }
+
+    // Frames that arrived but couldn't be decoded, kept apart from strategy drops
+    pub fn frame_malformed(&self) {
+        self.inner.write().frames_malformed += 1;
+    }
+
+    // Sequence gap at a server, packets lost before they got there
+    pub fn transit_lost(&self, server_id: u32, count: u64) {
+        let mut inner = self.inner.write();
+        inner.transit_lost += count;
+        inner.servers.entry(server_id).or_default().transit_lost += count;
+    }
+
+    // Showed up after a newer packet, the gap it left was already counted as lost
+    pub fn transit_late(&self, server_id: u32) {
+        let mut inner = self.inner.write();
+        inner.transit_lost = inner.transit_lost.saturating_sub(1);
+        inner.transit_reordered += 1;
+        let server = inner.servers.entry(server_id).or_default();
+        server.transit_lost = server.transit_lost.saturating_sub(1);
+        server.transit_reordered += 1;
+    }
+
+    // Whatever the link between the packet's agent and this server did to it
+    pub fn link_transit(&self, server_id: u32, transit: &Transit) {
+        let mut inner = self.inner.write();
+        inner.link.record(transit);
+        inner.servers.entry(server_id).or_default().link.record(transit);
+    }
+
+    pub fn record_queue_length(&self, server_id: u32, len: usize, bytes: u64) {
+        let mut inner = self.inner.write();
+        let server = inner.servers.entry(server_id).or_default();
+        server.queue_length = len;
+        server.queue_bytes = bytes;
+    }
+
+    // Whatever the strategy reports about its internal queues, replaces the last report
+    pub fn record_queue_stats(&self, server_id: u32, queues: Vec<QueueStats>) {
+        self.inner.write().servers.entry(server_id).or_default().queues = queues;
+    }
+
+    // srtt is None until the agent got its first ack
+    pub fn record_window(&self, agent_id: u32, cwnd: f64, srtt: Option<Duration>) {
+        let srtt_ms = srtt.map(|s| s.as_secs_f64() * 1000.0);
+        self.inner.write().agents.entry(agent_id).or_default().window = Some((cwnd, srtt_ms));
+    }
+
+    // Feedback as seen by the agent, the server side is already counted by packet_received/dropped
+    pub fn packet_acked(&self, feedback: &Feedback) {
+        let rtt_ms = feedback.rtt().map(|rtt| rtt.as_secs_f64() * 1000.0);
+
+        let mut inner = self.inner.write();
+        let agent = inner.agents.entry(feedback.source_agent).or_default();
+        if feedback.ce() {
+            agent.counters.marked += 1;
+        }
+        // Same 30s sanity limit as the latency samples
+        if let Some(rtt_ms) = rtt_ms
+            && rtt_ms <= 30_000.0
+        {
+            agent.total_rtt_ms += rtt_ms;
+            agent.rtt_samples += 1;
+        }
     }
 
     pub fn snapshot(&self) -> MetricsSnapshot {
-        let inner = self.inner.read();
-        
-        let elapsed = self.start_time.elapsed().as_secs_f64();
+        self.snapshot_of(&self.inner.read())
+    }
+
+    fn snapshot_of(&self, inner: &MetricsInner) -> MetricsSnapshot {
+        let elapsed = clock::elapsed(self.start_time).as_secs_f64();
         
-        let throughput_bps = if elapsed > 0.0 {
-            (inner.packets_received as f64 * 1500.0 * 8.0) / elapsed
-        } else {
-            0.0
-        };
+        let throughput_bps = inner.total.throughput_bps(elapsed);
         
-        let avg_latency_ms = if inner.latency_samples > 0 {
-            let avg = inner.total_latency_ms / inner.latency_samples as f64;
+        let avg_latency_ms = {
+            let avg = inner.total.avg_latency_ms();
             
             // Additional sanity check on the average, redundant
             if avg > 10_000.0 {
@@ -102,33 +503,175 @@ impl MetricsCollector {
             } else {
                 avg
             }
+        };
+        
+        let packet_loss_rate = inner.total.loss_rate(inner.total.sent);
+        
+        let interval_secs = elapsed - inner.saved_at;
+        let interval = inner.total.since(&inner.saved);
+        
+        let servers: BTreeMap<u32, ServerStats> = inner.servers.iter()
+            .map(|(&id, s)| {
+                let c = &s.counters;
+                let delta = c.since(&s.saved);
+                let stats = ServerStats {
+                    received: c.received,
+                    dropped: c.dropped,
+                    marked: c.marked,
+                    throughput_bps: c.throughput_bps(elapsed),
+                    avg_latency_ms: c.avg_latency_ms(),
+                    packet_loss_rate: c.loss_rate(c.received + c.dropped),
+                    queue_length: s.queue_length,
+                    queue_bytes: s.queue_bytes,
+                    interval_received: delta.received,
+                    interval_dropped: delta.dropped,
+                    interval_throughput_bps: delta.throughput_bps(interval_secs),
+                    interval_latency_ms: delta.avg_latency_ms(),
+                    transit_lost: s.transit_lost,
+                    transit_reordered: s.transit_reordered,
+                    link_lost: s.link.lost,
+                    link_duplicated: s.link.duplicated,
+                    link_reordered: s.link.reordered,
+                    queues: s.queues.clone(),
+                };
+                (id, stats)
+            })
+            .collect();
+        
+        let queue_length = servers.values().map(|s| s.queue_length).sum();
+        let queue_bytes = servers.values().map(|s| s.queue_bytes).sum();
+        
+        let agents: BTreeMap<u32, AgentStats> = inner.agents.iter()
+            .map(|(&id, a)| {
+                let c = &a.counters;
+                let delta = c.since(&a.saved);
+                let stats = AgentStats {
+                    sent: c.sent,
+                    received: c.received,
+                    dropped: c.dropped,
+                    marked: c.marked,
+                    throughput_bps: c.throughput_bps(elapsed),
+                    avg_latency_ms: c.avg_latency_ms(),
+                    packet_loss_rate: c.loss_rate(c.sent),
+                    interval_sent: delta.sent,
+                    interval_received: delta.received,
+                    interval_dropped: delta.dropped,
+                    interval_throughput_bps: delta.throughput_bps(interval_secs),
+                    interval_latency_ms: delta.avg_latency_ms(),
+                    avg_rtt_ms: if a.rtt_samples > 0 { a.total_rtt_ms / a.rtt_samples as f64 } else { 0.0 },
+                    jitter_ms: a.jitter_ms,
+                    cwnd: a.window.map(|(cwnd, _)| cwnd),
+                    srtt_ms: a.window.and_then(|(_, srtt)| srtt),
+                };
+                (id, stats)
+            })
+            .collect();
+        
+        let priorities: BTreeMap<Priority, PriorityStats> = inner.priorities.iter()
+            .filter(|(_, p)| p.counters.sent > 0)
+            .map(|(&priority, p)| {
+                let c = &p.counters;
+                let stats = PriorityStats {
+                    sent: c.sent,
+                    received: c.received,
+                    dropped: c.dropped,
+                    throughput_bps: c.throughput_bps(elapsed),
+                    avg_latency_ms: c.avg_latency_ms(),
+                    packet_loss_rate: c.loss_rate(c.sent),
+                    latency: p.latency.clone(),
+                };
+                (priority, stats)
+            })
+            .collect();
+        
+        let (flows, flow_fairness) = jain_index(inner.flows.values().filter(|f| f.sent > 0).map(|f| f.bytes_received as f64));
+        
+        let rtt_samples: u64 = inner.agents.values().map(|a| a.rtt_samples).sum();
+        let avg_rtt_ms = if rtt_samples > 0 {
+            inner.agents.values().map(|a| a.total_rtt_ms).sum::<f64>() / rtt_samples as f64
         } else {
             0.0
         };
         
-        let packet_loss_rate = if inner.packets_sent > 0 {
-            inner.packets_dropped as f64 / inner.packets_sent as f64
+        let windows: Vec<f64> = agents.values().filter_map(|a| a.cwnd).collect();
+        let avg_cwnd = if windows.is_empty() {
+            0.0
         } else {
+            windows.iter().sum::<f64>() / windows.len() as f64
+        };
+        
+        // Agents with fewer than two delivered packets have no jitter yet
+        let jitters: Vec<f64> = inner.agents.values()
+            .filter(|a| a.counters.latency_samples > 1)
+            .map(|a| a.jitter_ms)
+            .collect();
+        let jitter_ms = if jitters.is_empty() {
             0.0
+        } else {
+            jitters.iter().sum::<f64>() / jitters.len() as f64
         };
         
-        let queue_length = inner.queue_lengths.last().copied().unwrap_or(0);
+        let interval_latency = &inner.interval_latency;
         
         MetricsSnapshot {
             timestamp: elapsed,
-            packets_sent: inner.packets_sent,
-            packets_received: inner.packets_received,
-            packets_dropped: inner.packets_dropped,
+            packets_sent: inner.total.sent,
+            packets_received: inner.total.received,
+            packets_dropped: inner.total.dropped,
+            packets_marked: inner.total.marked,
             throughput_bps,
             avg_latency_ms,
             queue_length,
+            queue_bytes,
             packet_loss_rate,
+            frames_malformed: inner.frames_malformed,
+            send_errors: inner.send_errors,
+            avg_rtt_ms,
+            avg_cwnd,
+            interval_secs,
+            interval_sent: interval.sent,
+            interval_received: interval.received,
+            interval_dropped: interval.dropped,
+            interval_marked: interval.marked,
+            interval_bytes: interval.bytes_received,
+            interval_throughput_bps: interval.throughput_bps(interval_secs),
+            interval_latency_ms: interval.avg_latency_ms(),
+            interval_loss_rate: interval.loss_rate(interval.sent),
+            latency_p50_ms: interval_latency.percentile_ms(0.50),
+            latency_p90_ms: interval_latency.percentile_ms(0.90),
+            latency_p99_ms: interval_latency.percentile_ms(0.99),
+            latency_p999_ms: interval_latency.percentile_ms(0.999),
+            latency_max_ms: interval_latency.max_ms(),
+            jitter_ms,
+            transit_lost: inner.transit_lost,
+            transit_reordered: inner.transit_reordered,
+            link_lost: inner.link.lost,
+            link_duplicated: inner.link.duplicated,
+            link_reordered: inner.link.reordered,
+            flows,
+            flow_fairness,
+            latency: inner.latency.clone(),
+            servers,
+            agents,
+            priorities,
         }
     }
 
-    pub fn save_snapshot(&self) {
-        let snapshot = self.snapshot();
-        self.inner.write().snapshots.push(snapshot);
+    pub fn save_snapshot(&self) -> MetricsSnapshot {
+        // One lock so nothing lands between taking the snapshot and starting the next interval
+        let mut inner = self.inner.write();
+        let snapshot = self.snapshot_of(&inner);
+        inner.interval_latency.clear();
+        inner.saved = inner.total.clone();
+        inner.saved_at = snapshot.timestamp;
+        for server in inner.servers.values_mut() {
+            server.saved = server.counters.clone();
+        }
+        for agent in inner.agents.values_mut() {
+            agent.saved = agent.counters.clone();
+        }
+        inner.snapshots.push(snapshot.clone());
+        snapshot
     }
 
     pub fn get_snapshots(&self) -> Vec<MetricsSnapshot> {
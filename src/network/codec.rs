@@ -0,0 +1,160 @@
+// Framed wire protocol for the agent -> server stream.
+// TCP is a byte stream, so one read can hold half a packet or three of them at once.
+// Every message gets a small header so the receiver knows where it ends:
+//
+//   | magic "FN" (2) | version (1) | payload length, big endian (4) | wincode payload |
+
+use std::fmt;
+use std::marker::PhantomData;
+use wincode::{SchemaRead, SchemaWrite};
+
+pub const FRAME_MAGIC: [u8; 2] = *b"FN";
+pub const PROTOCOL_VERSION: u8 = 2; // 2: sequence numbers in Packet
+pub const HEADER_LEN: usize = 7;
+pub const MAX_FRAME_LEN: usize = 1 << 20; // 1 MiB, a lot more than any packet should need
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodecError {
+    BadMagic([u8; 2]),
+    UnsupportedVersion(u8),
+    FrameTooLarge(usize),
+    Malformed(String),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::BadMagic(magic) => write!(f, "bad frame magic {:02x?}", magic),
+            CodecError::UnsupportedVersion(v) => write!(f, "unsupported protocol version {}", v),
+            CodecError::FrameTooLarge(len) => write!(f, "frame of {} bytes exceeds limit of {}", len, MAX_FRAME_LEN),
+            CodecError::Malformed(e) => write!(f, "malformed frame payload: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+pub fn encode_frame<T>(message: &T) -> anyhow::Result<Vec<u8>>
+where
+    T: SchemaWrite<Src = T>,
+{
+    let payload = wincode::serialize(message)?;
+    if payload.len() > MAX_FRAME_LEN {
+        return Err(CodecError::FrameTooLarge(payload.len()).into());
+    }
+
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+    frame.extend_from_slice(&FRAME_MAGIC);
+    frame.push(PROTOCOL_VERSION);
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&payload);
+    Ok(frame)
+}
+
+/// A datagram holds exactly one frame, no buffering needed. Anything left over after it is an error too
+pub fn decode_frame<T>(datagram: &[u8]) -> Result<T, CodecError>
+where
+    T: for<'de> SchemaRead<'de, Dst = T>,
+{
+    let mut decoder = FrameDecoder::<T>::new();
+    decoder.extend(datagram);
+    match decoder.decode_next() {
+        Some(Ok(_)) if decoder.buffered() > 0 => Err(CodecError::Malformed(format!("{} trailing bytes", decoder.buffered()))),
+        Some(result) => result,
+        None => Err(CodecError::Malformed(format!("truncated datagram of {} bytes", datagram.len()))),
+    }
+}
+
+/// Streaming decoder, feed it whatever the socket returned and pull frames out until it runs dry.
+/// Partial frames stay buffered until the rest arrives.
+#[derive(Debug)]
+pub struct FrameDecoder<T> {
+    buf: Vec<u8>,
+    pos: usize,
+    _message: PhantomData<T>,
+}
+
+impl<T> FrameDecoder<T>
+where
+    T: for<'de> SchemaRead<'de, Dst = T>,
+{
+    pub fn new() -> Self {
+        Self {
+            buf: Vec::with_capacity(4096),
+            pos: 0,
+            _message: PhantomData,
+        }
+    }
+
+    pub fn extend(&mut self, bytes: &[u8]) {
+        // Compact before growing so the buffer doesn't creep forward forever
+        if self.pos > 0 {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Bytes received but not yet consumed by a complete frame
+    pub fn buffered(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    /// Returns None when more bytes are needed.
+    /// A broken payload is skipped as a whole, the length prefix keeps us in sync.
+    /// A broken header means we lost sync, so we skip ahead to the next magic.
+    pub fn decode_next(&mut self) -> Option<Result<T, CodecError>> {
+        let pending = &self.buf[self.pos..];
+        if pending.len() < HEADER_LEN {
+            return None;
+        }
+
+        if pending[..2] != FRAME_MAGIC {
+            let magic = [pending[0], pending[1]];
+            self.resync();
+            return Some(Err(CodecError::BadMagic(magic)));
+        }
+
+        if pending[2] != PROTOCOL_VERSION {
+            let version = pending[2];
+            self.resync();
+            return Some(Err(CodecError::UnsupportedVersion(version)));
+        }
+
+        let len = u32::from_be_bytes([pending[3], pending[4], pending[5], pending[6]]) as usize;
+        if len > MAX_FRAME_LEN {
+            self.resync();
+            return Some(Err(CodecError::FrameTooLarge(len)));
+        }
+
+        if pending.len() < HEADER_LEN + len {
+            return None;
+        }
+
+        let payload = &pending[HEADER_LEN..HEADER_LEN + len];
+        let result = wincode::deserialize::<T>(payload)
+            .map_err(|e| CodecError::Malformed(e.to_string()));
+        self.pos += HEADER_LEN + len;
+
+        Some(result)
+    }
+
+    fn resync(&mut self) {
+        let search_from = self.pos + 1;
+        self.pos = self.buf[search_from..]
+            .windows(FRAME_MAGIC.len())
+            .position(|w| w == FRAME_MAGIC)
+            .map(|offset| search_from + offset)
+            // keep a trailing byte around, it might be the first half of the next magic
+            .unwrap_or_else(|| self.buf.len().saturating_sub(FRAME_MAGIC.len() - 1).max(search_from));
+    }
+}
+
+impl<T> Default for FrameDecoder<T>
+where
+    T: for<'de> SchemaRead<'de, Dst = T>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,81 @@
+// Messages flowing back from a server to the agent that sent a packet.
+// Travels over the same connection as the packets, framed with the same codec.
+// Every packet that reaches a server gets exactly one of these, whatever happened to it.
+
+use super::{Ecn, Packet, PacketId};
+use crate::simulation::clock;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use wincode_derive::{SchemaRead, SchemaWrite};
+
+/// What happened to a packet at the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, SchemaWrite, SchemaRead)]
+pub enum Outcome {
+    Delivered,
+    // Delivered with CE set, the ECN echo
+    Marked,
+    Dropped,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SchemaWrite, SchemaRead)]
+pub struct Feedback {
+    pub packet_id: PacketId,
+    pub source_agent: u32,
+    // Which of the agent's flows the packet was on
+    pub stream: u16,
+    pub server: u32,
+    pub outcome: Outcome,
+    pub payload_size: u32,
+    // Echo of the packet's own timestamp, so the agent doesn't need to remember send times
+    pub sent_at_micros: u128,
+    // None for drops, the packet never left the queue
+    pub dequeued_at_micros: Option<u128>,
+}
+
+impl Feedback {
+    /// What `server` tells the sender of `packet`, stamped now
+    pub fn for_packet(packet: &Packet, server: u32, dropped: bool) -> Self {
+        let (outcome, dequeued_at_micros) = match (dropped, packet.ecn) {
+            (true, _) => (Outcome::Dropped, None),
+            (false, Ecn::Ce) => (Outcome::Marked, Some(clock::unix_micros())),
+            (false, _) => (Outcome::Delivered, Some(clock::unix_micros())),
+        };
+
+        Self {
+            packet_id: packet.id,
+            source_agent: packet.source_agent,
+            stream: packet.flow.stream,
+            server,
+            outcome,
+            payload_size: packet.payload_size,
+            sent_at_micros: packet.created_at_micros(),
+            dequeued_at_micros,
+        }
+    }
+
+    pub fn delivered(&self) -> bool {
+        matches!(self.outcome, Outcome::Delivered | Outcome::Marked)
+    }
+
+    pub fn ce(&self) -> bool {
+        self.outcome == Outcome::Marked
+    }
+
+    /// Send to feedback arrival, only for delivered packets. Call it when the feedback arrives
+    pub fn rtt(&self) -> Option<Duration> {
+        if !self.delivered() {
+            return None;
+        }
+        let micros = clock::unix_micros().saturating_sub(self.sent_at_micros);
+        Some(Duration::from_micros(micros.min(u64::MAX as u128) as u64))
+    }
+
+    /// Send to dequeue, the forward half of the RTT
+    pub fn one_way_delay(&self) -> Option<Duration> {
+        let micros = self.dequeued_at_micros?.saturating_sub(self.sent_at_micros);
+        Some(Duration::from_micros(micros.min(u64::MAX as u128) as u64))
+    }
+}
+
+pub type FeedbackSender = tokio::sync::mpsc::UnboundedSender<Feedback>;
+pub type FeedbackReceiver = tokio::sync::mpsc::UnboundedReceiver<Feedback>;
@@ -0,0 +1,272 @@
+// netem-like emulation of the way from an agent to a server. Every agent→server pair is its own link
+// with its own RNG stream and loss state, so a burst on one radio link doesn't hit the others.
+// What a link does to a packet (lost, delayed, overtaken, duplicated) is counted apart from what the
+// strategy does to it afterwards.
+
+use rand::Rng;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand_distr::{Distribution, Normal, Pareto};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Variation on top of the link's delay
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "kind")]
+pub enum Jitter {
+    #[default]
+    None,
+    // Anywhere in ±ms, what netem does without a distribution table
+    Uniform { ms: f64 },
+    // ms is the standard deviation
+    Normal { ms: f64 },
+    // Long tail, only ever adds delay. ms is the mean of what gets added
+    Pareto { ms: f64 },
+}
+
+impl Jitter {
+    fn ms(&self) -> f64 {
+        match *self {
+            Jitter::None => 0.0,
+            Jitter::Uniform { ms } | Jitter::Normal { ms } | Jitter::Pareto { ms } => ms,
+        }
+    }
+
+    fn sample_ms(&self, rng: &mut StdRng) -> f64 {
+        match *self {
+            Jitter::None => 0.0,
+            Jitter::Uniform { ms } => rng.gen_range(-ms..=ms),
+            Jitter::Normal { ms } => Normal::new(0.0, ms).map(|d| d.sample(rng)).unwrap_or(0.0),
+            // Shape 3 has a mean of 1.5 with scale 1, so (x - 1) * 2 averages 1
+            Jitter::Pareto { ms } => Pareto::new(1.0, 3.0).map(|d| (d.sample(rng) - 1.0) * 2.0 * ms).unwrap_or(0.0),
+        }
+    }
+}
+
+/// `0`, `2` (uniform), `uniform:2`, `normal:2` or `pareto:2`, all in ms
+impl FromStr for Jitter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, args) = s.split_once(':').unwrap_or(("uniform", s));
+        let bad = || anyhow::anyhow!("Bad jitter: {} (try 0, uniform:2, normal:2 or pareto:2)", s);
+        let ms: f64 = args.trim().parse().map_err(|_| bad())?;
+        if ms == 0.0 {
+            return Ok(Jitter::None);
+        }
+
+        match kind.to_lowercase().as_str() {
+            "uniform" => Ok(Jitter::Uniform { ms }),
+            "normal" => Ok(Jitter::Normal { ms }),
+            "pareto" => Ok(Jitter::Pareto { ms }),
+            _ => Err(bad()),
+        }
+    }
+}
+
+impl fmt::Display for Jitter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Jitter::None => write!(f, "0"),
+            Jitter::Uniform { ms } => write!(f, "uniform:{}", ms),
+            Jitter::Normal { ms } => write!(f, "normal:{}", ms),
+            Jitter::Pareto { ms } => write!(f, "pareto:{}", ms),
+        }
+    }
+}
+
+/// Random loss on the link, before any server sees the packet
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "kind")]
+pub enum Loss {
+    #[default]
+    None,
+    // Every packet independently
+    Bernoulli { p: f64 },
+    // Two states like netem's gemodel: p is good→bad, r is bad→good, and each state loses with its own
+    // probability. Losses come in bursts of about 1/r packets
+    GilbertElliott {
+        p: f64,
+        r: f64,
+        #[serde(default = "default_loss_bad")]
+        loss_bad: f64,
+        #[serde(default)]
+        loss_good: f64,
+    },
+}
+
+fn default_loss_bad() -> f64 {
+    1.0
+}
+
+/// `0.01` (Bernoulli), `bernoulli:0.01` or `ge:p,r[,loss_bad[,loss_good]]`
+impl FromStr for Loss {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, args) = s.split_once(':').unwrap_or(("bernoulli", s));
+        let bad = || anyhow::anyhow!("Bad loss: {} (try 0.01, bernoulli:0.01 or ge:0.01,0.3)", s);
+
+        match kind.to_lowercase().as_str() {
+            "bernoulli" => {
+                let p: f64 = args.trim().parse().map_err(|_| bad())?;
+                Ok(if p == 0.0 { Loss::None } else { Loss::Bernoulli { p } })
+            }
+            "ge" | "gilbert-elliott" | "gemodel" => {
+                let values = args.split(',')
+                    .map(|v| v.trim().parse::<f64>().map_err(|_| bad()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                match values[..] {
+                    [p, r] => Ok(Loss::GilbertElliott { p, r, loss_bad: default_loss_bad(), loss_good: 0.0 }),
+                    [p, r, loss_bad] => Ok(Loss::GilbertElliott { p, r, loss_bad, loss_good: 0.0 }),
+                    [p, r, loss_bad, loss_good] => Ok(Loss::GilbertElliott { p, r, loss_bad, loss_good }),
+                    _ => Err(bad()),
+                }
+            }
+            _ => Err(bad()),
+        }
+    }
+}
+
+impl fmt::Display for Loss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Loss::None => write!(f, "0"),
+            Loss::Bernoulli { p } => write!(f, "bernoulli:{}", p),
+            Loss::GilbertElliott { p, r, loss_bad, loss_good } => write!(f, "ge:{},{},{},{}", p, r, loss_bad, loss_good),
+        }
+    }
+}
+
+/// What every agent→server link does to packets. All off by default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkConfig {
+    // One-way propagation delay
+    #[serde(default)]
+    pub latency_ms: f64,
+    #[serde(default)]
+    pub jitter: Jitter,
+    #[serde(default)]
+    pub loss: Loss,
+    // Chance a packet skips the delay and overtakes the ones still underway, like netem's reorder
+    #[serde(default)]
+    pub reorder: f64,
+    // Chance a packet arrives twice, the copy gets a delay of its own
+    #[serde(default)]
+    pub duplicate: f64,
+}
+
+impl NetworkConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let probability = |name: &str, p: f64| {
+            anyhow::ensure!((0.0..=1.0).contains(&p), "network.{} must be in [0, 1], got {}", name, p);
+            Ok(())
+        };
+
+        anyhow::ensure!(self.latency_ms >= 0.0 && self.latency_ms.is_finite(), "network.latency_ms must be >= 0");
+        anyhow::ensure!(self.jitter.ms() >= 0.0 && self.jitter.ms().is_finite(), "network.jitter must be >= 0");
+        probability("reorder", self.reorder)?;
+        probability("duplicate", self.duplicate)?;
+        // Overtaking needs something to overtake
+        anyhow::ensure!(self.reorder == 0.0 || self.latency_ms > 0.0, "network.reorder needs latency_ms > 0");
+
+        match self.loss {
+            Loss::None => {}
+            Loss::Bernoulli { p } => probability("loss.p", p)?,
+            Loss::GilbertElliott { p, r, loss_bad, loss_good } => {
+                probability("loss.p", p)?;
+                probability("loss.r", r)?;
+                probability("loss.loss_bad", loss_bad)?;
+                probability("loss.loss_good", loss_good)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// False when every link is a perfect wire and packets can skip the emulation entirely
+    pub fn is_active(&self) -> bool {
+        *self != NetworkConfig::default()
+    }
+}
+
+impl fmt::Display for NetworkConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ms delay, jitter {}, loss {}, reorder {}, duplicate {}",
+            self.latency_ms, self.jitter, self.loss, self.reorder, self.duplicate
+        )
+    }
+}
+
+/// What one packet went through on its way
+#[derive(Debug, Clone, PartialEq)]
+pub enum Transit {
+    Lost,
+    Arrives {
+        delay: Duration,
+        // Skipped the delay
+        reordered: bool,
+        // Delay of the second copy, if there is one
+        duplicate: Option<Duration>,
+    },
+}
+
+/// State of one agent→server link
+#[derive(Debug)]
+pub struct Link {
+    config: NetworkConfig,
+    rng: StdRng,
+    // Gilbert-Elliott state
+    bad: bool,
+}
+
+impl Link {
+    pub fn new(config: NetworkConfig, seed: u64) -> Self {
+        Self {
+            config,
+            rng: StdRng::seed_from_u64(seed),
+            bad: false,
+        }
+    }
+
+    pub fn transmit(&mut self) -> Transit {
+        if self.lose() {
+            return Transit::Lost;
+        }
+
+        let duplicated = self.config.duplicate > 0.0 && self.rng.r#gen::<f64>() < self.config.duplicate;
+        let reordered = self.config.reorder > 0.0 && self.rng.r#gen::<f64>() < self.config.reorder;
+        let delay = if reordered { Duration::ZERO } else { self.delay() };
+
+        Transit::Arrives {
+            delay,
+            reordered,
+            duplicate: duplicated.then(|| self.delay()),
+        }
+    }
+
+    fn lose(&mut self) -> bool {
+        match self.config.loss {
+            Loss::None => false,
+            Loss::Bernoulli { p } => self.rng.r#gen::<f64>() < p,
+            Loss::GilbertElliott { p, r, loss_bad, loss_good } => {
+                // Move first, then lose with the new state's probability
+                let flip = if self.bad { r } else { p };
+                if self.rng.r#gen::<f64>() < flip {
+                    self.bad = !self.bad;
+                }
+                let loss = if self.bad { loss_bad } else { loss_good };
+                self.rng.r#gen::<f64>() < loss
+            }
+        }
+    }
+
+    fn delay(&mut self) -> Duration {
+        let ms = self.config.latency_ms + self.config.jitter.sample_ms(&mut self.rng);
+        Duration::from_secs_f64(ms.max(0.0) / 1000.0)
+    }
+}
@@ -1,20 +1,13 @@
 pub mod packet;
+pub mod codec;
+pub mod feedback;
+pub mod link;
+pub mod sequence;
+pub mod transport;
 
-pub use packet::{Packet, PacketId, Priority};
-
-#[derive(Debug, Clone)]
-pub struct NetworkConfig {
-    pub bandwidth_bps: u64,
-    pub latency_ms: u64,
-    pub buffer_size: usize,
-}
-
-impl Default for NetworkConfig {
-    fn default() -> Self {
-        Self {
-            bandwidth_bps: 100_000_000,
-            latency_ms: 5,
-            buffer_size: 1024,
-        }
-    }
-}
+pub use packet::{Ecn, FlowKey, Packet, PacketId, Priority, Protocol};
+pub use codec::{CodecError, FrameDecoder};
+pub use feedback::{Feedback, Outcome};
+pub use link::{Jitter, Link, Loss, NetworkConfig, Transit};
+pub use sequence::{Arrival, SequenceTracker};
+pub use transport::{Address, Transport};
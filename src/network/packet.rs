@@ -1,6 +1,9 @@
 use serde::{Deserialize, Serialize};
 use wincode_derive::{SchemaRead, SchemaWrite};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::simulation::{clock, seed};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, SchemaWrite, SchemaRead)]
 pub struct PacketId(u64);
@@ -11,6 +14,35 @@ impl PacketId {
     }
 }
 
+/// What kind of sender a flow is, the protocol field of the 5-tuple
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, SchemaWrite, SchemaRead)]
+#[serde(rename_all = "kebab-case")]
+pub enum Protocol {
+    // Open loop, sends on a schedule whatever happens to its packets
+    #[default]
+    Datagram = 0,
+    // Closed loop, a congestion window decides when it sends
+    Windowed = 1,
+}
+
+/// Which flow a packet belongs to, FlockNet's take on the 5-tuple. Flow queuing hashes this
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, SchemaWrite, SchemaRead)]
+pub struct FlowKey {
+    pub agent: u32,
+    // Per agent, 0 for the first (or only) flow
+    pub stream: u16,
+    pub destination: u32,
+    pub protocol: Protocol,
+}
+
+impl FlowKey {
+    /// Spread over the u64 range, `perturbation` reshuffles which flows share a bucket
+    pub fn hash(&self, perturbation: u64) -> u64 {
+        let packed = ((self.agent as u64) << 32) | ((self.stream as u64) << 16) | self.protocol as u64;
+        seed::mix(seed::mix(packed ^ perturbation) ^ self.destination as u64)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, SchemaWrite, SchemaRead)]
 pub struct Packet {
     pub id: PacketId,
@@ -18,8 +50,12 @@ pub struct Packet {
     pub destination_server: u32,
     pub payload_size: u32,
     pub priority: Priority,
+    pub ecn: Ecn,
+    pub flow: FlowKey,
+    // Per agent and server, only when the run asked for sequence numbers
+    pub seq: Option<u64>,
     
-    // Store creation time as microseconds since UNIX_EPOCH
+    // Store creation time as microseconds since UNIX_EPOCH (or the virtual epoch in discrete-event runs)
     // This CAN be serialized and works across network boundaries (:
     created_at_micros: u128,
     
@@ -34,10 +70,7 @@ impl Packet {
         size: u32,
         priority: Priority,
     ) -> Self {
-        let created_at_micros = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_micros();
+        let created_at_micros = clock::unix_micros();
         
         if created_at_micros < 1_000_000_000_000_000 {
             eprintln!("WARNING: Packet created with invalid timestamp: {}", created_at_micros);
@@ -49,11 +82,51 @@ impl Packet {
             destination_server: dest,
             payload_size: size,
             priority,
+            ecn: Ecn::NotEct,
+            flow: FlowKey {
+                agent: source,
+                stream: 0,
+                destination: dest,
+                protocol: Protocol::Datagram,
+            },
+            seq: None,
             created_at_micros,
             data: vec![0; size as usize],
         }
     }
     
+    pub fn with_ecn(mut self, ecn: Ecn) -> Self {
+        self.ecn = ecn;
+        self
+    }
+    
+    /// Agent and destination are the packet's own, only the stream and protocol are up to the sender
+    pub fn with_flow(mut self, stream: u16, protocol: Protocol) -> Self {
+        self.flow.stream = stream;
+        self.flow.protocol = protocol;
+        self
+    }
+    
+    pub fn with_seq(mut self, seq: Option<u64>) -> Self {
+        self.seq = seq;
+        self
+    }
+    
+    /// Sets CE on an ECN-capable packet, returns false if the packet can't carry the mark
+    /// (not ECT) or already had it
+    pub fn mark_ce(&mut self) -> bool {
+        if self.ecn.is_ect() {
+            self.ecn = Ecn::Ce;
+            true
+        } else {
+            false
+        }
+    }
+    
+    pub fn created_at_micros(&self) -> u128 {
+        self.created_at_micros
+    }
+    
     pub fn sojourn_time(&self) -> Duration {  // Sojourn time = the total time a packet spends inside the system, cool term I learned
         // checks if created_at_micros is 0 or unreasonably small, 
         // it likely means that the packet wasn't initialized properly. return 0 to avoid ruining metrics
@@ -61,10 +134,7 @@ impl Packet {
             return Duration::ZERO;
         }
         
-        let now_micros = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_micros();
+        let now_micros = clock::unix_micros();
         
         let elapsed_micros = now_micros.saturating_sub(self.created_at_micros);
         
@@ -76,16 +146,70 @@ impl Packet {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, SchemaWrite, SchemaRead)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, SchemaWrite, SchemaRead)]
+#[serde(rename_all = "kebab-case")]
 pub enum Priority {
     Low = 0,
+    #[default]
     Normal = 1,
     High = 2,
     Critical = 3,
 }
 
-impl Default for Priority {
-    fn default() -> Self {
-        Self::Normal
+impl Priority {
+    /// Lowest first, the index of each is its value
+    pub const ALL: [Priority; 4] = [Priority::Low, Priority::Normal, Priority::High, Priority::Critical];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// `low`, `normal`, `high` or `critical`, or the number 0-3
+impl FromStr for Priority {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "low" | "0" => Ok(Priority::Low),
+            "normal" | "1" => Ok(Priority::Normal),
+            "high" | "2" => Ok(Priority::High),
+            "critical" | "3" => Ok(Priority::Critical),
+            _ => anyhow::bail!("Unknown priority: {} (use low, normal, high or critical)", s),
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // pad so tables can line these up
+        f.pad(match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+            Priority::Critical => "critical",
+        })
     }
-}
\ No newline at end of file
+}
+
+// ECN codepoints as in RFC 3168, values match the two bits in the IP header
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, SchemaWrite, SchemaRead)]
+pub enum Ecn {
+    #[default]
+    NotEct = 0,
+    Ect1 = 1,
+    Ect0 = 2,
+    Ce = 3,
+}
+
+impl Ecn {
+    /// ECN-capable transport, the strategy may mark instead of drop
+    pub fn is_ect(self) -> bool {
+        matches!(self, Ecn::Ect0 | Ecn::Ect1)
+    }
+    
+    /// ECT(1) or CE, what an L4S queue classifies on (RFC 9331). CE could have been either, it goes with L4S
+    pub fn is_l4s(self) -> bool {
+        matches!(self, Ecn::Ect1 | Ecn::Ce)
+    }
+}
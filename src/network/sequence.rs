@@ -0,0 +1,36 @@
+// Receiver side of the optional per-flow sequence numbers. Every agent numbers the packets it sends to
+// each server 0, 1, 2, ... so a server can tell what got lost or reordered on the way, before any strategy saw it.
+
+/// What a sequence number says about the way there
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arrival {
+    InOrder,
+    // This many packets before this one are missing, maybe only late
+    Gap(u64),
+    // Older than one already seen, it was counted in a Gap before. Duplicates end up here too
+    Late,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SequenceTracker {
+    next: u64,
+}
+
+impl SequenceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn observe(&mut self, seq: u64) -> Arrival {
+        if seq < self.next {
+            return Arrival::Late;
+        }
+        let missing = seq - self.next;
+        self.next = seq + 1;
+        if missing == 0 {
+            Arrival::InOrder
+        } else {
+            Arrival::Gap(missing)
+        }
+    }
+}
@@ -0,0 +1,128 @@
+// In-process transport: packets move through bounded tokio channels as they are, no codec, no sockets.
+// Same shape as TCP (a connection per agent and server, feedback on its own way back) so the server
+// and agents can't tell the difference, only the kernel is missing from the latency.
+
+use super::PacketSink;
+use crate::network::feedback::FeedbackSender;
+use crate::network::{Feedback, Packet};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::mpsc;
+
+// Packets in flight per connection before the agent has to wait, roughly what a loopback socket buffer holds. Magic number
+const CONNECTION_CAPACITY: usize = 256;
+
+// Only for telling addresses apart in logs
+static NEXT_LISTENER: AtomicU64 = AtomicU64::new(0);
+
+/// What an agent hands the listener to connect
+pub struct Incoming {
+    packets: mpsc::Receiver<Packet>,
+    feedback: FeedbackSender,
+}
+
+pub struct Listener {
+    id: u64,
+    connect_tx: mpsc::UnboundedSender<Incoming>,
+    connect_rx: mpsc::UnboundedReceiver<Incoming>,
+}
+
+impl Listener {
+    pub fn new() -> Self {
+        let (connect_tx, connect_rx) = mpsc::unbounded_channel();
+        Self {
+            id: NEXT_LISTENER.fetch_add(1, Ordering::Relaxed),
+            connect_tx,
+            connect_rx,
+        }
+    }
+
+    pub fn address(&self) -> Address {
+        Address {
+            id: self.id,
+            connect_tx: self.connect_tx.clone(),
+        }
+    }
+
+    pub async fn accept(&mut self) -> Option<Incoming> {
+        self.connect_rx.recv().await
+    }
+}
+
+impl Default for Listener {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone)]
+pub struct Address {
+    id: u64,
+    connect_tx: mpsc::UnboundedSender<Incoming>,
+}
+
+impl Address {
+    pub fn connect<F>(&self, on_feedback: F) -> anyhow::Result<Sender>
+    where
+        F: Fn(&Feedback) + Send + 'static,
+    {
+        let (packet_tx, packets) = mpsc::channel(CONNECTION_CAPACITY);
+        let (feedback, mut feedback_rx) = mpsc::unbounded_channel();
+        self.connect_tx
+            .send(Incoming { packets, feedback })
+            .map_err(|_| anyhow::anyhow!("{} is not listening anymore", self))?;
+
+        // Ends when the server drops its side
+        tokio::spawn(async move {
+            while let Some(feedback) = feedback_rx.recv().await {
+                on_feedback(&feedback);
+            }
+        });
+
+        Ok(Sender { packets: packet_tx })
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "channel:{}", self.id)
+    }
+}
+
+impl fmt::Debug for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Address(channel:{})", self.id)
+    }
+}
+
+pub struct Sender {
+    packets: mpsc::Sender<Packet>,
+}
+
+impl Sender {
+    /// Waits while the connection is full, like a write to a full socket would
+    pub async fn send(&mut self, packet: Packet) -> anyhow::Result<()> {
+        self.packets
+            .send(packet)
+            .await
+            .map_err(|_| anyhow::anyhow!("Connection closed by server"))
+    }
+}
+
+pub async fn serve(conn: Incoming, sink: &impl PacketSink) -> anyhow::Result<()> {
+    let Incoming { mut packets, feedback } = conn;
+    let mut source_agent = None;
+
+    while let Some(packet) = packets.recv().await {
+        if source_agent.is_none() {
+            source_agent = Some(packet.source_agent);
+            sink.connected(packet.source_agent, feedback.clone());
+        }
+        sink.deliver(packet);
+    }
+
+    if let Some(agent_id) = source_agent {
+        sink.disconnected(agent_id, &feedback);
+    }
+    Ok(())
+}
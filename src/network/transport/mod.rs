@@ -0,0 +1,196 @@
+// How packets get from an agent to a server (and feedback back) in realtime runs.
+// Every transport has the same three pieces: a Listener the server accepts agents on, an Address the
+// agents dial, and a Sender for the packets. What arrives is handed to a PacketSink, which is the server.
+
+pub mod channel;
+pub mod tcp;
+pub mod udp;
+
+use super::{Feedback, Packet};
+use super::feedback::FeedbackSender;
+use crate::simulation::config::ListenConfig;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::net::tcp::OwnedWriteHalf;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Transport {
+    /// Real sockets, latency includes the kernel
+    #[default]
+    Tcp,
+    /// A datagram per packet, losses in the kernel are real losses
+    Udp,
+    /// Bounded tokio channels, no serialization or syscalls. For big swarms
+    Channel,
+}
+
+impl FromStr for Transport {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "tcp" => Ok(Transport::Tcp),
+            "udp" => Ok(Transport::Udp),
+            "channel" | "in-process" | "mem" => Ok(Transport::Channel),
+            _ => anyhow::bail!("Unknown transport: {} (use tcp, udp or channel)", s),
+        }
+    }
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transport::Tcp => write!(f, "tcp"),
+            Transport::Udp => write!(f, "udp"),
+            Transport::Channel => write!(f, "channel"),
+        }
+    }
+}
+
+/// First free port of `listen.ports` on `listen.host`, `bind` is the socket type's own bind
+async fn bind_first<T, F, Fut>(listen: &ListenConfig, bind: F) -> Result<T>
+where
+    F: Fn((String, u16)) -> Fut,
+    Fut: Future<Output = std::io::Result<T>>,
+{
+    for port in listen.ports.first..=listen.ports.last {
+        match bind((listen.host.clone(), port)).await {
+            Ok(socket) => return Ok(socket),
+            // Someone else has it (maybe another run of ours), try the next one
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => continue,
+            Err(e) => anyhow::bail!("Binding {}:{}: {}", listen.host, port, e),
+        }
+    }
+    anyhow::bail!("No free port in {} on {}", listen.ports, listen.host)
+}
+
+/// Where incoming packets go, the server implements this
+pub trait PacketSink: Send + Sync {
+    fn deliver(&self, packet: Packet);
+    /// First packet of an agent on a new connection, feedback for it goes to `feedback` from now on
+    fn connected(&self, agent_id: u32, feedback: FeedbackSender);
+    /// The connection `feedback` belonged to is gone
+    fn disconnected(&self, agent_id: u32, feedback: &FeedbackSender);
+    /// Bytes that didn't decode into a packet
+    fn malformed(&self);
+}
+
+/// A server's end, bound and ready. Agents can connect as soon as this exists
+pub enum Listener {
+    Tcp(tokio::net::TcpListener),
+    // Handed out by the first accept, a UDP server has one "connection" for everyone
+    Udp { socket: Option<UdpSocket>, addr: SocketAddr },
+    Channel(channel::Listener),
+}
+
+impl Listener {
+    pub async fn bind(transport: Transport, listen: &ListenConfig) -> Result<Self> {
+        Ok(match transport {
+            Transport::Tcp => Listener::Tcp(tcp::bind(listen).await?),
+            Transport::Udp => {
+                let socket = udp::bind(listen).await?;
+                let addr = socket.local_addr()?;
+                Listener::Udp { socket: Some(socket), addr }
+            }
+            Transport::Channel => Listener::Channel(channel::Listener::new()),
+        })
+    }
+
+    /// What agents should dial, the port the OS actually handed out for TCP
+    pub fn address(&self) -> Result<Address> {
+        Ok(match self {
+            Listener::Tcp(listener) => Address::Tcp(tcp::connect_addr(listener.local_addr()?)),
+            Listener::Udp { addr, .. } => Address::Udp(tcp::connect_addr(*addr)),
+            Listener::Channel(listener) => Address::Channel(listener.address()),
+        })
+    }
+
+    /// Next agent connection, None once nobody can connect anymore
+    pub async fn accept(&mut self) -> Option<Result<Incoming>> {
+        match self {
+            Listener::Tcp(listener) => Some(listener.accept().await.map(|(socket, _)| Incoming::Tcp(socket)).map_err(Into::into)),
+            Listener::Udp { socket, .. } => match socket.take() {
+                Some(socket) => Some(Ok(Incoming::Udp(socket))),
+                // Nothing more to accept, the server stops on its shutdown token
+                None => std::future::pending().await,
+            },
+            Listener::Channel(listener) => listener.accept().await.map(|conn| Ok(Incoming::Channel(conn))),
+        }
+    }
+}
+
+/// One accepted agent connection
+pub enum Incoming {
+    Tcp(TcpStream),
+    Udp(UdpSocket),
+    Channel(channel::Incoming),
+}
+
+impl Incoming {
+    /// Feeds everything that arrives into `sink` until the agent hangs up
+    pub async fn serve(self, sink: &impl PacketSink) -> Result<()> {
+        match self {
+            Incoming::Tcp(socket) => tcp::serve(socket, sink).await,
+            Incoming::Udp(socket) => udp::serve(socket, sink).await,
+            Incoming::Channel(conn) => channel::serve(conn, sink).await,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Address {
+    Tcp(SocketAddr),
+    Udp(SocketAddr),
+    Channel(channel::Address),
+    // Discrete-event runs, the event loop hands packets over itself and there's nothing to dial
+    Virtual(u32),
+}
+
+impl Address {
+    /// Opens a connection, feedback coming back over it is passed to `on_feedback`
+    pub async fn connect<F>(&self, on_feedback: F) -> Result<Sender>
+    where
+        F: Fn(&Feedback) + Send + 'static,
+    {
+        match self {
+            Address::Tcp(addr) => Ok(Sender::Tcp(tcp::connect(*addr, on_feedback).await?)),
+            Address::Udp(addr) => Ok(Sender::Udp(udp::connect(*addr, on_feedback).await?)),
+            Address::Channel(addr) => Ok(Sender::Channel(addr.connect(on_feedback)?)),
+            Address::Virtual(id) => anyhow::bail!("virtual:{} is driven by the event loop, it can't be dialed", id),
+        }
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Address::Tcp(addr) => write!(f, "{}", addr),
+            Address::Udp(addr) => write!(f, "udp://{}", addr),
+            Address::Channel(addr) => write!(f, "{}", addr),
+            Address::Virtual(id) => write!(f, "virtual:{}", id),
+        }
+    }
+}
+
+/// An agent's end of a connection
+pub enum Sender {
+    Tcp(OwnedWriteHalf),
+    Udp(udp::Sender),
+    Channel(channel::Sender),
+}
+
+impl Sender {
+    pub async fn send(&mut self, packet: Packet) -> Result<()> {
+        match self {
+            Sender::Tcp(writer) => tcp::send(writer, &packet).await,
+            Sender::Udp(sender) => sender.send(&packet).await,
+            Sender::Channel(sender) => sender.send(packet).await,
+        }
+    }
+}
@@ -0,0 +1,115 @@
+// One TCP connection per agent and server, packets one way and feedback the other, both as codec frames.
+
+use super::PacketSink;
+use crate::network::{codec, Feedback, FrameDecoder, Packet};
+use crate::simulation::config::ListenConfig;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tracing::warn;
+
+/// Once this returns the socket is listening, agents can connect right away even before anyone accepts them
+pub async fn bind(listen: &ListenConfig) -> anyhow::Result<TcpListener> {
+    super::bind_first(listen, |addr| async move { TcpListener::bind(addr).await }).await
+}
+
+/// Where agents should connect to reach a listener bound to `addr`, a wildcard bind isn't something to dial
+pub(super) fn connect_addr(addr: SocketAddr) -> SocketAddr {
+    match addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => SocketAddr::new(Ipv4Addr::LOCALHOST.into(), addr.port()),
+        IpAddr::V6(ip) if ip.is_unspecified() => SocketAddr::new(Ipv6Addr::LOCALHOST.into(), addr.port()),
+        _ => addr,
+    }
+}
+
+pub async fn connect<F>(addr: SocketAddr, on_feedback: F) -> anyhow::Result<OwnedWriteHalf>
+where
+    F: Fn(&Feedback) + Send + 'static,
+{
+    let (mut reader, writer) = TcpStream::connect(addr).await?.into_split();
+
+    // Feedback comes back over the same connection, ends when the server closes it
+    tokio::spawn(async move {
+        let mut buf = vec![0u8; 1024];
+        let mut decoder = FrameDecoder::<Feedback>::new();
+
+        while let Ok(n) = reader.read(&mut buf).await {
+            if n == 0 {
+                break;
+            }
+            decoder.extend(&buf[..n]);
+            while let Some(frame) = decoder.decode_next() {
+                if let Ok(feedback) = frame {
+                    on_feedback(&feedback);
+                }
+            }
+        }
+    });
+
+    Ok(writer)
+}
+
+pub async fn send(writer: &mut OwnedWriteHalf, packet: &Packet) -> anyhow::Result<()> {
+    let data = codec::encode_frame(packet)?;
+    writer.write_all(&data).await?;
+    Ok(())
+}
+
+pub async fn serve(socket: TcpStream, sink: &impl PacketSink) -> anyhow::Result<()> {
+    let (mut reader, mut writer) = socket.into_split();
+
+    // Return path, echoes for the agent on this connection go back over the same socket
+    let (feedback_tx, mut feedback_rx) = mpsc::unbounded_channel::<Feedback>();
+    let writer_task = tokio::spawn(async move {
+        while let Some(feedback) = feedback_rx.recv().await {
+            let Ok(frame) = codec::encode_frame(&feedback) else { continue };
+            if writer.write_all(&frame).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut source_agent = None;
+    let mut buf = vec![0u8; 4096];
+    let mut decoder = FrameDecoder::<Packet>::new();
+
+    let result: anyhow::Result<()> = async {
+        loop {
+            let n = reader.read(&mut buf).await?;
+            if n == 0 {
+                // Connection closed halfway through a frame
+                if decoder.buffered() > 0 {
+                    sink.malformed();
+                }
+                return Ok(());
+            }
+
+            decoder.extend(&buf[..n]);
+
+            while let Some(frame) = decoder.decode_next() {
+                match frame {
+                    Ok(packet) => {
+                        if source_agent.is_none() {
+                            source_agent = Some(packet.source_agent);
+                            sink.connected(packet.source_agent, feedback_tx.clone());
+                        }
+                        sink.deliver(packet);
+                    }
+                    Err(e) => {
+                        warn!("Received malformed frame: {}", e);
+                        sink.malformed();
+                    }
+                }
+            }
+        }
+    }.await;
+
+    if let Some(agent_id) = source_agent {
+        sink.disconnected(agent_id, &feedback_tx);
+    }
+    writer_task.abort();
+
+    result
+}
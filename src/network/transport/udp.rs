@@ -0,0 +1,118 @@
+// One datagram per packet, no connections, no retransmission. What the kernel drops when a socket buffer
+// runs full is simply gone, turn on sequence numbers to see how much that was.
+// Feedback goes back the same way, one datagram per echo to whatever address the packet came from.
+
+use super::PacketSink;
+use crate::network::feedback::FeedbackSender;
+use crate::network::{codec, Feedback, Packet};
+use crate::simulation::config::ListenConfig;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio_util::sync::{CancellationToken, DropGuard};
+use tracing::{debug, warn};
+
+// Largest UDP payload over IPv4, bigger packets can't be sent as one datagram
+const MAX_DATAGRAM: usize = 65_507;
+
+pub async fn bind(listen: &ListenConfig) -> anyhow::Result<UdpSocket> {
+    super::bind_first(listen, |addr| async move { UdpSocket::bind(addr).await }).await
+}
+
+pub async fn connect<F>(addr: SocketAddr, on_feedback: F) -> anyhow::Result<Sender>
+where
+    F: Fn(&Feedback) + Send + 'static,
+{
+    let local: SocketAddr = if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }.parse()?;
+    let socket = UdpSocket::bind(local).await?;
+    // Only so send() works and datagrams from anyone else are filtered out, nothing is set up
+    socket.connect(addr).await?;
+    let socket = Arc::new(socket);
+
+    // No end of stream in UDP, the reader stops when the Sender is dropped
+    let cancel = CancellationToken::new();
+    let reader = socket.clone();
+    let stop = cancel.clone();
+    tokio::spawn(async move {
+        let mut buf = vec![0u8; MAX_DATAGRAM];
+        loop {
+            tokio::select! {
+                received = reader.recv(&mut buf) => match received {
+                    Ok(n) => {
+                        if let Ok(feedback) = codec::decode_frame::<Feedback>(&buf[..n]) {
+                            on_feedback(&feedback);
+                        }
+                    }
+                    // ICMP port unreachable, the server is gone
+                    Err(_) => break,
+                },
+                _ = stop.cancelled() => break,
+            }
+        }
+    });
+
+    Ok(Sender {
+        socket,
+        _reader: cancel.drop_guard(),
+    })
+}
+
+pub struct Sender {
+    socket: Arc<UdpSocket>,
+    _reader: DropGuard,
+}
+
+impl Sender {
+    pub async fn send(&mut self, packet: &Packet) -> anyhow::Result<()> {
+        let frame = codec::encode_frame(packet)?;
+        anyhow::ensure!(frame.len() <= MAX_DATAGRAM, "Packet of {} bytes doesn't fit in a datagram", frame.len());
+        self.socket.send(&frame).await?;
+        Ok(())
+    }
+}
+
+/// Every datagram on the server's one socket, the sending address stands in for the connection
+pub async fn serve(socket: UdpSocket, sink: &impl PacketSink) -> anyhow::Result<()> {
+    let socket = Arc::new(socket);
+    let mut peers: HashMap<SocketAddr, FeedbackSender> = HashMap::new();
+    let mut writers = JoinSet::new();
+    let mut buf = vec![0u8; MAX_DATAGRAM];
+
+    loop {
+        let (n, peer) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            // Linux reports ICMP errors for earlier sends here, an agent that went away. Not fatal
+            Err(e) => {
+                debug!("UDP receive error: {}", e);
+                continue;
+            }
+        };
+
+        match codec::decode_frame::<Packet>(&buf[..n]) {
+            Ok(packet) => {
+                if let Entry::Vacant(slot) = peers.entry(peer) {
+                    let (feedback_tx, mut feedback_rx) = mpsc::unbounded_channel::<Feedback>();
+                    let socket = socket.clone();
+                    writers.spawn(async move {
+                        while let Some(feedback) = feedback_rx.recv().await {
+                            let Ok(frame) = codec::encode_frame(&feedback) else { continue };
+                            // Lost feedback is lost, same as on a real network
+                            let _ = socket.send_to(&frame, peer).await;
+                        }
+                    });
+                    sink.connected(packet.source_agent, feedback_tx.clone());
+                    slot.insert(feedback_tx);
+                }
+                sink.deliver(packet);
+            }
+            Err(e) => {
+                warn!("Received malformed datagram from {}: {}", peer, e);
+                sink.malformed();
+            }
+        }
+    }
+}
@@ -1,213 +1,390 @@
 // A lot of debug prints due to issues I had developing
 
-use crate::network::Packet;
-use crate::strategies::Strategy;
+use crate::network::{Arrival, Feedback, Link, NetworkConfig, Packet, SequenceTracker, Transit};
+use crate::network::feedback::FeedbackSender;
+use crate::network::transport::{Listener, PacketSink};
+use crate::strategies::{QueueEvents, Strategy};
 use crate::metrics::MetricsCollector;
-use tokio::net::TcpListener;
-use std::collections::VecDeque;
+use crate::simulation::clock;
+use crate::simulation::seed::{self, Stream};
+use crate::agent::size::DEFAULT_PACKET_SIZE;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use parking_lot::Mutex;
 use tracing::{info, warn, debug};
 
+/// Bookkeeping for the transmit loop, kept outside the server so the discrete-event backend can drive it too
+#[derive(Debug, Default)]
+pub(crate) struct TransmitState {
+    recent_sojourn_times: Vec<f64>,
+    update_counter: u32,
+    packets_processed: u64,
+    // What a forwarding server just put on its link, the event loop takes it from here
+    pub(crate) forwarded: Option<Packet>,
+}
+
+/// The emulated links from every agent to this server, each one created on its agent's first packet
+struct Links {
+    config: NetworkConfig,
+    // Root seed of the run, every link derives its own stream from it
+    seed: u64,
+    links: Mutex<HashMap<u32, Link>>,
+}
+
 pub struct Server {
     id: u32,
-    addr: String,
-    buffer: Arc<Mutex<VecDeque<Packet>>>,
+    // The strategy owns the queue
     strategy: Arc<Mutex<Box<dyn Strategy>>>,
     metrics: MetricsCollector,
     bandwidth_bps: u64,
+    feedback_routes: Mutex<HashMap<u32, FeedbackSender>>,
+    // Per agent, only packets with sequence numbers end up here
+    sequences: Mutex<HashMap<u32, SequenceTracker>>,
+    // None when the links are perfect, packets then go straight into the queue
+    links: Option<Links>,
+    // A router port in a topology: sent packets go on to the next hop instead of counting as received
+    forwards: bool,
+    // Realtime packets still underway on their link, the delay line in run() takes the receiver
+    underway_tx: mpsc::UnboundedSender<(Instant, Packet)>,
+    underway_rx: Mutex<Option<mpsc::UnboundedReceiver<(Instant, Packet)>>>,
 }
 
 impl Server {
     pub fn new(
         id: u32,
-        addr: String,
-        strategy: Box<dyn Strategy>,
+        mut strategy: Box<dyn Strategy>,
         metrics: MetricsCollector,
         bandwidth_bps: u64,
     ) -> Self {
+        strategy.set_link_rate(bandwidth_bps);
+        let (underway_tx, underway_rx) = mpsc::unbounded_channel();
         Self {
             id,
-            addr,
-            buffer: Arc::new(Mutex::new(VecDeque::new())),
             strategy: Arc::new(Mutex::new(strategy)),
             metrics,
             bandwidth_bps,
+            feedback_routes: Mutex::new(HashMap::new()),
+            sequences: Mutex::new(HashMap::new()),
+            links: None,
+            forwards: false,
+            underway_tx,
+            underway_rx: Mutex::new(Some(underway_rx)),
         }
     }
 
-    pub async fn run(self: Arc<Self>) -> anyhow::Result<()> {
-        let listener = TcpListener::bind(&self.addr).await?;
-        info!("Server {} listening on {}", self.id, self.addr);
+    /// Router egress port, see TransmitState::forwarded
+    pub fn forwarding(mut self) -> Self {
+        self.forwards = true;
+        self
+    }
 
-        let processor = self.clone();
-        tokio::spawn(async move {
-            processor.process_queue().await;
+    /// Packets from agents cross an emulated link first, `seed` is the run's root seed
+    pub fn with_links(mut self, config: NetworkConfig, seed: u64) -> Self {
+        self.links = config.is_active().then(|| Links {
+            config,
+            seed,
+            links: Mutex::new(HashMap::new()),
         });
+        self
+    }
 
-        loop {
-            match listener.accept().await {
-                Ok((socket, addr)) => {
-                    debug!("Server {} accepted connection from {}", self.id, addr);
-                    let server = self.clone();
-                    tokio::spawn(async move {
-                        if let Err(e) = server.handle_connection(socket).await {
-                            warn!("Connection error: {}", e);
-                        }
-                    });
-                }
-                Err(e) => {
-                    warn!("Accept error: {}", e);
-                }
-            }
+    /// Where per-packet feedback for `agent_id` should go
+    pub fn register_feedback(&self, agent_id: u32, sender: FeedbackSender) {
+        self.feedback_routes.lock().insert(agent_id, sender);
+    }
+
+    fn send_feedback(&self, packet: &Packet, dropped: bool) {
+        if let Some(sender) = self.feedback_routes.lock().get(&packet.source_agent) {
+            // Agent already gone, nothing to tell
+            let _ = sender.send(Feedback::for_packet(packet, self.id, dropped));
         }
     }
 
-    pub async fn run_with_counter(
-        self: Arc<Self>,
-        ready_counter: Arc<std::sync::atomic::AtomicU32>,
-    ) -> anyhow::Result<()> {
-        let listener = TcpListener::bind(&self.addr).await?;
-        info!("Server {} listening on {}", self.id, self.addr);
+    fn drop_packet(&self, packet: &Packet) {
+        self.metrics.packet_dropped(self.id, packet);
+        self.send_feedback(packet, true);
+    }
 
-        ready_counter.fetch_add(1, std::sync::atomic::Ordering::Release);
+    /// Serves `listener` until `shutdown` fires. Returns once every connection and the
+    /// transmit loop have stopped, the port is free again by then
+    pub async fn run(self: Arc<Self>, mut listener: Listener, shutdown: CancellationToken) -> anyhow::Result<()> {
+        info!("Server {} listening on {}", self.id, listener.address()?);
 
+        let mut tasks = JoinSet::new();
         let processor = self.clone();
-        tokio::spawn(async move {
+        tasks.spawn(async move {
             processor.process_queue().await;
         });
+        if let Some(underway) = self.underway_rx.lock().take() {
+            let server = self.clone();
+            tasks.spawn(async move {
+                server.delay_line(underway).await;
+            });
+        }
 
         loop {
-            match listener.accept().await {
-                Ok((socket, addr)) => {
-                    debug!("Server {} accepted connection from {}", self.id, addr);
-                    let server = self.clone();
-                    tokio::spawn(async move {
-                        if let Err(e) = server.handle_connection(socket).await {
-                            warn!("Connection error: {}", e);
-                        }
-                    });
-                }
-                Err(e) => {
-                    warn!("Accept error: {}", e);
-                }
+            tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Some(Ok(incoming)) => {
+                        debug!("Server {} accepted a connection", self.id);
+                        let server = self.clone();
+                        tasks.spawn(async move {
+                            if let Err(e) = incoming.serve(&*server).await {
+                                warn!("Server {} connection error: {}", server.id, e);
+                            }
+                        });
+                    }
+                    Some(Err(e)) => {
+                        warn!("Accept error: {}", e);
+                    }
+                    None => break,
+                },
+                // Finished connections, otherwise the set only ever grows
+                Some(_) = tasks.join_next() => {}
+                _ = shutdown.cancelled() => break,
             }
         }
+
+        drop(listener);
+        tasks.shutdown().await;
+        info!("Server {} shut down", self.id);
+        Ok(())
     }
 
-    async fn handle_connection(&self, mut socket: tokio::net::TcpStream) -> anyhow::Result<()> {
-        use tokio::io::AsyncReadExt;
+    /// Puts `packet` on the link from its agent. Every copy that makes it across comes back
+    /// with how long it is underway, lost packets don't come back at all
+    pub(crate) fn transit(&self, packet: Packet) -> Vec<(Duration, Packet)> {
+        let Some(links) = &self.links else {
+            return vec![(Duration::ZERO, packet)];
+        };
 
-        let mut buf = vec![0u8; 4096];
+        let agent = packet.source_agent;
+        let transit = links.links.lock()
+            .entry(agent)
+            .or_insert_with(|| Link::new(links.config, seed::derive(links.seed, Stream::Link { agent, server: self.id })))
+            .transmit();
+        self.metrics.link_transit(self.id, &transit);
 
-        loop {
-            let n = socket.read(&mut buf).await?;
-            if n == 0 {
-                break;
+        match transit {
+            Transit::Lost => Vec::new(),
+            Transit::Arrives { delay, duplicate: None, .. } => vec![(delay, packet)],
+            Transit::Arrives { delay, duplicate: Some(copy_delay), .. } => {
+                // No sequence number on the copy, it would only look like a late packet
+                let copy = packet.clone().with_seq(None);
+                vec![(delay, packet), (copy_delay, copy)]
             }
+        }
+    }
 
-            if let Ok(packet) = wincode::deserialize::<Packet>(&buf[..n]) {
-                self.enqueue_packet(packet);
+    /// Holds realtime packets until their link delay is over. Every packet has its own delay
+    /// (jitter, reordering), so this is a heap and not a FIFO
+    async fn delay_line(&self, mut incoming: mpsc::UnboundedReceiver<(Instant, Packet)>) {
+        let mut due: BinaryHeap<Reverse<(Instant, u64)>> = BinaryHeap::new();
+        let mut underway: HashMap<u64, Packet> = HashMap::new();
+        let mut next_id = 0u64;
+
+        loop {
+            let next_due = due.peek().map(|Reverse((at, _))| *at);
+            tokio::select! {
+                received = incoming.recv() => {
+                    let Some((at, packet)) = received else { break };
+                    due.push(Reverse((at, next_id)));
+                    underway.insert(next_id, packet);
+                    next_id += 1;
+                }
+                _ = tokio::time::sleep_until(next_due.unwrap_or_else(clock::now).into()), if next_due.is_some() => {
+                    let now = clock::now();
+                    while let Some(&Reverse((at, id))) = due.peek() {
+                        if at > now {
+                            break;
+                        }
+                        due.pop();
+                        if let Some(packet) = underway.remove(&id) {
+                            self.enqueue_packet(packet);
+                        }
+                    }
+                }
             }
         }
-
-        Ok(())
     }
 
-    fn enqueue_packet(&self, packet: Packet) {
-        let mut buffer = self.buffer.lock();
-        let mut strategy = self.strategy.lock();
+    pub(crate) fn enqueue_packet(&self, packet: Packet) {
+        // Sinks count sequence gaps in a topology, a port in the middle only sees some of the packets
+        if let Some(seq) = packet.seq
+            && !self.forwards
+        {
+            self.track_sequence(packet.source_agent, seq);
+        }
+        
+        let mut events = QueueEvents::new();
+        let (queue_len, queue_bytes) = {
+            let mut strategy = self.strategy.lock();
+            strategy.enqueue(packet, &mut events);
+            (strategy.len(), strategy.bytes())
+        };
 
-        let action = strategy.on_enqueue(&packet, buffer.len());
+        self.settle(events);
+        self.metrics.record_queue_length(self.id, queue_len, queue_bytes);
+    }
 
-        match action {
-            crate::strategies::Action::Accept => {
-                buffer.push_back(packet.clone());
-            }
-            crate::strategies::Action::Drop => {
-                self.metrics.packet_dropped();
-            }
-            crate::strategies::Action::Mark => {
-                buffer.push_back(packet.clone());
-            }
+    fn track_sequence(&self, agent_id: u32, seq: u64) {
+        let arrival = self.sequences.lock().entry(agent_id).or_default().observe(seq);
+        match arrival {
+            Arrival::InOrder => {}
+            Arrival::Gap(missing) => self.metrics.transit_lost(self.id, missing),
+            Arrival::Late => self.metrics.transit_late(self.id),
         }
+    }
 
-        self.metrics.record_queue_length(buffer.len());
+    /// Counts what the strategy dropped or marked and tells the senders about the drops
+    fn settle(&self, events: QueueEvents) {
+        for _ in 0..events.marked {
+            self.metrics.packet_marked(self.id);
+        }
+        for packet in &events.dropped {
+            self.drop_packet(packet);
+        }
     }
 
-    async fn process_queue(&self) {
-        let packet_time = std::time::Duration::from_secs_f64(
-            (1500.0 * 8.0) / self.bandwidth_bps as f64
-        );
+    pub fn id(&self) -> u32 {
+        self.id
+    }
 
-        info!("Server {} packet transmission time: {:?}", self.id, packet_time);
+    /// Time it takes to put `bytes` on the wire at the configured bandwidth
+    pub fn transmission_time(&self, bytes: u32) -> Duration {
+        Duration::from_secs_f64(
+            (bytes as f64 * 8.0) / self.bandwidth_bps as f64
+        )
+    }
+
+    /// How often an idle server looks at its queue again, one full-size packet
+    pub fn idle_poll(&self) -> Duration {
+        self.transmission_time(DEFAULT_PACKET_SIZE)
+    }
+
+    async fn process_queue(&self) {
+        info!("Server {} full-size packet transmission time: {:?}", self.id, self.idle_poll());
 
-        let mut recent_sojourn_times: Vec<f64> = Vec::new(); 
-        let mut update_counter = 0;
-        let mut packets_processed = 0u64;
+        let mut state = TransmitState::default();
+        let mut busy = self.idle_poll();
 
         loop {
-            tokio::time::sleep(packet_time).await;
+            tokio::time::sleep(busy).await;
+            busy = self.service_tick(&mut state);
+        }
+    }
 
-            let packet_opt = {
-                let mut buffer = self.buffer.lock();
-                buffer.pop_front()
-            };
+    /// One transmission slot: send the head of the queue (if any) and periodically update the strategy.
+    /// Returns how long until the next slot, the time the sent packet occupies the wire.
+    /// The real-time loop sleeps that long, the discrete-event backend schedules the next tick.
+    pub(crate) fn service_tick(&self, state: &mut TransmitState) -> Duration {
+        // Strategies may drop or mark heads on their way out (CoDel does)
+        let mut events = QueueEvents::new();
+        let (packet_opt, queues) = {
+            let mut strategy = self.strategy.lock();
+            (strategy.dequeue(&mut events), strategy.queue_stats())
+        };
+        self.settle(events);
+        if !queues.is_empty() {
+            self.metrics.record_queue_stats(self.id, queues);
+        }
 
-            if let Some(packet) = packet_opt {
-                let sojourn = packet.sojourn_time();
-                let sojourn_ms = sojourn.as_secs_f64() * 1000.0;
-                
-                packets_processed += 1;
-                
-                // Print first 10 packets just to verify timing
-                if packets_processed <= 10 {
-                    debug!("Server {} packet #{}: sojourn_time = {:.6}ms", 
-                          self.id, packets_processed, sojourn_ms);
-                }
-                
-                self.metrics.packet_received(sojourn);
-                
-                // Warn about impossible values (>30 seconds, magic number)
-                if sojourn_ms > 30_000.0 {
-                    warn!("Server {} DEQUEUE: Impossibly high sojourn time {:.2}ms for packet {:?}", 
-                          self.id, sojourn_ms, packet.id);
-                }
-                
-                recent_sojourn_times.push(sojourn_ms);
-                
-                if recent_sojourn_times.len() > 100 {
-                    recent_sojourn_times.remove(0);
-                }
+        let busy = packet_opt.as_ref()
+            .map(|packet| self.transmission_time(packet.payload_size))
+            .unwrap_or_else(|| self.idle_poll());
 
-                let queue_len = self.buffer.lock().len();
+        if let Some(packet) = packet_opt {
+            let sojourn = packet.sojourn_time();
+            let sojourn_ms = sojourn.as_secs_f64() * 1000.0;
+            
+            state.packets_processed += 1;
+            
+            // Print first 10 packets just to verify timing
+            if state.packets_processed <= 10 {
+                debug!("Server {} packet #{}: sojourn_time = {:.6}ms", 
+                      self.id, state.packets_processed, sojourn_ms);
+            }
+            
+            if self.forwards {
+                self.metrics.packet_forwarded(self.id, &packet);
+            } else {
+                self.metrics.packet_received(self.id, &packet, sojourn);
+                self.send_feedback(&packet, false);
+            }
+            
+            // Warn about impossible values (>30 seconds, magic number)
+            if sojourn_ms > 30_000.0 {
+                warn!("Server {} DEQUEUE: Impossibly high sojourn time {:.2}ms for packet {:?}", 
+                      self.id, sojourn_ms, packet.id);
+            }
+            
+            state.recent_sojourn_times.push(sojourn_ms);
+            
+            if state.recent_sojourn_times.len() > 100 {
+                state.recent_sojourn_times.remove(0);
+            }
+            
+            if self.forwards {
+                state.forwarded = Some(packet);
+            }
+        }
 
-                let mut strategy = self.strategy.lock();
-                strategy.on_dequeue(queue_len);
-                drop(strategy);
+        state.update_counter += 1;
+
+        if state.update_counter >= 3 {
+            state.update_counter = 0;
+            
+            let mut strategy = self.strategy.lock();
+            let queue_len = strategy.len();
+            
+            let avg_sojourn = if !state.recent_sojourn_times.is_empty() {
+                state.recent_sojourn_times.iter().sum::<f64>() / state.recent_sojourn_times.len() as f64
+            } else {
+                0.0
+            };
+            
+            if state.packets_processed.is_multiple_of(100) && state.packets_processed > 0 {
+                debug!("Server {} processed {} packets! Average sojourn: {:.2}ms, queue: {}", 
+                      self.id, state.packets_processed, avg_sojourn, queue_len);
             }
+            
+            strategy.update(avg_sojourn);
+        }
 
-            update_counter += 1;
-
-            if update_counter >= 3 {
-                update_counter = 0;
-                
-                let queue_len = self.buffer.lock().len();
-                
-                let avg_sojourn = if !recent_sojourn_times.is_empty() {
-                    recent_sojourn_times.iter().sum::<f64>() / recent_sojourn_times.len() as f64
-                } else {
-                    0.0
-                };
-                
-                if packets_processed % 100 == 0 && packets_processed > 0 {
-                    debug!("Server {} processed {} packets! Average sojourn: {:.2}ms, queue: {}", 
-                          self.id, packets_processed, avg_sojourn, queue_len);
-                }
-                
-                let mut strategy = self.strategy.lock();
-                strategy.update(queue_len, avg_sojourn);
+        busy
+    }
+}
+
+impl PacketSink for Server {
+    fn deliver(&self, packet: Packet) {
+        for (delay, packet) in self.transit(packet) {
+            if delay.is_zero() {
+                self.enqueue_packet(packet);
+            } else {
+                // Only fails once run() is gone, and with it the queue
+                let _ = self.underway_tx.send((clock::now() + delay, packet));
             }
         }
     }
-}
\ No newline at end of file
+
+    fn connected(&self, agent_id: u32, feedback: FeedbackSender) {
+        self.register_feedback(agent_id, feedback);
+    }
+
+    fn disconnected(&self, agent_id: u32, feedback: &FeedbackSender) {
+        let mut routes = self.feedback_routes.lock();
+        // The agent may have reconnected in the meantime, only remove our own route
+        if routes.get(&agent_id).is_some_and(|s| s.same_channel(feedback)) {
+            routes.remove(&agent_id);
+        }
+    }
+
+    fn malformed(&self) {
+        self.metrics.frame_malformed();
+    }
+}
@@ -0,0 +1,96 @@
+// Independent runs side by side, at most `jobs` at a time. Every run gets its own Simulation (so its own
+// metrics) on its own thread, the discrete-event clock is thread local so those really run in parallel.
+// Realtime runs don't get in each other's way either, servers take whatever port is free.
+
+use super::{SimConfig, Simulation};
+use crate::metrics::MetricsSnapshot;
+use anyhow::Result;
+use indicatif::{ProgressBar, ProgressStyle};
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Instant;
+use tokio::runtime::Handle;
+use tracing::{info, info_span};
+
+/// One core per run unless told otherwise
+pub fn default_jobs() -> usize {
+    std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+/// Runs every config and returns their snapshots in the same order. The first error stops
+/// new runs from starting, the ones already going finish first
+pub async fn run_all(configs: Vec<SimConfig>, jobs: usize) -> Result<Vec<Vec<MetricsSnapshot>>> {
+    let total = configs.len();
+    let jobs = jobs.clamp(1, total.max(1));
+
+    let pb = ProgressBar::new(configs.iter().map(|c| c.duration.as_secs()).sum());
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("[{elapsed_precise}] {bar:40.orange/yellow} {pos}/{len}s {msg}")?
+            .progress_chars("█▓░")
+    );
+
+    let queue = Arc::new(Mutex::new(configs.into_iter().enumerate()));
+    let results = Arc::new(Mutex::new((0..total).map(|_| None).collect::<Vec<_>>()));
+    let running = Arc::new(AtomicUsize::new(0));
+    let finished = Arc::new(AtomicUsize::new(0));
+    let failed = Arc::new(AtomicBool::new(false));
+    let started = Instant::now();
+    let message = move |running: usize, finished: usize| {
+        format!("{}/{} runs done, {} running", finished, total, running)
+    };
+    pb.set_message(message(0, 0));
+
+    let mut workers = tokio::task::JoinSet::new();
+    for _ in 0..jobs {
+        let queue = queue.clone();
+        let results = results.clone();
+        let running = running.clone();
+        let finished = finished.clone();
+        let failed = failed.clone();
+        let pb = pb.clone();
+        let handle = Handle::current();
+
+        workers.spawn_blocking(move || -> Result<()> {
+            while !failed.load(Ordering::Acquire) {
+                let Some((index, config)) = queue.lock().next() else {
+                    break;
+                };
+
+                let name = config.name.clone();
+                let span = info_span!("run", name = %name);
+                let _entered = span.enter();
+
+                pb.set_message(message(running.fetch_add(1, Ordering::AcqRel) + 1, finished.load(Ordering::Acquire)));
+                let mut sim = Simulation::new(config).with_progress(pb.clone());
+                let outcome = handle.block_on(sim.run());
+                let now_running = running.fetch_sub(1, Ordering::AcqRel) - 1;
+
+                if let Err(e) = outcome {
+                    failed.store(true, Ordering::Release);
+                    return Err(e.context(format!("Run {} failed", name)));
+                }
+                results.lock()[index] = Some(sim.metrics.get_snapshots());
+                let done = finished.fetch_add(1, Ordering::AcqRel) + 1;
+                pb.set_message(message(now_running, done));
+                info!("Finished {}/{} after {:.1}s", done, total, started.elapsed().as_secs_f64());
+            }
+            Ok(())
+        });
+    }
+
+    let mut first_error = None;
+    while let Some(joined) = workers.join_next().await {
+        if let Err(e) = joined? {
+            first_error.get_or_insert(e);
+        }
+    }
+    pb.finish_with_message(message(0, finished.load(Ordering::Acquire)));
+    if let Some(e) = first_error {
+        return Err(e);
+    }
+
+    let results = std::mem::take(&mut *results.lock());
+    Ok(results.into_iter().map(|r| r.expect("every run finished")).collect())
+}
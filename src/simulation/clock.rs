@@ -0,0 +1,81 @@
+// Time source for everything that cares about "now": packets, strategies and metrics.
+// In real-time mode this is just the OS clock. The discrete-event backend installs a
+// virtual clock on its thread and moves it forward event by event, so the exact same
+// strategy code sees simulated time instead of wall time.
+
+use std::cell::Cell;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Virtual runs pretend to start at 2023-11-14, well past the sanity checks in Packet
+const VIRTUAL_EPOCH_MICROS: u128 = 1_700_000_000_000_000;
+
+#[derive(Debug, Clone, Copy)]
+struct VirtualTime {
+    base: Instant,
+    offset: Duration,
+}
+
+thread_local! {
+    static VIRTUAL: Cell<Option<VirtualTime>> = const { Cell::new(None) };
+}
+
+pub fn now() -> Instant {
+    VIRTUAL.with(|v| match v.get() {
+        Some(t) => t.base + t.offset,
+        None => Instant::now(),
+    })
+}
+
+pub fn elapsed(since: Instant) -> Duration {
+    now().saturating_duration_since(since)
+}
+
+/// Wall clock timestamp in microseconds, used to stamp packets
+pub fn unix_micros() -> u128 {
+    VIRTUAL.with(|v| match v.get() {
+        Some(t) => VIRTUAL_EPOCH_MICROS + t.offset.as_micros(),
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_micros(),
+    })
+}
+
+pub fn is_virtual() -> bool {
+    VIRTUAL.with(|v| v.get().is_some())
+}
+
+/// Switches the current thread over to simulated time until the guard is dropped
+pub struct VirtualClock {
+    _not_send: std::marker::PhantomData<*const ()>,
+}
+
+impl VirtualClock {
+    pub fn install() -> Self {
+        VIRTUAL.with(|v| v.set(Some(VirtualTime {
+            base: Instant::now(),
+            offset: Duration::ZERO,
+        })));
+        Self { _not_send: std::marker::PhantomData }
+    }
+
+    pub fn advance_to(&self, offset: Duration) {
+        VIRTUAL.with(|v| {
+            if let Some(mut t) = v.get() {
+                debug_assert!(offset >= t.offset, "virtual time can't go backwards");
+                t.offset = offset;
+                v.set(Some(t));
+            }
+        });
+    }
+
+    pub fn offset(&self) -> Duration {
+        VIRTUAL.with(|v| v.get().map(|t| t.offset).unwrap_or_default())
+    }
+}
+
+impl Drop for VirtualClock {
+    fn drop(&mut self) {
+        VIRTUAL.with(|v| v.set(None));
+    }
+}
@@ -1,18 +1,269 @@
 
-use crate::agent::TrafficPattern;
+use crate::agent::{PacketSize, PriorityMix, TrafficPattern};
+use super::topology::Topology;
+use crate::network::{NetworkConfig, Transport};
+use crate::strategies::StrategyRegistry;
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
 use std::time::Duration;
 
+/// Which engine executes the simulation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// Real loopback TCP sockets, runs in wall clock time
+    #[default]
+    Realtime,
+    /// Virtual clock and an event queue, runs as fast as the CPU allows
+    DiscreteEvent,
+}
+
+impl FromStr for Backend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "realtime" | "real-time" | "tcp" => Ok(Backend::Realtime),
+            "discrete" | "discrete-event" | "des" => Ok(Backend::DiscreteEvent),
+            _ => anyhow::bail!("Unknown backend: {} (use realtime or discrete)", s),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Backend::Realtime => write!(f, "realtime"),
+            Backend::DiscreteEvent => write!(f, "discrete-event"),
+        }
+    }
+}
+
+/// A slice of the agents with its own traffic. Groups take agent ids in order, the first `count` go to the first group
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgentGroup {
+    #[serde(default)]
+    pub name: String,
+    pub count: u32,
+    pub traffic_pattern: TrafficPattern,
+    // Falls back to the run-wide setting when left out
+    #[serde(default)]
+    pub packet_size: Option<PacketSize>,
+    #[serde(default)]
+    pub ecn: Option<bool>,
+    #[serde(default)]
+    pub priority: Option<PriorityMix>,
+    // More flows per agent on top of traffic_pattern, all sending at once
+    #[serde(default)]
+    pub flows: Vec<FlowSpec>,
+}
+
+/// One more flow an agent runs next to its traffic_pattern, e.g. a bulk transfer beside its telemetry.
+/// Each flow is its own stream with its own congestion window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FlowSpec {
+    pub traffic_pattern: TrafficPattern,
+    // The agent's (group's, then run-wide) setting when left out
+    #[serde(default)]
+    pub packet_size: Option<PacketSize>,
+    #[serde(default)]
+    pub priority: Option<PriorityMix>,
+}
+
+/// Where results go and what gets written besides the CSV/JSON
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    #[serde(default = "default_output_dir")]
+    pub dir: String,
+    // LaTeX exports after a comparison
+    #[serde(default)]
+    pub latex: bool,
+    // The per-run CSV/JSON/plot files, a sweep with hundreds of runs may only want its table
+    #[serde(default = "default_save_runs")]
+    pub save_runs: bool,
+}
+
+fn default_save_runs() -> bool {
+    true
+}
+
+fn default_output_dir() -> String {
+    "results".to_string()
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            dir: default_output_dir(),
+            latex: false,
+            save_runs: true,
+        }
+    }
+}
+
+/// Ports realtime servers may listen on, `6000-6100` or a single port. `0` lets the OS pick one per server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PortRange {
+    pub first: u16,
+    pub last: u16,
+}
+
+impl PortRange {
+    pub fn is_ephemeral(&self) -> bool {
+        self.first == 0
+    }
+
+    pub fn count(&self) -> u32 {
+        self.last as u32 - self.first as u32 + 1
+    }
+}
+
+impl FromStr for PortRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || anyhow::anyhow!("Bad port range: {} (use 0, 6000 or 6000-6100)", s);
+        let (first, last) = match s.split_once('-') {
+            Some((first, last)) => (first.trim().parse().map_err(|_| bad())?, last.trim().parse().map_err(|_| bad())?),
+            None => {
+                let port = s.trim().parse().map_err(|_| bad())?;
+                (port, port)
+            }
+        };
+        // 0 only works on its own, "0-100" would mean "any port, or one of these"
+        anyhow::ensure!(first <= last && (first > 0 || last == 0), bad());
+        Ok(Self { first, last })
+    }
+}
+
+impl TryFrom<String> for PortRange {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<PortRange> for String {
+    fn from(range: PortRange) -> Self {
+        range.to_string()
+    }
+}
+
+impl fmt::Display for PortRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.first == self.last {
+            write!(f, "{}", self.first)
+        } else {
+            write!(f, "{}-{}", self.first, self.last)
+        }
+    }
+}
+
+/// Where realtime servers listen. Each server takes the first free port in the range,
+/// the address it actually got is what the agents connect to
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ListenConfig {
+    // Interface to bind, 0.0.0.0 for all of them
+    #[serde(default = "default_listen_host")]
+    pub host: String,
+    #[serde(default)]
+    pub ports: PortRange,
+}
+
+fn default_listen_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+impl Default for ListenConfig {
+    fn default() -> Self {
+        Self {
+            host: default_listen_host(),
+            ports: PortRange::default(),
+        }
+    }
+}
+
+// Durations as plain (fractional) seconds, {secs, nanos} tables are no fun to write by hand
+mod secs {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(duration.as_secs_f64())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let secs = f64::deserialize(deserializer)?;
+        Duration::try_from_secs_f64(secs).map_err(serde::de::Error::custom)
+    }
+}
+
+// Unknown keys are an error, a typo in a scenario file shouldn't silently run the defaults
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct SimConfig {
     pub name: String,
+    // May be left out in a scenario with a [compare] section
+    #[serde(default)]
     pub strategy_name: String,
+    // Passed to the strategy factory, e.g. max_p = 0.2 for RED
+    #[serde(default)]
+    pub strategy_params: BTreeMap<String, f64>,
+    // Can be left out when there are groups, it's their sum then
+    #[serde(default)]
     pub num_agents: u32,
+    // Can be left out with a topology, its sinks are the servers then
+    #[serde(default)]
     pub num_servers: u32,
+    #[serde(with = "secs")]
     pub duration: Duration,
     pub buffer_size: usize,
     pub bandwidth_bps: u64,
     pub traffic_pattern: TrafficPattern,
+    // Empty means every agent uses traffic_pattern
+    #[serde(default)]
+    pub groups: Vec<AgentGroup>,
+    // Extra flows for agents outside any group
+    #[serde(default)]
+    pub flows: Vec<FlowSpec>,
+    #[serde(default)]
+    pub packet_size: PacketSize,
+    // Normal for everything unless a group says otherwise
+    #[serde(default)]
+    pub priority: PriorityMix,
+    #[serde(default)]
+    pub backend: Backend,
+    // Root of every RNG stream in the run, recorded in the analysis output
+    #[serde(default = "super::seed::random_seed")]
+    pub seed: u64,
+    // Agents send ECT(0) packets, strategies may mark them instead of dropping
+    #[serde(default)]
+    pub ecn: bool,
+    #[serde(default)]
+    pub output: OutputConfig,
+    // Realtime only, how packets travel between agents and servers
+    #[serde(default)]
+    pub transport: Transport,
+    // Agents number their packets per server, servers count what went missing or came out of order
+    #[serde(default)]
+    pub sequence_numbers: bool,
+    #[serde(default)]
+    pub listen: ListenConfig,
+    // Delay, jitter, loss, reordering and duplication on every agent→server link, both backends
+    #[serde(default)]
+    pub network: NetworkConfig,
+    // Routers, links and sinks between the agents and where their packets end up. Discrete-event only
+    #[serde(default)]
+    pub topology: Option<Topology>,
 }
 
 impl Default for SimConfig {
@@ -20,22 +271,145 @@ impl Default for SimConfig {
         Self {
             name: "default_sim".to_string(),
             strategy_name: "drop-tail".to_string(),
+            strategy_params: BTreeMap::new(),
             num_agents: 64,
             num_servers: 4,
             duration: Duration::from_secs(60),
             buffer_size: 1024,
             bandwidth_bps: 100_000_000,
             traffic_pattern: TrafficPattern::Constant { rate_pps: 100.0 },
+            groups: Vec::new(),
+            flows: Vec::new(),
+            packet_size: PacketSize::default(),
+            priority: PriorityMix::default(),
+            backend: Backend::default(),
+            seed: super::seed::random_seed(),
+            ecn: false,
+            output: OutputConfig::default(),
+            transport: Transport::default(),
+            sequence_numbers: false,
+            listen: ListenConfig::default(),
+            network: NetworkConfig::default(),
+            topology: None,
         }
     }
 }
 
 impl SimConfig {
+    /// Catches what would otherwise only fail halfway into a run (or not at all).
+    /// Fills in num_agents from the groups when it was left out
+    pub fn validate(&mut self) -> Result<()> {
+        if !self.groups.is_empty() {
+            let total: u32 = self.groups.iter().map(|g| g.count).sum();
+            if self.num_agents == 0 {
+                self.num_agents = total;
+            }
+            anyhow::ensure!(
+                self.num_agents == total,
+                "num_agents is {} but the groups add up to {}",
+                self.num_agents,
+                total
+            );
+        }
+        if let Some(topology) = &self.topology {
+            if self.num_agents == 0 {
+                self.num_agents = topology.agent_count();
+            }
+            if self.num_servers == 0 {
+                self.num_servers = topology.sinks.len() as u32;
+            }
+            anyhow::ensure!(
+                self.num_servers as usize == topology.sinks.len(),
+                "num_servers is {} but the topology has {} sinks",
+                self.num_servers,
+                topology.sinks.len()
+            );
+            anyhow::ensure!(
+                self.backend == Backend::DiscreteEvent,
+                "Topologies only run on the discrete-event backend"
+            );
+        }
+        // Streams are numbered in a u16, the first one is traffic_pattern
+        let most_flows = self.groups.iter().map(|g| g.flows.len()).chain([self.flows.len()]).max().unwrap_or(0);
+        anyhow::ensure!(most_flows < u16::MAX as usize, "At most {} flows per agent, got {}", u16::MAX - 1, most_flows);
+        self.traffic_pattern.validate("traffic_pattern")?;
+        for (i, flow) in self.flows.iter().enumerate() {
+            flow.traffic_pattern.validate(&format!("flows.{}.traffic_pattern", i))?;
+        }
+        for (g, group) in self.groups.iter().enumerate() {
+            group.traffic_pattern.validate(&format!("groups.{}.traffic_pattern", g))?;
+            for (i, flow) in group.flows.iter().enumerate() {
+                flow.traffic_pattern.validate(&format!("groups.{}.flows.{}.traffic_pattern", g, i))?;
+            }
+        }
+        anyhow::ensure!(self.num_agents > 0, "Need at least one agent");
+        anyhow::ensure!(self.num_servers > 0, "Need at least one server");
+        anyhow::ensure!(self.buffer_size > 0, "buffer_size must be at least 1");
+        anyhow::ensure!(self.bandwidth_bps > 0, "bandwidth_bps must be positive");
+        anyhow::ensure!(self.duration >= Duration::from_secs(1), "duration must be at least 1 second");
+        anyhow::ensure!(!self.listen.host.is_empty(), "listen.host is empty");
+        anyhow::ensure!(
+            self.listen.ports.is_ephemeral() || self.listen.ports.count() >= self.num_servers,
+            "listen.ports {} has room for {} servers, need {}",
+            self.listen.ports,
+            self.listen.ports.count(),
+            self.num_servers
+        );
+        self.network.validate()?;
+        match &self.topology {
+            // Checks the strategy of every router, the run's own only if some router uses it
+            Some(topology) => {
+                topology.plan(self)?;
+            }
+            None => {
+                StrategyRegistry::global().create_with(&self.strategy_name, self.buffer_size, &self.strategy_params)?;
+            }
+        }
+        Ok(())
+    }
+    
+    /// The group agent `id` belongs to, None without groups
+    pub fn group_of(&self, id: u32) -> Option<&AgentGroup> {
+        let mut first = 0;
+        for group in &self.groups {
+            if id < first + group.count {
+                return Some(group);
+            }
+            first += group.count;
+        }
+        None
+    }
+    
     pub fn with_strategy(mut self, strategy: impl Into<String>) -> Self {
         self.strategy_name = strategy.into();
         self
     }
     
+    pub fn with_packet_size(mut self, packet_size: PacketSize) -> Self {
+        self.packet_size = packet_size;
+        self
+    }
+    
+    pub fn with_priority(mut self, priority: PriorityMix) -> Self {
+        self.priority = priority;
+        self
+    }
+    
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+    
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+    
+    pub fn with_ecn(mut self, ecn: bool) -> Self {
+        self.ecn = ecn;
+        self
+    }
+    
     pub fn with_peak_traffic(mut self, base: f64, peak: f64, duration_s: f64) -> Self {
         self.traffic_pattern = TrafficPattern::PeakTraffic {
             base_rate: base,
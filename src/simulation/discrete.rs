@@ -0,0 +1,200 @@
+// Discrete-event backend. Same agents, servers and strategies as the real-time backend,
+// but instead of sockets and sleeps everything hangs off one event queue and a virtual clock.
+// A 256 second run takes however long the CPU needs to chew through the events.
+
+use super::clock::VirtualClock;
+use super::topology::{Next, Routes};
+use crate::agent::{Agent, TrafficSchedule};
+use crate::network::Feedback;
+use crate::metrics::{MetricsCollector, MetricsSnapshot};
+use crate::server::{Server, TransmitState};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Event {
+    // A flow's next departure, by slot (see run)
+    AgentSend(usize),
+    ServerTick(usize),
+    // A packet at the end of its link, (server, key into the packets underway)
+    Arrival(usize, u64),
+    // Same for a topology's sinks, (sink, key)
+    SinkArrival(usize, u64),
+    Snapshot,
+}
+
+/// Min-heap on (time, insertion order), so events at the same instant fire in the order they were scheduled
+#[derive(Debug, Default)]
+struct EventQueue {
+    heap: BinaryHeap<Reverse<(Duration, u64, Event)>>,
+    seq: u64,
+}
+
+impl EventQueue {
+    fn schedule(&mut self, at: Duration, event: Event) {
+        self.heap.push(Reverse((at, self.seq, event)));
+        self.seq += 1;
+    }
+
+    fn pop(&mut self) -> Option<(Duration, Event)> {
+        self.heap.pop().map(|Reverse((at, _, event))| (at, event))
+    }
+}
+
+/// Runs until `duration` of virtual time has passed. `clock` must be installed on this thread
+/// before the agents, servers and metrics are created so they all share the same time base.
+pub(crate) fn run(
+    clock: &VirtualClock,
+    duration: Duration,
+    agents: &[Arc<Agent>],
+    servers: &[Arc<Server>],
+    routes: &mut Routes,
+    metrics: &MetricsCollector,
+    mut on_snapshot: impl FnMut(&MetricsSnapshot),
+) {
+    let mut events = EventQueue::default();
+    // Every flow of every agent is a slot, (agent, flow). Agents with one flow have slot == agent id
+    let slots: Vec<(usize, usize)> = agents.iter()
+        .enumerate()
+        .flat_map(|(i, agent)| (0..agent.flow_count()).map(move |f| (i, f)))
+        .collect();
+    let first_slot: Vec<usize> = agents.iter()
+        .scan(0, |next, agent| {
+            let first = *next;
+            *next += agent.flow_count();
+            Some(first)
+        })
+        .collect();
+    let mut schedules: Vec<TrafficSchedule> = slots.iter().map(|&(i, f)| agents[i].schedule(f)).collect();
+    let mut transmit: Vec<TransmitState> = servers.iter().map(|_| TransmitState::default()).collect();
+    // When each slot's next AgentSend is due. A window flow woken by feedback gets a new send
+    // scheduled, the timeout that was queued before goes stale and is skipped when it pops
+    let mut next_send: Vec<Duration> = vec![Duration::ZERO; slots.len()];
+    let mut blocked: Vec<bool> = vec![false; slots.len()];
+    // Packets on an emulated link or a topology link, waiting for their Arrival
+    let mut underway = HashMap::new();
+    let mut next_underway = 0u64;
+
+    // One shared return path, echoes are handed to the agent right after the tick that produced them
+    let (feedback_tx, mut feedback_rx) = tokio::sync::mpsc::unbounded_channel();
+    for server in servers {
+        for agent in agents {
+            server.register_feedback(agent.id(), feedback_tx.clone());
+        }
+    }
+
+    let start = clock.offset();
+
+    for (slot, schedule) in schedules.iter_mut().enumerate() {
+        next_send[slot] = start + schedule.next_gap(Duration::ZERO);
+        events.schedule(next_send[slot], Event::AgentSend(slot));
+    }
+    for (i, server) in servers.iter().enumerate() {
+        events.schedule(start + server.idle_poll(), Event::ServerTick(i));
+    }
+    events.schedule(start + SNAPSHOT_INTERVAL, Event::Snapshot);
+
+    let end = start + duration;
+
+    while let Some((at, event)) = events.pop() {
+        if at > end {
+            break;
+        }
+        clock.advance_to(at);
+
+        match event {
+            Event::AgentSend(slot) => {
+                if at != next_send[slot] {
+                    continue;
+                }
+                let (i, flow) = slots[slot];
+
+                if let Some(wait) = agents[i].window_wait(flow) {
+                    blocked[slot] = true;
+                    next_send[slot] = at + wait.max(Duration::from_nanos(1));
+                    events.schedule(next_send[slot], Event::AgentSend(slot));
+                    continue;
+                }
+                blocked[slot] = false;
+
+                let (_, packet) = agents[i].next_packet(flow);
+                metrics.packet_sent(&packet.flow, packet.priority);
+                let server_idx = routes.entry(i, &packet);
+                for (delay, packet) in servers[server_idx].transit(packet) {
+                    if delay.is_zero() {
+                        servers[server_idx].enqueue_packet(packet);
+                    } else {
+                        underway.insert(next_underway, packet);
+                        events.schedule(at + delay, Event::Arrival(server_idx, next_underway));
+                        next_underway += 1;
+                    }
+                }
+
+                // Never schedule at the same instant again, a zero gap would spin forever
+                let gap = agents[i].pace(flow, schedules[slot].next_gap(at - start)).max(Duration::from_nanos(1));
+                next_send[slot] = at + gap;
+                events.schedule(next_send[slot], Event::AgentSend(slot));
+            }
+            Event::ServerTick(i) => {
+                let busy = servers[i].service_tick(&mut transmit[i]);
+                // A router port: the packet is on the next link once it's fully transmitted, store and forward
+                if let Some(packet) = transmit[i].forwarded.take() {
+                    let (delay, next) = routes.next(i, &packet);
+                    underway.insert(next_underway, packet);
+                    let arrival = match next {
+                        Next::Port(port) => Event::Arrival(port, next_underway),
+                        Next::Sink(sink) => Event::SinkArrival(sink, next_underway),
+                    };
+                    events.schedule(at + busy + delay, arrival);
+                    next_underway += 1;
+                }
+                hand_back(&mut feedback_rx, agents, &first_slot, &mut blocked, &mut next_send, &mut events, at);
+                events.schedule(at + busy.max(Duration::from_nanos(1)), Event::ServerTick(i));
+            }
+            Event::Arrival(i, key) => {
+                if let Some(packet) = underway.remove(&key) {
+                    servers[i].enqueue_packet(packet);
+                }
+            }
+            Event::SinkArrival(sink, key) => {
+                if let Some(packet) = underway.remove(&key) {
+                    routes.deliver(sink, packet, metrics, &feedback_tx);
+                    hand_back(&mut feedback_rx, agents, &first_slot, &mut blocked, &mut next_send, &mut events, at);
+                }
+            }
+            Event::Snapshot => {
+                let snapshot = metrics.save_snapshot();
+                on_snapshot(&snapshot);
+                events.schedule(at + SNAPSHOT_INTERVAL, Event::Snapshot);
+            }
+        }
+    }
+}
+
+/// Gives every pending echo to its agent, a window flow that was blocked gets to send again right away
+fn hand_back(
+    feedback_rx: &mut tokio::sync::mpsc::UnboundedReceiver<Feedback>,
+    agents: &[Arc<Agent>],
+    first_slot: &[usize],
+    blocked: &mut [bool],
+    next_send: &mut [Duration],
+    events: &mut EventQueue,
+    at: Duration,
+) {
+    while let Ok(feedback) = feedback_rx.try_recv() {
+        let agent = feedback.source_agent as usize;
+        let flow = feedback.stream as usize;
+        agents[agent].on_feedback(&feedback);
+
+        let slot = first_slot[agent] + flow;
+        if blocked[slot] && agents[agent].window_wait(flow).is_none() {
+            blocked[slot] = false;
+            next_send[slot] = at;
+            events.schedule(at, Event::AgentSend(slot));
+        }
+    }
+}
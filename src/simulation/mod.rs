@@ -1,23 +1,48 @@
+pub mod batch;
 pub mod config;
-pub use config::SimConfig;
+pub mod clock;
+pub mod discrete;
+pub mod scenario;
+pub mod seed;
+pub mod sweep;
+pub mod topology;
+pub use config::{AgentGroup, Backend, FlowSpec, ListenConfig, OutputConfig, PortRange, SimConfig};
+pub use scenario::Scenario;
+pub use sweep::SweepSpec;
+pub use topology::Topology;
 
-use crate::agent::{Agent, TrafficPattern};
+use crate::agent::{Agent, PrioritySampler, SizeSampler, TrafficPattern};
+use crate::network::Address;
+use crate::network::transport::Listener;
 use crate::server::Server;
-use crate::strategies::StrategyRegistry;
-use crate::metrics::{MetricsCollector, analyzer};
+use crate::strategies::StrategyBuilder;
+use crate::metrics::{MetricsCollector, MetricsSnapshot, analyzer};
 use crate::metrics::logger::MetricsLogger;
 use anyhow::Result;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::time::{interval, sleep};
-use tokio::sync::Notify;
+use clock::VirtualClock;
+use seed::Stream;
+use topology::Routes;
+use tokio::time::{interval_at, Instant};
 use tokio_util::sync::CancellationToken;
-use tracing::{info, error};
+use tracing::{debug, info, error};
 use indicatif::{ProgressBar, ProgressStyle};
 
+struct AgentSetup {
+    traffic_pattern: TrafficPattern,
+    packet_size: SizeSampler,
+    priority: PrioritySampler,
+    ecn: bool,
+    // Besides traffic_pattern
+    flows: Vec<(TrafficPattern, SizeSampler, PrioritySampler)>,
+}
+
 pub struct Simulation {
     config: SimConfig,
     pub metrics: MetricsCollector,
+    // Set when this run is one of a batch, the batch owns the bar and its message
+    progress: Option<ProgressBar>,
 }
 
 impl Simulation {
@@ -25,146 +50,333 @@ impl Simulation {
         Self {
             config,
             metrics: MetricsCollector::new(),
+            progress: None,
         }
     }
     
+    /// Ticks `pb` once per simulated second instead of drawing a bar of its own
+    pub fn with_progress(mut self, pb: ProgressBar) -> Self {
+        self.progress = Some(pb);
+        self
+    }
+    
     pub async fn run(&mut self) -> Result<()> {
+        self.config.validate()?;
+        
         info!("Starting simulation: {}", self.config.name);
         info!("Strategy: {}", self.config.strategy_name);
         info!("Duration: {:?}", self.config.duration);
         info!("Agents: {}, Servers: {}", self.config.num_agents, self.config.num_servers);
+        info!("Backend: {}", self.config.backend);
+        if self.config.backend == Backend::Realtime {
+            info!("Transport: {}", self.config.transport);
+        }
+        info!("Seed: {}", self.config.seed);
+        info!("Packet size: {}", self.config.packet_size);
+        info!("Priority: {}", self.config.priority);
+        for group in &self.config.groups {
+            let priority = group.priority.unwrap_or(self.config.priority);
+            info!("Group {}: {} agents, {:?}, priority {}", group.name, group.count, group.traffic_pattern, priority);
+            for flow in &group.flows {
+                info!("  Flow: {:?}, priority {}", flow.traffic_pattern, flow.priority.unwrap_or(priority));
+            }
+        }
+        for flow in &self.config.flows {
+            info!("Flow: {:?}, priority {}", flow.traffic_pattern, flow.priority.unwrap_or(self.config.priority));
+        }
+        if self.config.ecn {
+            info!("ECN: enabled");
+        }
+        if self.config.network.is_active() {
+            info!("Links: {}", self.config.network);
+        }
         
-        let cancel_token = CancellationToken::new(); // Create cancellation token for graceful shutdown later, prevents issues next run
-        
-        // Create notification system for server readiness
-        let ready_count = Arc::new(std::sync::atomic::AtomicU32::new(0));
-        let mut servers = Vec::new();
-        let mut server_handles = Vec::new();
-        
-        // Start servers w readiness notification
-        for i in 0..self.config.num_servers {
-            let addr = format!("127.0.0.1:{}", 5000 + i);
-            let strategy = StrategyRegistry::global()
-                .create(&self.config.strategy_name, self.config.buffer_size)
-                .ok_or_else(|| anyhow::anyhow!("Unknown strategy: {}", self.config.strategy_name))?;
-            
-            let server = Arc::new(Server::new(
-                i,
-                addr.clone(),
-                strategy,
-                self.metrics.clone(),
-                self.config.bandwidth_bps,
-            ));
-            
-            let server_clone = server.clone();
-            let ready_counter = ready_count.clone();
-            let cancel = cancel_token.clone();
-            
-            let handle = tokio::spawn(async move {
-                tokio::select! {
-                    result = server_clone.run_with_counter(ready_counter) => {
-                        if let Err(e) = result {
-                            error!("Server {} error: {}", i, e);
-                        }
-                    }
-                    _ = cancel.cancelled() => {
-                        info!("Server {} shutting down", i);
-                    }
-                }
-            });
-            
-            server_handles.push(handle);
-            servers.push(server);
+        match self.config.backend {
+            Backend::Realtime => self.run_realtime().await?,
+            Backend::DiscreteEvent => self.run_discrete()?,
         }
         
-        // Wait for ALL servers to be ready
-        info!("Waiting for servers to be ready...");
-        while ready_count.load(std::sync::atomic::Ordering::Acquire) < self.config.num_servers {
-            sleep(Duration::from_millis(10)).await;
+        if self.config.output.save_runs {
+            self.save_results()?;
         }
+        Ok(())
+    }
+    
+    async fn run_realtime(&mut self) -> Result<()> {
+        // Bad distribution files should fail before any server is listening
+        let setups = self.agent_setups()?;
+        // Servers and agents both stop on this, the handles below tell us when they actually have
+        let cancel_token = CancellationToken::new();
         
-        // Extra safety margin to ensure OS has fully bound ports, magic number
-        sleep(Duration::from_millis(100)).await;
-        info!("All servers ready!");
+        // All bound before anything is spawned, so running out of ports leaves nothing behind.
+        // A listening socket already queues connections, there's no need to wait for the servers after this
+        let mut listeners = Vec::new();
+        let mut server_addrs = Vec::new();
+        for _ in 0..self.config.num_servers {
+            let listener = Listener::bind(self.config.transport, &self.config.listen).await?;
+            server_addrs.push(listener.address()?);
+            listeners.push(listener);
+        }
         
-        let server_addrs: Vec<String> = (0..self.config.num_servers)
-            .map(|i| format!("127.0.0.1:{}", 5000 + i))
-            .collect();
+        let mut server_handles = Vec::new();
+        for (i, listener) in (0..).zip(listeners) {
+            let server = self.create_server(i)?;
+            let cancel = cancel_token.clone();
+            server_handles.push(tokio::spawn(async move {
+                if let Err(e) = server.run(listener, cancel).await {
+                    error!("Server {} error: {}", i, e);
+                }
+            }));
+        }
+        info!(
+            "Servers listening on {}",
+            server_addrs.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", ")
+        );
         
-        let mut agents = Vec::new();
         let mut agent_handles = Vec::new();
         
         for i in 0..self.config.num_agents {
-            let pattern = self.get_traffic_pattern(i);
-            let agent = Arc::new(Agent::new(
-                i,
-                server_addrs.clone(),
-                self.metrics.clone(),
-                pattern,
-            ));
-            
-            let agent_clone = agent.clone();
+            let agent = self.create_agent(i, server_addrs.clone(), &setups[i as usize]);
             let cancel = cancel_token.clone();
             
             let handle = tokio::spawn(async move {
                 tokio::select! {
-                    result = agent_clone.run() => {
+                    result = agent.run() => {
                         if let Err(e) = result {
                             error!("Agent {} error: {}", i, e);
                         }
                     }
                     _ = cancel.cancelled() => {
-                        info!("Agent {} shutting down", i);
+                        debug!("Agent {} shutting down", i);
                     }
                 }
             });
             
             agent_handles.push(handle);
-            agents.push(agent);
         }
         
-        let pb = ProgressBar::new(self.config.duration.as_secs());
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("[{elapsed_precise}] {bar:40.orange/yellow} {pos}/{len}s {msg}")? // god I should've begun using colours earlier
-                .progress_chars("█▓░")
-        );
+        let pb = self.progress_bar()?;
         
-        let mut tick = interval(Duration::from_secs(1));
+        // First snapshot after one full second, same as the discrete-event backend
+        let mut tick = interval_at(Instant::now() + Duration::from_secs(1), Duration::from_secs(1));
         for _ in 0..self.config.duration.as_secs() {
             tick.tick().await;
-            self.metrics.save_snapshot();
-            pb.inc(1);
-            
-            let snapshot = self.metrics.snapshot();
-            pb.set_message(format!(
-                "Loss: {:.2}% | Queue: {}",
-                snapshot.packet_loss_rate * 100.0,
-                snapshot.queue_length
-            ));
+            let snapshot = self.metrics.save_snapshot();
+            self.progress_tick(&pb, &snapshot);
         }
         
-        pb.finish_with_message("Simulation complete");
+        self.progress_finish(&pb);
         
         info!("Shutting down simulation..");
         cancel_token.cancel();
         
+        // Agents first so nothing is mid-send when the servers close their sockets
         for handle in agent_handles {
-            let _ = tokio::time::timeout(Duration::from_secs(2), handle).await;
+            handle.await?;
         }
         for handle in server_handles {
-            let _ = tokio::time::timeout(Duration::from_secs(2), handle).await;
+            handle.await?;
         }
+        info!("All servers and agents stopped");
+        
+        Ok(())
+    }
+    
+    /// Same agents, servers and strategies, driven by an event queue on a virtual clock.
+    /// Blocks the calling thread until the run is done.
+    fn run_discrete(&mut self) -> Result<()> {
+        let clock = VirtualClock::install();
+        
+        // Metrics have to start at virtual time zero, not when the Simulation was built
+        self.metrics = MetricsCollector::new();
+        
+        let setups = self.agent_setups()?;
+        let (servers, agents, mut routes) = match &self.config.topology {
+            Some(topology) => {
+                let plan = topology.plan(&self.config)?;
+                let ports = (0..).zip(&plan.ports)
+                    .map(|(i, port)| self.create_port(i, port))
+                    .collect::<Result<Vec<_>>>()?;
+                for (i, port) in plan.ports.iter().enumerate() {
+                    info!("Port {}: {} ({}, {} Mbps, {:?})", i, port.label, port.strategy, port.bandwidth_bps / 1_000_000, port.delay);
+                }
+                for (i, name) in plan.sink_names.iter().enumerate() {
+                    info!("Sink {}: {}", plan.ports.len() + i, name);
+                }
+                
+                // Virtual addresses are global sink indices, that's what the routes go by
+                let agents: Vec<Arc<Agent>> = (0..self.config.num_agents)
+                    .map(|i| {
+                        let addrs = plan.destinations(i).iter().map(|&s| Address::Virtual(s)).collect();
+                        self.create_agent(i, addrs, &setups[i as usize])
+                    })
+                    .collect();
+                (ports, agents, Routes::new(&plan))
+            }
+            None => {
+                let server_addrs: Vec<Address> = (0..self.config.num_servers)
+                    .map(Address::Virtual)
+                    .collect();
+                
+                let servers = (0..self.config.num_servers)
+                    .map(|i| self.create_server(i))
+                    .collect::<Result<Vec<_>>>()?;
+                
+                let agents: Vec<Arc<Agent>> = (0..self.config.num_agents)
+                    .map(|i| self.create_agent(i, server_addrs.clone(), &setups[i as usize]))
+                    .collect();
+                (servers, agents, Routes::direct())
+            }
+        };
+        
+        let pb = self.progress_bar()?;
         
-        // Give OS time to release ports, magic number
-        sleep(Duration::from_millis(500)).await;
+        discrete::run(&clock, self.config.duration, &agents, &servers, &mut routes, &self.metrics, |snapshot| {
+            self.progress_tick(&pb, snapshot);
+        });
         
-        self.save_results()?;
+        self.progress_finish(&pb);
         Ok(())
     }
     
-    fn get_traffic_pattern(&self, agent_id: u32) -> TrafficPattern {
-        match &self.config.traffic_pattern {
+    fn create_server(&self, id: u32) -> Result<Arc<Server>> {
+        let mut strategy = StrategyBuilder::new(&self.config.strategy_name)
+            .buffer_size(self.config.buffer_size)
+            .params(&self.config.strategy_params)
+            .build()?;
+        strategy.reseed(seed::derive(self.config.seed, Stream::Server(id)));
+        
+        Ok(Arc::new(Server::new(
+            id,
+            strategy,
+            self.metrics.clone(),
+            self.config.bandwidth_bps,
+        )
+        .with_links(self.config.network, self.config.seed)))
+    }
+    
+    /// A router's egress port in a topology, same as a server except that what it sends goes on to the next hop
+    fn create_port(&self, id: u32, port: &topology::Port) -> Result<Arc<Server>> {
+        let mut strategy = StrategyBuilder::new(&port.strategy)
+            .buffer_size(port.buffer_size)
+            .params(&port.strategy_params)
+            .build()?;
+        strategy.reseed(seed::derive(self.config.seed, Stream::Server(id)));
+        
+        // Link emulation only applies to the agents' first hop, where transit() is called
+        Ok(Arc::new(Server::new(id, strategy, self.metrics.clone(), port.bandwidth_bps)
+            .forwarding()
+            .with_links(self.config.network, self.config.seed)))
+    }
+    
+    /// Traffic, packet sizes, priorities and ECN for every agent id, from its group or the run-wide settings
+    fn agent_setups(&self) -> Result<Vec<AgentSetup>> {
+        let default_size = self.config.packet_size.sampler()?;
+        let default_priority = self.config.priority.sampler()?;
+        let group_sizes = self.config.groups.iter()
+            .map(|g| g.packet_size.as_ref().map(|p| p.sampler()).transpose())
+            .collect::<Result<Vec<_>>>()?;
+        let group_priorities = self.config.groups.iter()
+            .map(|g| g.priority.as_ref().map(|p| p.sampler()).transpose())
+            .collect::<Result<Vec<_>>>()?;
+        
+        let mut setups = Vec::with_capacity(self.config.num_agents as usize);
+        for ((group, size), priority) in self.config.groups.iter().zip(&group_sizes).zip(&group_priorities) {
+            let size = size.clone().unwrap_or_else(|| default_size.clone());
+            let priority = priority.unwrap_or(default_priority);
+            let flows = Self::flow_samplers(&group.flows, &size, priority)?;
+            for _ in 0..group.count {
+                let id = setups.len() as u32;
+                setups.push(AgentSetup {
+                    traffic_pattern: self.get_traffic_pattern(&group.traffic_pattern, id),
+                    packet_size: size.clone(),
+                    priority,
+                    ecn: group.ecn.unwrap_or(self.config.ecn),
+                    flows: flows.iter()
+                        .map(|(pattern, size, priority)| (self.get_traffic_pattern(pattern, id), size.clone(), *priority))
+                        .collect(),
+                });
+            }
+        }
+        // No groups (or fewer agents in them than num_agents), the rest gets the defaults
+        let flows = Self::flow_samplers(&self.config.flows, &default_size, default_priority)?;
+        while setups.len() < self.config.num_agents as usize {
+            let id = setups.len() as u32;
+            setups.push(AgentSetup {
+                traffic_pattern: self.get_traffic_pattern(&self.config.traffic_pattern, id),
+                packet_size: default_size.clone(),
+                priority: default_priority,
+                ecn: self.config.ecn,
+                flows: flows.iter()
+                    .map(|(pattern, size, priority)| (self.get_traffic_pattern(pattern, id), size.clone(), *priority))
+                    .collect(),
+            });
+        }
+        Ok(setups)
+    }
+    
+    /// Extra flows with their sizes and priorities resolved, what they leave out comes from the agent's own
+    fn flow_samplers(
+        flows: &[FlowSpec],
+        size: &SizeSampler,
+        priority: PrioritySampler,
+    ) -> Result<Vec<(TrafficPattern, SizeSampler, PrioritySampler)>> {
+        flows.iter()
+            .map(|flow| Ok((
+                flow.traffic_pattern.clone(),
+                flow.packet_size.as_ref().map(|p| p.sampler()).transpose()?.unwrap_or_else(|| size.clone()),
+                flow.priority.as_ref().map(|p| p.sampler()).transpose()?.unwrap_or(priority),
+            )))
+            .collect()
+    }
+    
+    fn create_agent(&self, id: u32, server_addrs: Vec<Address>, setup: &AgentSetup) -> Arc<Agent> {
+        let agent = Agent::new(
+            id,
+            server_addrs,
+            self.metrics.clone(),
+            setup.traffic_pattern.clone(),
+            seed::rng(self.config.seed, Stream::Agent(id)),
+        )
+        .with_ecn(setup.ecn)
+        .with_packet_size(setup.packet_size.clone())
+        .with_priority(setup.priority)
+        .with_sequence_numbers(self.config.sequence_numbers);
+        
+        let agent = setup.flows.iter().fold(agent, |agent, (pattern, size, priority)| {
+            agent.with_flow(pattern.clone(), size.clone(), *priority)
+        });
+        Arc::new(agent)
+    }
+    
+    fn progress_bar(&self) -> Result<ProgressBar> {
+        if let Some(pb) = &self.progress {
+            return Ok(pb.clone());
+        }
+        let pb = ProgressBar::new(self.config.duration.as_secs());
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template("[{elapsed_precise}] {bar:40.orange/yellow} {pos}/{len}s {msg}")? // god I should've begun using colours earlier
+                .progress_chars("█▓░")
+        );
+        Ok(pb)
+    }
+    
+    fn progress_tick(&self, pb: &ProgressBar, snapshot: &MetricsSnapshot) {
+        pb.inc(1);
+        if self.progress.is_none() {
+            pb.set_message(progress_message(snapshot));
+        }
+    }
+    
+    fn progress_finish(&self, pb: &ProgressBar) {
+        if self.progress.is_none() {
+            pb.finish_with_message("Simulation complete");
+        }
+    }
+    
+    fn get_traffic_pattern(&self, pattern: &TrafficPattern, agent_id: u32) -> TrafficPattern {
+        match pattern {
             TrafficPattern::PeakTraffic { base_rate, peak_rate, peak_duration_s } => {
                 let variance = 0.1;
                 let factor = 1.0 + (agent_id as f64 * 0.01) % variance - variance / 2.0;
@@ -182,30 +394,130 @@ impl Simulation {
         let snapshots = self.metrics.get_snapshots();
         let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
         
-        std::fs::create_dir_all("results")?;
+        let dir = &self.config.output.dir;
+        std::fs::create_dir_all(dir)?;
         
-        let csv_path = format!("results/{}_{}.csv", self.config.name, timestamp);
+        let csv_path = format!("{}/{}_{}.csv", dir, self.config.name, timestamp);
         let mut logger = MetricsLogger::new(&csv_path)?;
         logger.log_batch(&snapshots)?;
         info!("Results saved to: {}", csv_path);
         
-        let report = analyzer::analyze(
+        if snapshots.iter().any(|s| !s.agents.is_empty()) {
+            let agents_path = format!("{}/{}_{}_agents.csv", dir, self.config.name, timestamp);
+            MetricsLogger::new(&agents_path)?.log_agents(&snapshots)?;
+            info!("Per-agent results saved to: {}", agents_path);
+        }
+        
+        if snapshots.iter().any(|s| !s.servers.is_empty()) {
+            let servers_path = format!("{}/{}_{}_servers.csv", dir, self.config.name, timestamp);
+            MetricsLogger::new(&servers_path)?.log_servers(&snapshots)?;
+            info!("Per-server results saved to: {}", servers_path);
+        }
+        
+        if snapshots.iter().any(|s| s.servers.values().any(|server| !server.queues.is_empty())) {
+            let queues_path = format!("{}/{}_{}_queues.csv", dir, self.config.name, timestamp);
+            MetricsLogger::new(&queues_path)?.log_queues(&snapshots)?;
+            info!("Per-queue results saved to: {}", queues_path);
+        }
+        
+        let mut report = analyzer::analyze(
             &snapshots,
             &self.config.strategy_name
         );
+        report.seed = Some(self.config.seed);
         
-        let json_path = format!("results/{}_{}_analysis.json", self.config.name, timestamp);
+        let json_path = format!("{}/{}_{}_analysis.json", dir, self.config.name, timestamp);
         std::fs::write(&json_path, serde_json::to_string_pretty(&report)?)?;
         info!("Analysis saved to: {}", json_path);
         
-        let plot_data_path = format!("results/{}_{}_plot.dat", self.config.name, timestamp);
+        let plot_data_path = format!("{}/{}_{}_plot.dat", dir, self.config.name, timestamp);
         analyzer::export_latex_plot_data(&snapshots, &plot_data_path)?;
         info!("Plot data saved to: {}", plot_data_path);
         
-        info!("Avg Throughput: {:.2} Mbps", report.avg_throughput_mbps);
+        info!("Avg Throughput: {:.2} Mbps (peak {:.2} Mbps)", report.avg_throughput_mbps, report.peak_throughput_mbps);
         info!("Avg Latency: {:.2} ms", report.avg_latency_ms);
+        info!(
+            "Latency p50/p90/p99/p99.9/max: {:.2}/{:.2}/{:.2}/{:.2}/{:.2} ms",
+            report.latency_p50_ms,
+            report.latency_p90_ms,
+            report.latency_p99_ms,
+            report.latency_p999_ms,
+            report.latency_max_ms
+        );
+        info!("Jitter: {:.2} ms", report.jitter_ms);
+        info!("Avg RTT: {:.2} ms", report.avg_rtt_ms);
         info!("Packet Loss: {:.2}%", report.packet_loss_rate * 100.0);
+        if report.packets_marked > 0 {
+            info!("ECN Marks: {} ({:.2}%)", report.packets_marked, report.mark_rate * 100.0);
+        }
+        if report.frames_malformed > 0 {
+            info!("Malformed Frames: {}", report.frames_malformed);
+        }
+        if report.send_errors > 0 {
+            info!("Send Errors: {}", report.send_errors);
+        }
+        if self.config.sequence_numbers {
+            info!("Lost in Transit: {}, Reordered: {}", report.packets_lost_in_transit, report.packets_reordered);
+        }
+        if self.config.network.is_active() {
+            info!(
+                "Link Lost: {}, Duplicated: {}, Reordered: {}",
+                report.link_lost, report.link_duplicated, report.link_reordered
+            );
+        }
+        if report.flows > 1 {
+            info!("Flow Fairness (Jain): {:.3} over {} flows", report.flow_fairness, report.flows);
+        }
+        for queue in &report.queues {
+            info!(
+                "  Queue {}: {} packets, avg sojourn {:.2} ms, {:.2}% marked, {} dropped",
+                queue.name,
+                queue.packets_dequeued,
+                queue.avg_sojourn_ms,
+                queue.mark_rate * 100.0,
+                queue.packets_dropped
+            );
+        }
+        if report.priorities.len() > 1 {
+            for class in &report.priorities {
+                info!(
+                    "  Priority {}: {:.2} Mbps, {:.2} ms (p99 {:.2} ms), {:.2}% loss",
+                    class.priority,
+                    class.avg_throughput_mbps,
+                    class.avg_latency_ms,
+                    class.latency_p99_ms,
+                    class.packet_loss_rate * 100.0
+                );
+            }
+        }
+        // Only interesting when the load can be uneven
+        if report.servers.len() > 1 {
+            for server in &report.servers {
+                info!(
+                    "  Server {}: {:.2} Mbps, {:.2} ms, {:.2}% loss, avg queue {:.1}",
+                    server.id,
+                    server.avg_throughput_mbps,
+                    server.avg_latency_ms,
+                    server.packet_loss_rate * 100.0,
+                    server.avg_queue_length
+                );
+            }
+        }
         
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+fn progress_message(snapshot: &MetricsSnapshot) -> String {
+    let mut message = format!(
+        "{:.1} Mbps | Loss: {:.2}% | Queue: {} ({} KB)",
+        snapshot.interval_throughput_bps / 1_000_000.0,
+        snapshot.packet_loss_rate * 100.0,
+        snapshot.queue_length,
+        snapshot.queue_bytes / 1024
+    );
+    if snapshot.avg_cwnd > 0.0 {
+        message.push_str(&format!(" | cwnd: {:.1}", snapshot.avg_cwnd));
+    }
+    message
+}
@@ -0,0 +1,91 @@
+// Scenario files: a whole experiment (or comparison) in one checked-in file instead of a shell history line.
+// TOML unless the extension says .json, see scenarios/ for examples.
+
+use super::SimConfig;
+use crate::strategies::StrategyRegistry;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub simulation: SimConfig,
+    // Present means run every strategy in the list on the same config, strategy_name is ignored then
+    #[serde(default)]
+    pub compare: Option<CompareConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CompareConfig {
+    pub strategies: Vec<String>,
+    #[serde(default = "default_repetitions")]
+    pub repetitions: u32,
+    // Runs at the same time, one per core when left out
+    #[serde(default)]
+    pub jobs: Option<usize>,
+    // Per strategy, [compare.params.red] max_p = 0.2
+    #[serde(default)]
+    pub params: BTreeMap<String, BTreeMap<String, f64>>,
+}
+
+fn default_repetitions() -> u32 {
+    3
+}
+
+impl Scenario {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Reading scenario {}", path.display()))?;
+
+        let mut scenario: Scenario = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::from_str(&content)
+                .with_context(|| format!("Parsing scenario {}", path.display()))?,
+            _ => toml::from_str(&content)
+                .with_context(|| format!("Parsing scenario {}", path.display()))?,
+        };
+
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    pub fn validate(&mut self) -> Result<()> {
+        if let Some(compare) = &mut self.compare {
+            let registry = StrategyRegistry::global();
+            anyhow::ensure!(!compare.strategies.is_empty(), "compare.strategies is empty");
+            anyhow::ensure!(compare.repetitions > 0, "compare.repetitions must be at least 1");
+            anyhow::ensure!(compare.jobs != Some(0), "compare.jobs must be at least 1");
+            anyhow::ensure!(
+                self.simulation.strategy_params.is_empty(),
+                "Use [compare.params.<strategy>] instead of simulation.strategy_params in a comparison"
+            );
+
+            // Keyed by registered name from here on, so `ared` and `adaptive-red` are the same thing
+            let mut params = BTreeMap::new();
+            for (name, values) in std::mem::take(&mut compare.params) {
+                let canonical = registry.canonical(&name)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown strategy in compare.params: {}", name))?;
+                anyhow::ensure!(
+                    compare.strategies.iter().any(|s| registry.canonical(s) == Some(canonical)),
+                    "compare.params.{0} but {0} isn't in compare.strategies",
+                    name
+                );
+                params.insert(canonical.to_string(), values);
+            }
+            compare.params = params;
+
+            for name in &compare.strategies {
+                let canonical = registry.canonical(name)
+                    .ok_or_else(|| anyhow::anyhow!("Unknown strategy in compare.strategies: {}", name))?;
+                let values = compare.params.get(canonical).cloned().unwrap_or_default();
+                registry.create_with(name, self.simulation.buffer_size, &values)?;
+            }
+            // Checked below against the first strategy, it's replaced per run anyway
+            self.simulation.strategy_name = compare.strategies[0].clone();
+        }
+        self.simulation.validate()
+    }
+}
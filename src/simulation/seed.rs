@@ -0,0 +1,47 @@
+// Every source of randomness in a run hangs off SimConfig::seed.
+// Each agent and server gets its own stream, so adding an agent doesn't shift the numbers
+// every other agent sees, and the same seed always replays the same experiment.
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Agent(u32),
+    Server(u32),
+    // Link emulation between one agent and one server
+    Link { agent: u32, server: u32 },
+    // Drawing sweep points, not part of any run
+    Sweep,
+}
+
+impl Stream {
+    fn tag(self) -> u64 {
+        match self {
+            Stream::Agent(id) => (1 << 32) | id as u64,
+            Stream::Server(id) => (2 << 32) | id as u64,
+            Stream::Sweep => 3 << 32,
+            Stream::Link { agent, server } => (4 << 56) | ((server as u64) << 32) | agent as u64,
+        }
+    }
+}
+
+// SplitMix64 finalizer, spreads nearby seeds far apart. Flow hashing uses it too
+pub(crate) fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+pub fn derive(seed: u64, stream: Stream) -> u64 {
+    mix(mix(seed) ^ stream.tag())
+}
+
+pub fn rng(seed: u64, stream: Stream) -> StdRng {
+    StdRng::seed_from_u64(derive(seed, stream))
+}
+
+pub fn random_seed() -> u64 {
+    rand::random()
+}
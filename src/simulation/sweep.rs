@@ -0,0 +1,463 @@
+// Parameter sweeps over SimConfig and strategy parameters, instead of shell loops around `compare`.
+// Parameters are dotted paths into the config as it's serialized: `buffer_size`, `strategy_params.max_p`,
+// `traffic_pattern.peak_rate`, `groups.0.count`. Every run ends up as rows in one long-format table,
+// (point, repetition, seed, <one column per parameter>, metric, value), which is also what resuming reads back.
+
+use super::seed::{self, Stream};
+use super::{SimConfig, Simulation};
+use crate::metrics::analyzer::{self, AnalysisReport};
+use anyhow::{Context, Result};
+use rand::Rng;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SweepSpec {
+    pub simulation: SimConfig,
+    pub sweep: SweepConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SweepConfig {
+    #[serde(default)]
+    pub mode: SweepMode,
+    // Points to draw for random and latin-hypercube, grids have as many as the product of their axes
+    #[serde(default)]
+    pub samples: u32,
+    #[serde(default = "default_repetitions")]
+    pub repetitions: u32,
+    pub parameters: BTreeMap<String, ParamSpec>,
+}
+
+// SimConfig draws a seed when it's left out, every invocation a different one. Only here to see if it was
+#[derive(Deserialize)]
+struct SeedCheck {
+    simulation: SeedOnly,
+}
+
+#[derive(Deserialize)]
+struct SeedOnly {
+    seed: Option<u64>,
+}
+
+fn default_repetitions() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SweepMode {
+    #[default]
+    Grid,
+    Random,
+    LatinHypercube,
+}
+
+/// A list of values (any type, strategy names work too) or a numeric range
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ParamSpec {
+    Values(Vec<Value>),
+    Range {
+        min: f64,
+        max: f64,
+        // Grid only, number of evenly spaced values including both ends
+        #[serde(default)]
+        steps: Option<u32>,
+        // Spaced/sampled on a log scale, for things like max_p
+        #[serde(default)]
+        log: bool,
+    },
+}
+
+impl ParamSpec {
+    fn check(&self, name: &str, mode: SweepMode) -> Result<()> {
+        match self {
+            ParamSpec::Values(values) => anyhow::ensure!(!values.is_empty(), "Parameter {} has no values", name),
+            ParamSpec::Range { min, max, steps, log } => {
+                anyhow::ensure!(min <= max, "Parameter {}: min is above max", name);
+                anyhow::ensure!(!log || *min > 0.0, "Parameter {}: a log range needs min > 0", name);
+                if mode == SweepMode::Grid {
+                    anyhow::ensure!(
+                        steps.is_some_and(|s| s >= 1),
+                        "Parameter {}: a range in a grid sweep needs steps",
+                        name
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Every value on the grid axis
+    fn grid(&self) -> Vec<Value> {
+        match self {
+            ParamSpec::Values(values) => values.clone(),
+            ParamSpec::Range { steps, .. } => {
+                let steps = steps.unwrap_or(1);
+                (0..steps)
+                    .map(|i| if steps > 1 { i as f64 / (steps - 1) as f64 } else { 0.0 })
+                    .map(|u| self.at(u))
+                    .collect()
+            }
+        }
+    }
+
+    // u in [0, 1) picks a value, uniform over lists and over the (log) range
+    fn at(&self, u: f64) -> Value {
+        match self {
+            ParamSpec::Values(values) => {
+                let idx = ((u * values.len() as f64) as usize).min(values.len() - 1);
+                values[idx].clone()
+            }
+            ParamSpec::Range { min, max, log, .. } => {
+                let x = if *log {
+                    (min.ln() + u * (max.ln() - min.ln())).exp()
+                } else {
+                    min + u * (max - min)
+                };
+                Value::from(x)
+            }
+        }
+    }
+}
+
+/// One configuration to run, parameter values in the order of SweepConfig::parameters
+#[derive(Debug, Clone)]
+pub struct SweepPoint {
+    pub index: usize,
+    pub values: Vec<Value>,
+}
+
+impl SweepSpec {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Reading sweep {}", path.display()))?;
+
+        let json = path.extension().and_then(|e| e.to_str()) == Some("json");
+        let mut spec: SweepSpec = parse(&content, json)
+            .with_context(|| format!("Parsing sweep {}", path.display()))?;
+        let check: SeedCheck = parse(&content, json)?;
+        // Resuming checks the table against the seed, a drawn one never matches the next time
+        anyhow::ensure!(
+            check.simulation.seed.is_some(),
+            "{} needs simulation.seed, without one the sweep can't be resumed",
+            path.display()
+        );
+
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    pub fn validate(&mut self) -> Result<()> {
+        let sweep = &self.sweep;
+        anyhow::ensure!(!sweep.parameters.is_empty(), "Sweep has no parameters");
+        anyhow::ensure!(sweep.repetitions > 0, "sweep.repetitions must be at least 1");
+        if sweep.mode != SweepMode::Grid {
+            anyhow::ensure!(sweep.samples > 0, "sweep.samples must be at least 1 for {:?} sampling", sweep.mode);
+        }
+        for (name, spec) in &sweep.parameters {
+            spec.check(name, sweep.mode)?;
+        }
+
+        self.simulation.validate()?;
+        // Every point has to make a valid config, better to find out before the first run than after the tenth
+        for point in self.points() {
+            self.config_for(&point, 1)?.validate()
+                .with_context(|| format!("Sweep point {} ({})", point.index, self.describe(&point)))?;
+        }
+        Ok(())
+    }
+
+    pub fn parameter_names(&self) -> Vec<&str> {
+        self.sweep.parameters.keys().map(String::as_str).collect()
+    }
+
+    /// Deterministic, random and LHS draws come from the simulation seed so a resumed sweep sees the same points
+    pub fn points(&self) -> Vec<SweepPoint> {
+        let specs: Vec<&ParamSpec> = self.sweep.parameters.values().collect();
+
+        let rows: Vec<Vec<Value>> = match self.sweep.mode {
+            SweepMode::Grid => {
+                let mut rows = vec![Vec::new()];
+                for spec in &specs {
+                    let axis = spec.grid();
+                    rows = rows.into_iter()
+                        .flat_map(|row| axis.iter().map(move |v| {
+                            let mut row = row.clone();
+                            row.push(v.clone());
+                            row
+                        }))
+                        .collect();
+                }
+                rows
+            }
+            SweepMode::Random => {
+                let mut rng = seed::rng(self.simulation.seed, Stream::Sweep);
+                (0..self.sweep.samples)
+                    .map(|_| specs.iter().map(|spec| spec.at(rng.r#gen::<f64>())).collect())
+                    .collect()
+            }
+            SweepMode::LatinHypercube => {
+                let mut rng = seed::rng(self.simulation.seed, Stream::Sweep);
+                let n = self.sweep.samples as usize;
+                // One shuffled set of strata per parameter, each stratum gets used exactly once
+                let columns: Vec<Vec<Value>> = specs.iter()
+                    .map(|spec| {
+                        let mut strata: Vec<usize> = (0..n).collect();
+                        strata.shuffle(&mut rng);
+                        strata.into_iter()
+                            .map(|s| spec.at((s as f64 + rng.r#gen::<f64>()) / n as f64))
+                            .collect()
+                    })
+                    .collect();
+                (0..n).map(|i| columns.iter().map(|c| c[i].clone()).collect()).collect()
+            }
+        };
+
+        // Integer fields get whole numbers, so the table shows the buffer_size that actually ran
+        let base = serde_json::to_value(&self.simulation).unwrap_or(Value::Null);
+        let integer: Vec<bool> = self.parameter_names().iter()
+            .map(|name| {
+                name.split('.')
+                    .try_fold(&base, |v, key| match v {
+                        Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+                        _ => v.get(key),
+                    })
+                    .is_some_and(|v| v.is_u64() || v.is_i64())
+            })
+            .collect();
+
+        rows.into_iter()
+            .enumerate()
+            .map(|(index, values)| SweepPoint {
+                index,
+                values: values.into_iter()
+                    .zip(&integer)
+                    .map(|(value, &integer)| match value.as_f64() {
+                        Some(x) if integer && value.is_f64() => Value::from(x.round() as i64),
+                        _ => value,
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// The base config with the point's values filled in, seeded like compare does (base + repetition - 1)
+    pub fn config_for(&self, point: &SweepPoint, repetition: u32) -> Result<SimConfig> {
+        let mut config = serde_json::to_value(&self.simulation)?;
+        for (name, value) in self.parameter_names().into_iter().zip(&point.values) {
+            set_path(&mut config, name, value.clone())
+                .with_context(|| format!("Setting sweep parameter {}", name))?;
+        }
+
+        let mut config: SimConfig = serde_json::from_value(config)
+            .with_context(|| format!("Sweep point {} doesn't make a valid config", point.index))?;
+        config.name = format!("{}_p{}_r{}", self.simulation.name, point.index, repetition);
+        config.seed = self.simulation.seed.wrapping_add(repetition as u64 - 1);
+        Ok(config)
+    }
+
+    pub fn describe(&self, point: &SweepPoint) -> String {
+        self.parameter_names().iter()
+            .zip(&point.values)
+            .map(|(name, value)| format!("{}={}", name, cell(value)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    pub fn table_path(&self) -> PathBuf {
+        Path::new(&self.simulation.output.dir).join(format!("{}_sweep.csv", self.simulation.name))
+    }
+}
+
+fn parse<T: serde::de::DeserializeOwned>(content: &str, json: bool) -> Result<T> {
+    Ok(if json { serde_json::from_str(content)? } else { toml::from_str(content)? })
+}
+
+// Walks a dotted path, numbers index into arrays. Missing object keys get created (new strategy_params)
+fn set_path(root: &mut Value, path: &str, value: Value) -> Result<()> {
+    let mut target = root;
+    for key in path.split('.') {
+        target = match target {
+            Value::Object(map) => map.entry(key.to_string()).or_insert(Value::Null),
+            Value::Array(items) => {
+                let idx: usize = key.parse().with_context(|| format!("{} is not an index", key))?;
+                let len = items.len();
+                items.get_mut(idx).with_context(|| format!("Index {} out of range ({} items)", idx, len))?
+            }
+            _ => anyhow::bail!("{} is not a table", key),
+        };
+    }
+
+    *target = value;
+    Ok(())
+}
+
+// Strings without quotes, everything else as JSON
+fn cell(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+// Every plain number in the report becomes a metric, new report fields show up without touching this
+fn metrics_of(report: &AnalysisReport) -> Result<Vec<(String, f64)>> {
+    let Value::Object(fields) = serde_json::to_value(report)? else {
+        return Ok(Vec::new());
+    };
+    Ok(fields.into_iter()
+        .filter(|(name, _)| name != "seed")
+        .filter_map(|(name, value)| value.as_f64().map(|v| (name, v)))
+        .collect())
+}
+
+/// (point, repetition) pairs already in the table. Errors if the table was written by a different sweep.
+/// Rows of a run that got cut off are taken out of the table, that run starts over from nothing
+fn finished_runs(spec: &SweepSpec, path: &Path, points: &[SweepPoint]) -> Result<HashSet<(usize, u32)>> {
+    if !path.exists() {
+        return Ok(HashSet::new());
+    }
+
+    let mut reader = csv::Reader::from_path(path)?;
+    let expected = table_header(spec);
+    let header: Vec<String> = reader.headers()?.iter().map(str::to_string).collect();
+    anyhow::ensure!(
+        header == expected,
+        "{} has different columns than this sweep, move it away or change simulation.name",
+        path.display()
+    );
+
+    let names = spec.parameter_names();
+    let mut rows: HashMap<(usize, u32), usize> = HashMap::new();
+    let mut kept: Vec<((usize, u32), csv::StringRecord)> = Vec::new();
+    let mut unreadable = 0;
+    for record in reader.records() {
+        // A run cut off while writing leaves a short last line, that run just gets redone
+        let Ok(record) = record else {
+            unreadable += 1;
+            continue;
+        };
+        let (Some(Ok(point)), Some(Ok(repetition))) = (
+            record.get(0).map(str::parse::<usize>),
+            record.get(1).map(str::parse::<u32>),
+        ) else {
+            unreadable += 1;
+            continue;
+        };
+
+        let seed = spec.simulation.seed.wrapping_add(repetition as u64).wrapping_sub(1);
+        anyhow::ensure!(
+            record.get(2) == Some(seed.to_string().as_str()),
+            "{} was written with a different simulation.seed, move it away or change simulation.name",
+            path.display()
+        );
+
+        let point_values = points.get(point)
+            .with_context(|| format!("{} has point {} which this sweep doesn't", path.display(), point))?;
+        for (i, value) in point_values.values.iter().enumerate() {
+            anyhow::ensure!(
+                record.get(3 + i) == Some(cell(value).as_str()),
+                "{} has different values for point {} ({}), the sweep changed since it was written",
+                path.display(),
+                point,
+                names[i]
+            );
+        }
+        *rows.entry((point, repetition)).or_default() += 1;
+        kept.push(((point, repetition), record));
+    }
+
+    // Only runs with every metric written count, anything less was cut off
+    let per_run = metrics_of(&analyzer::analyze(&[], ""))?.len();
+    let done: HashSet<(usize, u32)> = rows.into_iter()
+        .filter(|&(_, count)| count >= per_run)
+        .map(|(run, _)| run)
+        .collect();
+
+    // Appending the redone run after its partial rows would count it twice, so the table gets rewritten without them
+    let partial = kept.iter().filter(|(run, _)| !done.contains(run)).count();
+    if partial + unreadable > 0 {
+        info!("Dropping {} rows of runs that were cut off from {}", partial + unreadable, path.display());
+        let tmp = path.with_extension("csv.tmp");
+        let mut writer = csv::Writer::from_path(&tmp)?;
+        writer.write_record(&expected)?;
+        for (_, record) in kept.iter().filter(|(run, _)| done.contains(run)) {
+            writer.write_record(record)?;
+        }
+        writer.flush()?;
+        std::fs::rename(&tmp, path)?;
+    }
+    Ok(done)
+}
+
+fn table_header(spec: &SweepSpec) -> Vec<String> {
+    let mut header = vec!["point".to_string(), "repetition".to_string(), "seed".to_string()];
+    header.extend(spec.parameter_names().into_iter().map(str::to_string));
+    header.extend(["metric".to_string(), "value".to_string()]);
+    header
+}
+
+/// Runs every point `repetitions` times, skipping what the table already has
+pub async fn run(spec: &SweepSpec) -> Result<()> {
+    let points = spec.points();
+    let path = spec.table_path();
+    std::fs::create_dir_all(&spec.simulation.output.dir)?;
+
+    let done = finished_runs(spec, &path, &points)?;
+    let total = points.len() * spec.sweep.repetitions as usize;
+    info!("Sweep: {} points x {} repetitions = {} runs", points.len(), spec.sweep.repetitions, total);
+    if !done.is_empty() {
+        info!("Resuming, {} runs already in {}", done.len(), path.display());
+    }
+
+    let fresh = !path.exists();
+    let file = OpenOptions::new().create(true).append(true).open(&path)?;
+    let mut writer = csv::Writer::from_writer(file);
+    if fresh {
+        writer.write_record(table_header(spec))?;
+        writer.flush()?;
+    }
+
+    let mut completed = done.len();
+    for point in &points {
+        for repetition in 1..=spec.sweep.repetitions {
+            if done.contains(&(point.index, repetition)) {
+                continue;
+            }
+            completed += 1;
+            info!("[{}/{}] Point {} rep {}: {}", completed, total, point.index, repetition, spec.describe(point));
+
+            let config = spec.config_for(point, repetition)?;
+            let seed = config.seed;
+            let strategy_name = config.strategy_name.clone();
+
+            let mut sim = Simulation::new(config);
+            sim.run().await?;
+            let report = analyzer::analyze(&sim.metrics.get_snapshots(), &strategy_name);
+
+            let mut prefix = vec![point.index.to_string(), repetition.to_string(), seed.to_string()];
+            prefix.extend(point.values.iter().map(cell));
+            for (metric, value) in metrics_of(&report)? {
+                let mut row = prefix.clone();
+                row.push(metric);
+                row.push(value.to_string());
+                writer.write_record(&row)?;
+            }
+            // One flush per finished run, that's the unit resuming works in
+            writer.flush()?;
+        }
+    }
+
+    info!("Sweep results in {}", path.display());
+    Ok(())
+}
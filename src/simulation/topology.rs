@@ -0,0 +1,309 @@
+// Multi-hop topologies for the discrete-event backend. Instead of every agent talking straight to a server,
+// agents hang off routers, routers forward over links (bandwidth + delay) and packets end up at sinks.
+// Every link leaving a router is an egress port with its own queue, run by the router's strategy, so a
+// dumbbell has one bottleneck queue and a parking lot has one per hop.
+// Routes are fixed at the start: fewest hops, ties go to the link listed first.
+
+use super::SimConfig;
+use crate::metrics::MetricsCollector;
+use crate::network::feedback::FeedbackSender;
+use crate::network::{Arrival, Feedback, Packet, SequenceTracker};
+use crate::strategies::StrategyRegistry;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Topology {
+    pub routers: Vec<RouterSpec>,
+    pub sinks: Vec<SinkSpec>,
+    pub links: Vec<LinkSpec>,
+    // Which router the agents hang off, takes agent ids in order like groups do
+    pub attach: Vec<Attachment>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouterSpec {
+    pub name: String,
+    // The run's strategy (and its params) when left out, so a comparison swaps every router that doesn't pin one
+    #[serde(default)]
+    pub strategy: Option<String>,
+    #[serde(default)]
+    pub strategy_params: BTreeMap<String, f64>,
+    // Per egress port, the run's buffer_size when left out
+    #[serde(default)]
+    pub buffer_size: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SinkSpec {
+    pub name: String,
+}
+
+/// From a router to a router or a sink. One direction unless `duplex`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LinkSpec {
+    pub from: String,
+    pub to: String,
+    // The run's bandwidth_bps when left out
+    #[serde(default)]
+    pub bandwidth_bps: Option<u64>,
+    #[serde(default)]
+    pub latency_ms: f64,
+    // Same link the other way too, for relay meshes. Needs both ends to be routers
+    #[serde(default)]
+    pub duplex: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Attachment {
+    pub router: String,
+    pub count: u32,
+    // Sinks these agents send to, picked at random per packet. Every sink when empty
+    #[serde(default)]
+    pub sinks: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Node {
+    Router(usize),
+    Sink(usize),
+}
+
+/// One router egress port, what the simulation builds a forwarding server from
+#[derive(Debug, Clone)]
+pub struct Port {
+    pub label: String,
+    pub strategy: String,
+    pub strategy_params: BTreeMap<String, f64>,
+    pub buffer_size: usize,
+    pub bandwidth_bps: u64,
+    pub delay: Duration,
+    router: usize,
+    to: Node,
+}
+
+/// A topology with names resolved and routes computed
+#[derive(Debug, Clone)]
+pub struct Plan {
+    pub ports: Vec<Port>,
+    pub sink_names: Vec<String>,
+    // [router][sink] -> port towards that sink
+    table: Vec<Vec<Option<usize>>>,
+    // Per agent id
+    attached: Vec<usize>,
+    destinations: Vec<Vec<u32>>,
+}
+
+impl Plan {
+    /// Global sink indices agent `id` may send to
+    pub fn destinations(&self, id: u32) -> &[u32] {
+        &self.destinations[id as usize]
+    }
+}
+
+impl Topology {
+    pub fn agent_count(&self) -> u32 {
+        self.attach.iter().map(|a| a.count).sum()
+    }
+
+    /// Resolves names, checks every agent can reach every sink it sends to and computes the routes
+    pub fn plan(&self, config: &SimConfig) -> Result<Plan> {
+        anyhow::ensure!(!self.routers.is_empty(), "A topology needs at least one router");
+        anyhow::ensure!(!self.sinks.is_empty(), "A topology needs at least one sink");
+
+        let mut nodes: HashMap<&str, Node> = HashMap::new();
+        for (i, router) in self.routers.iter().enumerate() {
+            anyhow::ensure!(nodes.insert(&router.name, Node::Router(i)).is_none(), "Node {} is defined twice", router.name);
+        }
+        for (i, sink) in self.sinks.iter().enumerate() {
+            anyhow::ensure!(nodes.insert(&sink.name, Node::Sink(i)).is_none(), "Node {} is defined twice", sink.name);
+        }
+        let node = |name: &str| nodes.get(name).copied().ok_or_else(|| anyhow::anyhow!("Unknown node {} in topology", name));
+
+        let registry = StrategyRegistry::global();
+        let mut ports = Vec::new();
+        for link in &self.links {
+            let (from, to) = (node(&link.from)?, node(&link.to)?);
+            anyhow::ensure!(link.latency_ms >= 0.0 && link.latency_ms.is_finite(), "Link {} → {}: latency_ms must be >= 0", link.from, link.to);
+            anyhow::ensure!(link.bandwidth_bps != Some(0), "Link {} → {}: bandwidth_bps must be positive", link.from, link.to);
+
+            let mut directions = vec![(from, to, &link.from, &link.to)];
+            if link.duplex {
+                directions.push((to, from, &link.to, &link.from));
+            }
+            for (from, to, from_name, to_name) in directions {
+                let Node::Router(router) = from else {
+                    anyhow::bail!("Link {} → {} starts at a sink, sinks only receive", from_name, to_name);
+                };
+                anyhow::ensure!(from != to, "Link {} → {} loops back to itself", from_name, to_name);
+
+                let spec = &self.routers[router];
+                let (strategy, strategy_params) = match &spec.strategy {
+                    Some(strategy) => (strategy.clone(), spec.strategy_params.clone()),
+                    None => (config.strategy_name.clone(), config.strategy_params.clone()),
+                };
+                let buffer_size = spec.buffer_size.unwrap_or(config.buffer_size);
+                anyhow::ensure!(buffer_size > 0, "Router {}: buffer_size must be at least 1", spec.name);
+                registry.create_with(&strategy, buffer_size, &strategy_params)?;
+
+                ports.push(Port {
+                    label: format!("{} → {}", from_name, to_name),
+                    strategy,
+                    strategy_params,
+                    buffer_size,
+                    bandwidth_bps: link.bandwidth_bps.unwrap_or(config.bandwidth_bps),
+                    delay: Duration::from_secs_f64(link.latency_ms / 1000.0),
+                    router,
+                    to,
+                });
+            }
+        }
+
+        // Breadth-first from every sink against the link direction, the first port found to a router is its route
+        let mut table = vec![vec![None; self.sinks.len()]; self.routers.len()];
+        for (sink, _) in self.sinks.iter().enumerate() {
+            let mut seen = HashSet::from([Node::Sink(sink)]);
+            let mut frontier = VecDeque::from([Node::Sink(sink)]);
+            while let Some(at) = frontier.pop_front() {
+                for (i, port) in ports.iter().enumerate() {
+                    if port.to == at && seen.insert(Node::Router(port.router)) {
+                        table[port.router][sink] = Some(i);
+                        frontier.push_back(Node::Router(port.router));
+                    }
+                }
+            }
+        }
+
+        let mut attached = Vec::new();
+        let mut destinations = Vec::new();
+        for attachment in &self.attach {
+            let Node::Router(router) = node(&attachment.router)? else {
+                anyhow::bail!("Agents can only attach to routers, {} is a sink", attachment.router);
+            };
+            let sinks: Vec<u32> = if attachment.sinks.is_empty() {
+                (0..self.sinks.len() as u32).collect()
+            } else {
+                attachment.sinks.iter()
+                    .map(|name| match node(name)? {
+                        Node::Sink(s) => Ok(s as u32),
+                        Node::Router(_) => anyhow::bail!("{} is a router, agents can only send to sinks", name),
+                    })
+                    .collect::<Result<_>>()?
+            };
+            for &sink in &sinks {
+                anyhow::ensure!(
+                    table[router][sink as usize].is_some(),
+                    "No route from {} to {}",
+                    attachment.router,
+                    self.sinks[sink as usize].name
+                );
+            }
+            for _ in 0..attachment.count {
+                attached.push(router);
+                destinations.push(sinks.clone());
+            }
+        }
+        anyhow::ensure!(
+            attached.len() == config.num_agents as usize,
+            "num_agents is {} but the topology attaches {}",
+            config.num_agents,
+            attached.len()
+        );
+
+        Ok(Plan {
+            ports,
+            sink_names: self.sinks.iter().map(|s| s.name.clone()).collect(),
+            table,
+            attached,
+            destinations,
+        })
+    }
+}
+
+/// Where a transmitted packet goes next
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Next {
+    Port(usize),
+    Sink(usize),
+}
+
+#[derive(Debug)]
+struct Sink {
+    // Metrics id, after the ports
+    id: u32,
+    sequences: HashMap<u32, SequenceTracker>,
+}
+
+/// How packets move through a discrete-event run. Flat runs hand every packet straight to the server
+/// the agent picked and that server delivers it, topologies forward it port by port until a sink
+#[derive(Debug, Default)]
+pub(crate) struct Routes {
+    // Empty for a flat run, otherwise per port
+    hops: Vec<(Duration, Node)>,
+    table: Vec<Vec<Option<usize>>>,
+    attached: Vec<usize>,
+    sinks: Vec<Sink>,
+}
+
+impl Routes {
+    pub(crate) fn direct() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn new(plan: &Plan) -> Self {
+        let first_sink_id = plan.ports.len() as u32;
+        Self {
+            hops: plan.ports.iter().map(|p| (p.delay, p.to)).collect(),
+            table: plan.table.clone(),
+            attached: plan.attached.clone(),
+            sinks: (0..plan.sink_names.len() as u32)
+                .map(|s| Sink { id: first_sink_id + s, sequences: HashMap::new() })
+                .collect(),
+        }
+    }
+
+    /// Server (or port) a packet from agent `agent` goes into first
+    pub(crate) fn entry(&self, agent: usize, packet: &Packet) -> usize {
+        match self.attached.get(agent) {
+            Some(&router) => self.route(router, packet),
+            None => packet.destination_server as usize,
+        }
+    }
+
+    /// Where a packet that just left `port` ends up and how long the link takes
+    pub(crate) fn next(&self, port: usize, packet: &Packet) -> (Duration, Next) {
+        let (delay, to) = self.hops[port];
+        match to {
+            Node::Router(router) => (delay, Next::Port(self.route(router, packet))),
+            Node::Sink(sink) => (delay, Next::Sink(sink)),
+        }
+    }
+
+    fn route(&self, router: usize, packet: &Packet) -> usize {
+        // plan() made sure every destination an agent uses is reachable from where it is attached,
+        // and routes are shortest paths, so every router on the way has one too
+        self.table[router][packet.destination_server as usize].expect("route checked by Topology::plan")
+    }
+
+    /// Packet reached its sink: counted as received and acknowledged like a server would
+    pub(crate) fn deliver(&mut self, sink: usize, packet: Packet, metrics: &MetricsCollector, feedback: &FeedbackSender) {
+        let sink = &mut self.sinks[sink];
+        if let Some(seq) = packet.seq {
+            match sink.sequences.entry(packet.source_agent).or_default().observe(seq) {
+                Arrival::InOrder => {}
+                Arrival::Gap(missing) => metrics.transit_lost(sink.id, missing),
+                Arrival::Late => metrics.transit_late(sink.id),
+            }
+        }
+        metrics.packet_received(sink.id, &packet, packet.sojourn_time());
+        // One shared return path in discrete-event runs, it can't be closed while the loop runs
+        let _ = feedback.send(Feedback::for_packet(&packet, sink.id, false));
+    }
+}
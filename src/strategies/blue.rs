@@ -1,6 +1,8 @@
-use super::{Action, Strategy};
+use super::{Action, PacketQueue, Params, QueueEvents, Strategy, StrategyParam};
 use crate::network::Packet;
-use rand::Rng;
+use crate::simulation::clock;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 use std::time::Instant;
 
 #[derive(Debug, Clone)]
@@ -14,36 +16,49 @@ pub struct Blue {
     last_increase: Instant,
     last_decrease: Instant,
     last_loss_event: Option<Instant>,
+    rng: StdRng,
+    queue: PacketQueue,
 }
 
+pub const PARAMS: &[StrategyParam] = &[
+    StrategyParam { name: "d1", default: Some(0.02), min: 0.0, max: 1.0, description: "Marking probability increase on queue overflow" },
+    StrategyParam { name: "d2", default: Some(0.002), min: 0.0, max: 1.0, description: "Marking probability decrease when the link goes idle" },
+    StrategyParam { name: "freeze_ms", default: Some(100.0), min: 0.0, max: 10_000.0, description: "Minimum time between two changes of the probability" },
+];
+
 impl Blue {
     pub fn new(buffer_size: usize) -> Self {
-        let now = Instant::now();
+        Self::with_params(buffer_size, &Params::defaults(PARAMS))
+    }
+
+    pub fn with_params(buffer_size: usize, params: &Params) -> Self {
+        let now = clock::now();
         Self {
             p_mark: 0.0,
-            d1: 0.02,
-            d2: 0.002, 
-            freeze_time: std::time::Duration::from_millis(100),
+            d1: params.get("d1"),
+            d2: params.get("d2"),
+            freeze_time: std::time::Duration::from_secs_f64(params.get("freeze_ms") / 1000.0),
             last_update: now,
             buffer_size,
             last_increase: now,
             last_decrease: now,
             last_loss_event: None,
+            rng: StdRng::from_entropy(),
+            queue: PacketQueue::new(),
         }
     }
 
     fn can_increase(&self) -> bool {
-        self.last_increase.elapsed() >= self.freeze_time
+        clock::elapsed(self.last_increase) >= self.freeze_time
     }
 
     fn can_decrease(&self) -> bool {
-        self.last_decrease.elapsed() >= self.freeze_time
+        clock::elapsed(self.last_decrease) >= self.freeze_time
     }
-}
 
-impl Strategy for Blue {
-    fn on_enqueue(&mut self, _packet: &Packet, queue_len: usize) -> Action {
-        let now = Instant::now();
+    fn admit(&mut self, packet: &Packet) -> Action {
+        let now = clock::now();
+        let queue_len = self.queue.len();
         
         let threshold = (self.buffer_size as f64 * 0.8) as usize;
         if queue_len >= threshold && self.can_increase() {
@@ -54,7 +69,7 @@ impl Strategy for Blue {
         if queue_len >= self.buffer_size {
             if let Some(last_loss) = self.last_loss_event {
                 // If losses are frequent, increase more aggressively
-                if last_loss.elapsed() < self.freeze_time {
+                if clock::elapsed(last_loss) < self.freeze_time {
                     self.p_mark = (self.p_mark + self.d1 * 2.0).min(1.0);
                 }
             }
@@ -64,31 +79,51 @@ impl Strategy for Blue {
         }
         
         // Probabilistic marking
-        if self.p_mark > 0.0 && rand::thread_rng().r#gen::<f64>() < self.p_mark {
-            Action::Drop
+        if self.p_mark > 0.0 && self.rng.r#gen::<f64>() < self.p_mark {
+            Action::signal(packet)
         } else {
             Action::Accept
         }
     }
 
-    fn on_dequeue(&mut self, queue_len: usize) {
+    fn on_dequeue(&mut self) {
+        let queue_len = self.queue.len();
         // decrease when queue is low and has no recent losses
         if queue_len < (self.buffer_size / 4) && self.can_decrease() {
             if let Some(last_loss) = self.last_loss_event {
-                if last_loss.elapsed() > self.freeze_time * 2 {
+                if clock::elapsed(last_loss) > self.freeze_time * 2 {
                     self.p_mark = (self.p_mark - self.d2).max(0.0);
-                    self.last_decrease = Instant::now();
+                    self.last_decrease = clock::now();
                 }
             } else {
                 self.p_mark = (self.p_mark - self.d2).max(0.0);
-                self.last_decrease = Instant::now();
+                self.last_decrease = clock::now();
             }
         }
     }
+}
+
+impl Strategy for Blue {
+    fn enqueue(&mut self, packet: Packet, events: &mut QueueEvents) {
+        let action = self.admit(&packet);
+        if let Some(packet) = events.apply(action, packet) {
+            self.queue.push(packet);
+        }
+    }
+
+    fn dequeue(&mut self, _events: &mut QueueEvents) -> Option<Packet> {
+        let queued = self.queue.pop()?;
+        self.on_dequeue();
+        Some(queued.packet)
+    }
 
-    fn update(&mut self, queue_len: usize, _avg_sojourn_ms: f64) {
+    fn len(&self) -> usize { self.queue.len() }
+    fn bytes(&self) -> u64 { self.queue.bytes() }
+
+    fn update(&mut self, _avg_sojourn_ms: f64) {
+        let queue_len = self.queue.len();
         // periodic adjustment
-        if self.last_update.elapsed() > self.freeze_time * 5 {
+        if clock::elapsed(self.last_update) > self.freeze_time * 5 {
             let target = self.buffer_size / 2;
             
             if queue_len > target && self.p_mark < 0.5 {
@@ -97,21 +132,29 @@ impl Strategy for Blue {
                 self.p_mark = (self.p_mark - self.d2 * 0.5).max(0.0);
             }
             
-            self.last_update = Instant::now();
+            self.last_update = clock::now();
         }
     }
 
     fn name(&self) -> &str { "BLUE" }
     fn reset(&mut self) {
         self.p_mark = 0.0;
-        let now = Instant::now();
+        let now = clock::now();
         self.last_update = now;
         self.last_increase = now;
         self.last_decrease = now;
         self.last_loss_event = None;
+        self.queue.clear();
     }
 
     fn clone_box(&self) -> Box<dyn Strategy> {
-        Box::new(Self::new(self.buffer_size))
+        // Fresh state, same parameters
+        let mut fresh = self.clone();
+        fresh.reset();
+        Box::new(fresh)
+    }
+
+    fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
     }
-}
\ No newline at end of file
+}
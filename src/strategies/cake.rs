@@ -0,0 +1,305 @@
+// CAKE-style queuing, after sch_cake in Linux. Flows hash into 8-way sets so two flows only share a queue
+// when a whole set is busy, the DRR quantum of a flow is split by how many flows its host (source_agent) has
+// going so a chatty agent can't take more than its share by opening streams, and a shaper keeps the
+// output at the link rate. Every flow runs COBALT: CoDel for the flows that back off, BLUE on top for the
+// ones that keep overflowing the buffer anyway.
+
+use super::fq_codel_rfc::{CoDelState, List};
+use super::{PacketQueue, Params, QueueEvents, Strategy, StrategyParam};
+use crate::network::Packet;
+use crate::simulation::{clock, seed};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+const WAYS: usize = 8;
+// BLUE steps, as in sch_cake
+const BLUE_INCREMENT: f64 = 1.0 / 256.0;
+const BLUE_DECREMENT: f64 = 1.0 / 4096.0;
+
+/// The BLUE half of COBALT, a drop probability that goes up every time the flow overflows the buffer
+#[derive(Debug, Clone, Default)]
+struct BlueState {
+    p_drop: f64,
+    last_update: Option<Instant>,
+}
+
+impl BlueState {
+    // At most one step per `freeze`, a burst of overflows counts once
+    fn may_update(&self, now: Instant, freeze: Duration) -> bool {
+        self.last_update.is_none_or(|last| now.saturating_duration_since(last) > freeze)
+    }
+
+    fn on_full(&mut self, now: Instant, freeze: Duration) {
+        if self.may_update(now, freeze) {
+            self.p_drop = (self.p_drop + BLUE_INCREMENT).min(1.0);
+            self.last_update = Some(now);
+        }
+    }
+
+    fn on_empty(&mut self, now: Instant, freeze: Duration) {
+        if self.p_drop > 0.0 && self.may_update(now, freeze) {
+            self.p_drop = (self.p_drop - BLUE_DECREMENT).max(0.0);
+            self.last_update = Some(now);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Flow {
+    queue: PacketQueue,
+    deficit: i64,
+    list: Option<List>,
+    codel: CoDelState,
+    blue: BlueState,
+    // Full hash of the flow that owns this way, an idle way goes to whoever needs one next
+    tag: Option<u64>,
+    host: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cake {
+    flows: Vec<Flow>,
+    // Active flows per host bucket, a host's flows share one quantum
+    host_flows: Vec<u32>,
+    new_flows: VecDeque<usize>,
+    old_flows: VecDeque<usize>,
+    queued: usize,
+    buffer_size: usize,
+    quantum: i64,
+    target: Duration,
+    interval: Duration,
+    // Shaper, None until the server says how fast its link is (or rate_mbps was given)
+    rate_bps: Option<f64>,
+    fixed_rate: bool,
+    next_send: Option<Instant>,
+    perturbation: u64,
+    fixed_perturbation: bool,
+    // Packets that found their whole set busy, per-host isolation doesn't hold for those
+    way_collisions: u64,
+    rng: StdRng,
+}
+
+pub const PARAMS: &[StrategyParam] = &[
+    StrategyParam { name: "flows", default: Some(1024.0), min: WAYS as f64, max: 65_536.0, description: "Flow buckets, in sets of 8" },
+    StrategyParam { name: "hosts", default: Some(1024.0), min: 1.0, max: 65_536.0, description: "Host buckets for per-agent fairness" },
+    super::codel::PARAMS[0],
+    super::codel::PARAMS[1],
+    super::fq_codel_rfc::PARAMS[3],
+    StrategyParam { name: "rate_mbps", default: None, min: 0.001, max: 1e6, description: "Shaper rate, auto follows the link's bandwidth" },
+    super::fq_codel::PARAMS[3],
+];
+
+impl Cake {
+    pub fn new(buffer_size: usize) -> Self {
+        Self::with_params(buffer_size, &Params::defaults(PARAMS))
+    }
+
+    pub fn with_params(buffer_size: usize, params: &Params) -> Self {
+        let sets = params.get("flows") as usize / WAYS;
+        Self {
+            flows: vec![Flow::default(); sets * WAYS],
+            host_flows: vec![0; params.get("hosts") as usize],
+            new_flows: VecDeque::new(),
+            old_flows: VecDeque::new(),
+            queued: 0,
+            buffer_size,
+            quantum: params.get("quantum") as i64,
+            target: Duration::from_secs_f64(params.get("target_ms") / 1000.0),
+            interval: Duration::from_secs_f64(params.get("interval_ms") / 1000.0),
+            rate_bps: params.get_opt("rate_mbps").map(|mbps| mbps * 1_000_000.0),
+            fixed_rate: params.get_opt("rate_mbps").is_some(),
+            next_send: None,
+            perturbation: params.get_opt("perturbation").unwrap_or(0.0) as u64,
+            fixed_perturbation: params.get_opt("perturbation").is_some(),
+            way_collisions: 0,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// The flow's own way in its set, else an idle one it can claim. A set with every way busy
+    /// puts the packet wherever its hash points, that's the only time flows collide
+    fn classify(&mut self, packet: &Packet) -> usize {
+        let hash = packet.flow.hash(self.perturbation);
+        let host = (seed::mix(packet.source_agent as u64 ^ self.perturbation) % self.host_flows.len() as u64) as usize;
+        let sets = self.flows.len() / WAYS;
+        let set = (hash % sets as u64) as usize * WAYS;
+        let ways = set..set + WAYS;
+
+        if let Some(way) = ways.clone().find(|&w| self.flows[w].tag == Some(hash)) {
+            return way;
+        }
+        if let Some(way) = ways.clone().find(|&w| self.flows[w].list.is_none()) {
+            self.flows[way].tag = Some(hash);
+            self.flows[way].host = host;
+            return way;
+        }
+
+        // Like sch_cake the newcomer takes the way over, and an active way's count moves to its host.
+        // Otherwise the occupying flow's host would pay for this packet in flow_quantum
+        let way = set + (hash / sets as u64 % WAYS as u64) as usize;
+        self.way_collisions += 1;
+        debug!("CAKE set {} is full, flow collision {} (per-host isolation doesn't hold)", set / WAYS, self.way_collisions);
+        let old = self.flows[way].host;
+        if self.flows[way].list.is_some() && old != host {
+            self.host_flows[old] = self.host_flows[old].saturating_sub(1);
+            self.host_flows[host] += 1;
+        }
+        self.flows[way].tag = Some(hash);
+        self.flows[way].host = host;
+        way
+    }
+
+    // A flow's share of the round, its host's quantum split over the host's active flows
+    fn flow_quantum(&self, flow: usize) -> i64 {
+        let flows = self.host_flows[self.flows[flow].host].max(1) as i64;
+        (self.quantum / flows).max(1)
+    }
+
+    fn pop_list(&mut self, list: List) {
+        match list {
+            List::New => self.new_flows.pop_front(),
+            List::Old => self.old_flows.pop_front(),
+        };
+    }
+
+    // The flow ran empty, it stops counting towards its host
+    fn retire(&mut self, flow: usize) {
+        self.flows[flow].list = None;
+        let host = self.flows[flow].host;
+        self.host_flows[host] = self.host_flows[host].saturating_sub(1);
+    }
+
+    // Overflow: the flow with the most bytes loses its head, and BLUE takes note
+    fn drop_from_fattest(&mut self, events: &mut QueueEvents) {
+        let Some(fattest) = self.new_flows.iter()
+            .chain(&self.old_flows)
+            .copied()
+            .max_by_key(|&f| self.flows[f].queue.bytes())
+        else {
+            return;
+        };
+        let flow = &mut self.flows[fattest];
+        flow.blue.on_full(clock::now(), self.target);
+        if let Some(head) = flow.queue.pop() {
+            self.queued -= 1;
+            events.drop(head.packet);
+        }
+    }
+
+    fn transmission_time(&self, bytes: u32) -> Option<Duration> {
+        self.rate_bps.map(|rate| Duration::from_secs_f64(bytes as f64 * 8.0 / rate))
+    }
+}
+
+impl Strategy for Cake {
+    fn enqueue(&mut self, packet: Packet, events: &mut QueueEvents) {
+        let flow = self.classify(&packet);
+        self.flows[flow].queue.push(packet);
+        self.queued += 1;
+
+        if self.flows[flow].list.is_none() {
+            self.flows[flow].list = Some(List::New);
+            self.host_flows[self.flows[flow].host] += 1;
+            self.flows[flow].deficit = self.flow_quantum(flow);
+            self.new_flows.push_back(flow);
+        }
+
+        if self.queued > self.buffer_size {
+            self.drop_from_fattest(events);
+        }
+    }
+
+    fn dequeue(&mut self, events: &mut QueueEvents) -> Option<Packet> {
+        let now = clock::now();
+        // Shaper: nothing goes out before the last packet would have cleared the link
+        if self.next_send.is_some_and(|next| now < next) {
+            return None;
+        }
+
+        loop {
+            let (flow, list) = match self.new_flows.front() {
+                Some(&flow) => (flow, List::New),
+                None => (*self.old_flows.front()?, List::Old),
+            };
+
+            if self.flows[flow].deficit <= 0 {
+                self.flows[flow].deficit += self.flow_quantum(flow);
+                self.pop_list(list);
+                self.flows[flow].list = Some(List::Old);
+                self.old_flows.push_back(flow);
+                continue;
+            }
+
+            let Flow { queue, codel, blue, .. } = &mut self.flows[flow];
+            let before = queue.len();
+            // BLUE goes first, a flow that keeps overflowing the buffer isn't listening to CoDel's signals
+            while blue.p_drop > 0.0 && !queue.is_empty() && self.rng.r#gen::<f64>() < blue.p_drop {
+                if let Some(head) = queue.pop() {
+                    events.drop(head.packet);
+                }
+            }
+            if queue.is_empty() {
+                blue.on_empty(now, self.target);
+            }
+            let packet = codel.dequeue(queue, self.target, self.interval, events);
+            self.queued -= before - queue.len();
+
+            let Some(packet) = packet else {
+                self.pop_list(list);
+                if list == List::New && !self.old_flows.is_empty() {
+                    self.flows[flow].list = Some(List::Old);
+                    self.old_flows.push_back(flow);
+                } else {
+                    self.retire(flow);
+                }
+                continue;
+            };
+
+            self.flows[flow].deficit -= packet.payload_size as i64;
+            if let Some(busy) = self.transmission_time(packet.payload_size) {
+                self.next_send = Some(self.next_send.map_or(now, |next| next.max(now)) + busy);
+            }
+            return Some(packet);
+        }
+    }
+
+    fn len(&self) -> usize { self.queued }
+    fn bytes(&self) -> u64 {
+        self.new_flows.iter().chain(&self.old_flows).map(|&f| self.flows[f].queue.bytes()).sum()
+    }
+    fn update(&mut self, _avg_sojourn_ms: f64) { }
+    fn name(&self) -> &str { "CAKE" }
+
+    fn reset(&mut self) {
+        self.flows.iter_mut().for_each(|f| *f = Flow::default());
+        self.host_flows.iter_mut().for_each(|h| *h = 0);
+        self.new_flows.clear();
+        self.old_flows.clear();
+        self.queued = 0;
+        self.next_send = None;
+        self.way_collisions = 0;
+    }
+
+    fn clone_box(&self) -> Box<dyn Strategy> {
+        // Fresh state, same parameters
+        let mut fresh = self.clone();
+        fresh.reset();
+        Box::new(fresh)
+    }
+
+    fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+        if !self.fixed_perturbation {
+            self.perturbation = seed;
+        }
+    }
+
+    fn set_link_rate(&mut self, bandwidth_bps: u64) {
+        if !self.fixed_rate {
+            self.rate_bps = Some(bandwidth_bps as f64);
+        }
+    }
+}
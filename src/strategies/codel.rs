@@ -1,13 +1,7 @@
-use super::{Action, Strategy};
+use super::{Action, PacketQueue, Params, QueueEvents, Strategy, StrategyParam};
 use crate::network::Packet;
+use crate::simulation::clock;
 use std::time::{Duration, Instant};
-use std::collections::VecDeque;
-
-#[derive(Debug, Clone)]
-struct QueuedPacket {
-    packet: Packet,
-    enqueue_time: Instant,
-}
 
 #[derive(Debug, Clone)]
 pub struct CoDel {
@@ -17,21 +11,30 @@ pub struct CoDel {
     drop_next: Instant,
     count: u32,
     dropping: bool,
-    queue: VecDeque<QueuedPacket>,
     buffer_size: usize,
+    queue: PacketQueue,
 }
 
+pub const PARAMS: &[StrategyParam] = &[
+    StrategyParam { name: "target_ms", default: Some(5.0), min: 0.0, max: 10_000.0, description: "Acceptable standing queue delay" },
+    StrategyParam { name: "interval_ms", default: Some(100.0), min: 0.001, max: 60_000.0, description: "Window the delay has to stay above target before dropping, roughly a worst case RTT" },
+];
+
 impl CoDel {
     pub fn new(buffer_size: usize) -> Self {
+        Self::with_params(buffer_size, &Params::defaults(PARAMS))
+    }
+
+    pub fn with_params(buffer_size: usize, params: &Params) -> Self {
         Self {
-            target: Duration::from_millis(5),
-            interval: Duration::from_millis(100),
+            target: Duration::from_secs_f64(params.get("target_ms") / 1000.0),
+            interval: Duration::from_secs_f64(params.get("interval_ms") / 1000.0),
             first_above_time: None,
-            drop_next: Instant::now(),
+            drop_next: clock::now(),
             count: 0,
             dropping: false,
-            queue: VecDeque::new(),
             buffer_size,
+            queue: PacketQueue::new(),
         }
     }
 
@@ -40,79 +43,79 @@ impl CoDel {
             self.interval.as_secs_f64() / (self.count as f64).sqrt().max(1.0)
         )
     }
+
+    // Runs once per head packet, dequeue keeps asking until something gets sent
+    fn on_head(&mut self, packet: &Packet, sojourn_time: Duration) -> Action {
+        let now = clock::now();
+
+        // Check if sojourn time is below target
+        if sojourn_time < self.target {
+            self.first_above_time = None;
+            self.dropping = false;
+            return Action::Accept;
+        }
+
+        let Some(first_above_time) = self.first_above_time else {
+            self.first_above_time = Some(now);
+            return Action::Accept;
+        };
+
+        let time_above = now.duration_since(first_above_time);
+
+        if time_above < self.interval {
+            return Action::Accept;
+        }
+
+        if !self.dropping {
+            self.dropping = true;
+            self.count = 1;
+            self.drop_next = now;
+            return Action::signal(packet);
+        }
+
+        // Already in dropping state
+        if now >= self.drop_next {
+            self.count += 1;
+            self.drop_next = now + self.control_law();
+            Action::signal(packet)
+        } else {
+            Action::Accept
+        }
+    }
 }
 
 impl Strategy for CoDel {
-    fn on_enqueue(&mut self, packet: &Packet, _queue_len: usize) -> Action {
+    fn enqueue(&mut self, packet: Packet, events: &mut QueueEvents) {
         if self.queue.len() >= self.buffer_size {
-            return Action::Drop;
+            events.drop(packet);
+        } else {
+            self.queue.push(packet);
         }
-
-        // Always accept and tag with timestamp
-        self.queue.push_back(QueuedPacket {
-            packet: packet.clone(),
-            enqueue_time: Instant::now(),
-        });
-        Action::Accept
     }
 
-    fn on_dequeue(&mut self, _queue_len: usize) {
+    fn dequeue(&mut self, events: &mut QueueEvents) -> Option<Packet> {
         loop {
-            let Some(head) = self.queue.front() else {
+            let Some(head) = self.queue.pop() else {
                 // Queue empty, exit dropping state
                 self.dropping = false;
                 self.first_above_time = None;
-                return;
+                return None;
             };
 
-            let now = Instant::now();
-            let sojourn_time = now.duration_since(head.enqueue_time);
-
-            // Check if sojourn time is below target
-            if sojourn_time < self.target {
-                self.first_above_time = None;
-                self.dropping = false;
-                self.queue.pop_front();
-                return;
-            }
-
-            if self.first_above_time.is_none() {
-                self.first_above_time = Some(now);
-                self.queue.pop_front();
-                return;
-            }
-
-            let time_above = now.duration_since(self.first_above_time.unwrap());
-            
-            if time_above < self.interval {
-                self.queue.pop_front();
-                return;
-            }
-
-            if !self.dropping {
-                self.dropping = true;
-                self.count = 1;
-                self.drop_next = now;
-                self.queue.pop_front(); // DROP the packet
-
-                continue;
-            }
-
-            // Already in dropping state
-            if now >= self.drop_next {
-                self.count += 1;
-                self.drop_next = now + self.control_law();
-                self.queue.pop_front(); // DROP the packet
-                continue;
-            } else {
-                // dequeue normally
-                self.queue.pop_front();
-                return;
+            let action = self.on_head(&head.packet, head.sojourn_time());
+            if let Some(packet) = events.apply(action, head.packet) {
+                if self.queue.is_empty() {
+                    self.dropping = false;
+                    self.first_above_time = None;
+                }
+                return Some(packet);
             }
         }
     }
 
-    fn update(&mut self, _queue_len: usize, _avg_sojourn_ms: f64) { }
+    fn len(&self) -> usize { self.queue.len() }
+    fn bytes(&self) -> u64 { self.queue.bytes() }
+    fn update(&mut self, _avg_sojourn_ms: f64) { }
     fn name(&self) -> &str { "CoDel" }
 
     fn reset(&mut self) {
@@ -123,6 +126,9 @@ impl Strategy for CoDel {
     }
 
     fn clone_box(&self) -> Box<dyn Strategy> {
-        Box::new(Self::new(self.buffer_size))
+        // Fresh state, same parameters
+        let mut fresh = self.clone();
+        fresh.reset();
+        Box::new(fresh)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,196 @@
+// DualPI2 (RFC 9332), the coupled dual-queue AQM behind L4S. ECT(1) and CE packets go to the L queue,
+// everything else to the classic one. One PI controller runs on the queue delay and yields a base probability p':
+// classic packets are dropped (or marked) with p'², L packets are marked with k·p' or whenever their own
+// sojourn passes a small step threshold. Squaring on one side and not the other is what lets scalable and
+// classic senders end up with about the same rate. A time-shifted FIFO between the two keeps L latency low
+// without starving the classic queue.
+
+use super::{Action, PacketQueue, Params, QueueEvents, Strategy, StrategyParam};
+use crate::metrics::QueueStats;
+use crate::network::Packet;
+use crate::simulation::clock;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::time::{Duration, Instant};
+
+// The step only counts with more than this much in the L queue, on a slow link a single packet
+// takes longer than step_ms to leave (RFC 9332 uses 2 MTU too)
+const MIN_STEP_BYTES: u64 = 2 * 1500;
+
+#[derive(Debug, Clone)]
+pub struct DualPi2 {
+    l_queue: PacketQueue,
+    c_queue: PacketQueue,
+    buffer_size: usize,
+    target: Duration,
+    tupdate: Duration,
+    // Per update, already scaled by tupdate
+    alpha: f64,
+    beta: f64,
+    coupling: f64,
+    step: Duration,
+    tshift: Duration,
+    p_prime: f64,
+    qdelay_old: f64,
+    last_update: Instant,
+    l_stats: QueueStats,
+    c_stats: QueueStats,
+    rng: StdRng,
+}
+
+pub const PARAMS: &[StrategyParam] = &[
+    StrategyParam { name: "target_ms", default: Some(15.0), min: 0.0, max: 10_000.0, description: "Queue delay the PI controller aims for" },
+    StrategyParam { name: "tupdate_ms", default: Some(16.0), min: 0.001, max: 10_000.0, description: "How often the base probability gets recalculated" },
+    StrategyParam { name: "alpha", default: Some(0.16), min: 0.0, max: 100.0, description: "Integral gain in Hz" },
+    StrategyParam { name: "beta", default: Some(3.2), min: 0.0, max: 1000.0, description: "Proportional gain in Hz" },
+    StrategyParam { name: "coupling", default: Some(2.0), min: 0.0, max: 100.0, description: "k, L marking probability is k times the base probability" },
+    StrategyParam { name: "step_ms", default: Some(1.0), min: 0.0, max: 10_000.0, description: "L packets waiting longer than this always get marked" },
+    StrategyParam { name: "tshift_ms", default: Some(40.0), min: 0.0, max: 10_000.0, description: "Head start of the L queue in the time-shifted FIFO" },
+];
+
+impl DualPi2 {
+    pub fn new(buffer_size: usize) -> Self {
+        Self::with_params(buffer_size, &Params::defaults(PARAMS))
+    }
+
+    pub fn with_params(buffer_size: usize, params: &Params) -> Self {
+        let tupdate = Duration::from_secs_f64(params.get("tupdate_ms") / 1000.0);
+        let ms = |name: &str| Duration::from_secs_f64(params.get(name) / 1000.0);
+        Self {
+            l_queue: PacketQueue::new(),
+            c_queue: PacketQueue::new(),
+            buffer_size,
+            target: ms("target_ms"),
+            tupdate,
+            alpha: params.get("alpha") * tupdate.as_secs_f64(),
+            beta: params.get("beta") * tupdate.as_secs_f64(),
+            coupling: params.get("coupling"),
+            step: ms("step_ms"),
+            tshift: ms("tshift_ms"),
+            p_prime: 0.0,
+            qdelay_old: 0.0,
+            last_update: clock::now(),
+            l_stats: QueueStats { name: "l4s", ..Default::default() },
+            c_stats: QueueStats { name: "classic", ..Default::default() },
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    fn roll(&mut self, p: f64) -> bool {
+        p > 0.0 && self.rng.r#gen::<f64>() < p
+    }
+
+    // Step threshold on the packet's own sojourn, or the coupled probability from the classic side.
+    // Once k·p' reaches 1 the L senders aren't keeping up, they get dropped like classic traffic
+    fn l_action(&mut self, packet: &Packet, sojourn: Duration, backlog: u64) -> Action {
+        let p_cl = self.coupling * self.p_prime;
+        if p_cl >= 1.0 {
+            return if self.roll(self.p_prime * self.p_prime) { Action::Drop } else { Action::Accept };
+        }
+        if (sojourn >= self.step && backlog > MIN_STEP_BYTES) || self.roll(p_cl) {
+            Action::signal(packet)
+        } else {
+            Action::Accept
+        }
+    }
+
+    fn c_action(&mut self, packet: &Packet) -> Action {
+        if self.roll(self.p_prime * self.p_prime) {
+            Action::signal(packet)
+        } else {
+            Action::Accept
+        }
+    }
+}
+
+impl Strategy for DualPi2 {
+    fn enqueue(&mut self, packet: Packet, events: &mut QueueEvents) {
+        let l4s = packet.ecn.is_l4s();
+        if self.l_queue.len() + self.c_queue.len() >= self.buffer_size {
+            if l4s { self.l_stats.dropped += 1 } else { self.c_stats.dropped += 1 }
+            events.drop(packet);
+        } else if l4s {
+            self.l_queue.push(packet);
+        } else {
+            self.c_queue.push(packet);
+        }
+    }
+
+    fn dequeue(&mut self, events: &mut QueueEvents) -> Option<Packet> {
+        loop {
+            // Time-shifted FIFO: the L head goes first unless the classic head has waited tshift longer
+            let use_l = match (self.l_queue.front(), self.c_queue.front()) {
+                (None, None) => return None,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (Some(l), Some(c)) => l.sojourn_time() + self.tshift >= c.sojourn_time(),
+            };
+
+            let head = if use_l { self.l_queue.pop()? } else { self.c_queue.pop()? };
+            let sojourn = head.sojourn_time();
+            let backlog = self.l_queue.bytes() + head.packet.payload_size as u64;
+            let action = if use_l { self.l_action(&head.packet, sojourn, backlog) } else { self.c_action(&head.packet) };
+
+            let marked = events.marked;
+            let packet = events.apply(action, head.packet);
+            let stats = if use_l { &mut self.l_stats } else { &mut self.c_stats };
+            stats.marked += events.marked - marked;
+            match packet {
+                Some(packet) => {
+                    stats.dequeued += 1;
+                    stats.total_sojourn_ms += sojourn.as_secs_f64() * 1000.0;
+                    return Some(packet);
+                }
+                None => stats.dropped += 1,
+            }
+        }
+    }
+
+    fn len(&self) -> usize { self.l_queue.len() + self.c_queue.len() }
+    fn bytes(&self) -> u64 { self.l_queue.bytes() + self.c_queue.bytes() }
+
+    // PI on the worse of the two queue delays, every tupdate
+    fn update(&mut self, _avg_sojourn_ms: f64) {
+        let now = clock::now();
+        if now.duration_since(self.last_update) < self.tupdate {
+            return;
+        }
+
+        let qdelay = self.l_queue.head_sojourn().max(self.c_queue.head_sojourn()).as_secs_f64();
+        let target = self.target.as_secs_f64();
+        self.p_prime += self.alpha * (qdelay - target) + self.beta * (qdelay - self.qdelay_old);
+        self.p_prime = self.p_prime.clamp(0.0, 1.0);
+        self.qdelay_old = qdelay;
+        self.last_update = now;
+    }
+
+    fn name(&self) -> &str { "DualPI2" }
+
+    fn reset(&mut self) {
+        self.l_queue.clear();
+        self.c_queue.clear();
+        self.p_prime = 0.0;
+        self.qdelay_old = 0.0;
+        self.last_update = clock::now();
+        self.l_stats = QueueStats { name: self.l_stats.name, ..Default::default() };
+        self.c_stats = QueueStats { name: self.c_stats.name, ..Default::default() };
+    }
+
+    fn clone_box(&self) -> Box<dyn Strategy> {
+        // Fresh state, same parameters
+        let mut fresh = self.clone();
+        fresh.reset();
+        Box::new(fresh)
+    }
+
+    fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    fn queue_stats(&self) -> Vec<QueueStats> {
+        vec![
+            QueueStats { length: self.l_queue.len(), ..self.l_stats },
+            QueueStats { length: self.c_queue.len(), ..self.c_stats },
+        ]
+    }
+}
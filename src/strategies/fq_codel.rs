@@ -1,6 +1,7 @@
-use super::{Action, Strategy};
+use super::{PacketQueue, Params, QueueEvents, Strategy, StrategyParam};
 use crate::network::Packet;
-use std::collections::{HashMap, VecDeque};
+use crate::simulation::clock;
+use std::collections::{BTreeMap, HashMap};
 use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone)]
@@ -15,46 +16,59 @@ impl FlowState {
     fn new() -> Self {
         Self {
             first_above_time: None,
-            drop_next: Instant::now(),
+            drop_next: clock::now(),
             count: 0,
             dropping: false,
         }
     }
 }
 
-#[derive(Debug, Clone)]
-struct QueuedPacket {
-    packet: Packet,
-    flow_id: u32,
-    enqueue_time: Instant,
-}
-
 #[derive(Debug)]
 pub struct FqCoDel {
     num_flows: usize,
     flow_states: HashMap<u32, FlowState>,
-    flow_queues: HashMap<u32, VecDeque<QueuedPacket>>,
+    // Ordered so the round robin can pick up after the last flow served
+    flow_queues: BTreeMap<u32, PacketQueue>,
+    queued: usize,
     buffer_size: usize,
     target: Duration,
     interval: Duration,
     last_dequeue_flow: u32,
+    // Mixed into the flow hash. Set by hand or taken from the run's seed, so which flows collide changes with it
+    perturbation: u64,
+    fixed_perturbation: bool,
 }
 
+pub const PARAMS: &[StrategyParam] = &[
+    StrategyParam { name: "flows", default: Some(1024.0), min: 1.0, max: 65_536.0, description: "Number of flow buckets" },
+    super::codel::PARAMS[0],
+    super::codel::PARAMS[1],
+    StrategyParam { name: "perturbation", default: None, min: 0.0, max: u32::MAX as f64, description: "Salt for the flow hash, auto follows the run's seed" },
+];
+
 impl FqCoDel {
     pub fn new(buffer_size: usize) -> Self {
+        Self::with_params(buffer_size, &Params::defaults(PARAMS))
+    }
+
+    pub fn with_params(buffer_size: usize, params: &Params) -> Self {
         Self {
-            num_flows: 1024,
+            num_flows: params.get("flows") as usize,
             flow_states: HashMap::new(),
-            flow_queues: HashMap::new(),
+            flow_queues: BTreeMap::new(),
+            queued: 0,
             buffer_size,
-            target: Duration::from_millis(5),
-            interval: Duration::from_millis(100),
+            target: Duration::from_secs_f64(params.get("target_ms") / 1000.0),
+            interval: Duration::from_secs_f64(params.get("interval_ms") / 1000.0),
             last_dequeue_flow: 0,
+            perturbation: params.get_opt("perturbation").unwrap_or(0.0) as u64,
+            fixed_perturbation: params.get_opt("perturbation").is_some(),
         }
     }
 
-    fn hash_flow(packet: &Packet) -> u32 {
-        packet.source_agent % 1024
+    // The whole flow key (agent, stream, destination, protocol), so an agent's flows get a bucket each
+    fn hash_flow(&self, packet: &Packet) -> u32 {
+        (packet.flow.hash(self.perturbation) % self.num_flows as u64) as u32
     }
 
     fn control_law(&self, count: u32) -> Duration {
@@ -63,21 +77,11 @@ impl FqCoDel {
         )
     }
 
-    fn total_queue_length(&self) -> usize {
-        self.flow_queues.values().map(|q| q.len()).sum()
-    }
-
-    fn flow_queue_length(&self, flow_id: u32) -> usize {
-        self.flow_queues.get(&flow_id).map(|q| q.len()).unwrap_or(0)
-    }
-
     fn get_sojourn_time(&self, flow_id: u32) -> Duration {
-        if let Some(queue) = self.flow_queues.get(&flow_id) {
-            if let Some(oldest) = queue.front() {
-                return oldest.enqueue_time.elapsed();
-            }
-        }
-        Duration::from_millis(0)
+        self.flow_queues
+            .get(&flow_id)
+            .map(|queue| queue.head_sojourn())
+            .unwrap_or(Duration::ZERO)
     }
 
     // same as standalone CoDel
@@ -113,8 +117,6 @@ impl FqCoDel {
             if now >= state.drop_next {
                 state.count += 1;
                 let count = state.count;
-                let interval = self.interval;
-                drop(state);
                 let control_duration = self.control_law(count);
                 let state = self.flow_states.get_mut(&flow_id).unwrap();
                 state.drop_next = now + control_duration;
@@ -126,59 +128,50 @@ impl FqCoDel {
     }
 }
 
+// The original FlockNet take on FQ-CoDel: CoDel decides at enqueue on the flow's head sojourn,
+// flows get served one packet each in round robin. Not what RFC 8290 describes, fq_codel_rfc is.
+// Kept as "fq-codel" so earlier results can be reproduced
 impl Strategy for FqCoDel {
-    fn on_enqueue(&mut self, packet: &Packet, _queue_len: usize) -> Action {
-        let flow_id = Self::hash_flow(packet);
-        let now = Instant::now();
+    fn enqueue(&mut self, packet: Packet, events: &mut QueueEvents) {
+        let flow_id = self.hash_flow(&packet);
+        let now = clock::now();
         
-        if self.total_queue_length() >= self.buffer_size {
-            return Action::Drop;
+        if self.queued >= self.buffer_size {
+            events.drop(packet);
+            return;
         }
 
         let sojourn_time = self.get_sojourn_time(flow_id);
 
         // Apply CoDel algorithm per flow
         if self.should_drop_flow(flow_id, sojourn_time, now) {
-            Action::Drop
+            events.drop(packet);
         } else {
-            let queued_packet = QueuedPacket {
-                packet: packet.clone(),
-                flow_id,
-                enqueue_time: now,
-            };
             self.flow_queues
                 .entry(flow_id)
-                .or_insert_with(VecDeque::new)
-                .push_back(queued_packet);
-            Action::Accept
+                .or_default()
+                .push(packet);
+            self.queued += 1;
         }
     }
 
-    fn on_dequeue(&mut self, _queue_len: usize) {
-        let mut flow_ids: Vec<u32> = self.flow_queues.keys().copied().collect();
-        if flow_ids.is_empty() {
-            return;
-        }
-        
-        flow_ids.sort();
-        
-        // find start position (after last dequeued flow)
-        let start_pos = flow_ids.iter().position(|&id| id > self.last_dequeue_flow).unwrap_or(0);
+    fn dequeue(&mut self, _events: &mut QueueEvents) -> Option<Packet> {
+        // first flow after the last dequeued one, wrapping around
+        let flow_id = self.flow_queues.range(self.last_dequeue_flow + 1..)
+            .chain(self.flow_queues.range(..=self.last_dequeue_flow))
+            .find(|(_, queue)| !queue.is_empty())
+            .map(|(&id, _)| id)?;
         
-        flow_ids.rotate_left(start_pos);
-        
-        for flow_id in flow_ids {
-            if let Some(queue) = self.flow_queues.get_mut(&flow_id) {
-                if !queue.is_empty() {
-                    queue.pop_front();
-                    self.last_dequeue_flow = flow_id;
-                    break;
-                }
-            }
-        }
+        let queued = self.flow_queues.get_mut(&flow_id)?.pop()?;
+        self.queued -= 1;
+        self.last_dequeue_flow = flow_id;
+        Some(queued.packet)
     }
 
-    fn update(&mut self, _queue_len: usize, _avg_sojourn_ms: f64) {
+    fn len(&self) -> usize { self.queued }
+    fn bytes(&self) -> u64 { self.flow_queues.values().map(|q| q.bytes()).sum() }
+
+    fn update(&mut self, _avg_sojourn_ms: f64) {
         // Cleanup empty flows to prevent memory leak (redundant)
         self.flow_states.retain(|_, state| state.dropping || state.first_above_time.is_some());
         self.flow_queues.retain(|_, queue| !queue.is_empty());
@@ -189,10 +182,29 @@ impl Strategy for FqCoDel {
     fn reset(&mut self) {
         self.flow_states.clear();
         self.flow_queues.clear();
+        self.queued = 0;
         self.last_dequeue_flow = 0;
     }
 
     fn clone_box(&self) -> Box<dyn Strategy> {
-        Box::new(Self::new(self.buffer_size))
+        // Fresh state, same parameters
+        Box::new(Self {
+            num_flows: self.num_flows,
+            flow_states: HashMap::new(),
+            flow_queues: BTreeMap::new(),
+            queued: 0,
+            buffer_size: self.buffer_size,
+            target: self.target,
+            interval: self.interval,
+            last_dequeue_flow: 0,
+            perturbation: self.perturbation,
+            fixed_perturbation: self.fixed_perturbation,
+        })
+    }
+
+    fn reseed(&mut self, seed: u64) {
+        if !self.fixed_perturbation {
+            self.perturbation = seed;
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,291 @@
+// FQ-CoDel as RFC 8290 describes it, next to the older FlockNet variant in fq_codel.rs.
+// Packets hash into flow buckets, flows are served by deficit round robin with a byte quantum, and newly
+// active flows get a turn before the ones that have been backlogged for a while. Every flow runs its own
+// CoDel (RFC 8289) when its head leaves, and a full buffer drops from whichever flow holds the most bytes.
+
+use super::{Action, PacketQueue, Params, QueueEvents, Strategy, StrategyParam};
+use crate::network::Packet;
+use crate::simulation::clock;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// A queue holding no more than this is never dropped from, a single packet can't be a standing queue
+const MTU: u64 = 1500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum List {
+    New,
+    Old,
+}
+
+/// RFC 8289 CoDel for one flow's queue, CAKE keeps one per flow too
+#[derive(Debug, Clone, Default)]
+pub(super) struct CoDelState {
+    // When the head sojourn went above target, plus interval
+    first_above_time: Option<Instant>,
+    drop_next: Option<Instant>,
+    count: u32,
+    lastcount: u32,
+    dropping: bool,
+}
+
+impl CoDelState {
+    fn control_law(t: Instant, interval: Duration, count: u32) -> Instant {
+        t + interval.div_f64((count as f64).sqrt().max(1.0))
+    }
+
+    fn drop_due(&self, now: Instant) -> bool {
+        self.drop_next.is_none_or(|next| now >= next)
+    }
+
+    // dodequeue from RFC 8289: the head and whether CoDel would be allowed to drop it
+    fn pop_head(&mut self, queue: &mut PacketQueue, target: Duration, interval: Duration, now: Instant) -> Option<(Packet, bool)> {
+        let Some(head) = queue.pop() else {
+            self.first_above_time = None;
+            return None;
+        };
+
+        let mut ok_to_drop = false;
+        if head.sojourn_time() < target || queue.bytes() <= MTU {
+            self.first_above_time = None;
+        } else {
+            match self.first_above_time {
+                None => self.first_above_time = Some(now + interval),
+                Some(first_above) => ok_to_drop = now >= first_above,
+            }
+        }
+        Some((head.packet, ok_to_drop))
+    }
+
+    /// Runs when the flow's head leaves. Drops (or marks) until a packet may go, None once the queue is empty
+    pub(super) fn dequeue(
+        &mut self,
+        queue: &mut PacketQueue,
+        target: Duration,
+        interval: Duration,
+        events: &mut QueueEvents,
+    ) -> Option<Packet> {
+        let now = clock::now();
+        let Some((mut packet, mut ok_to_drop)) = self.pop_head(queue, target, interval, now) else {
+            self.dropping = false;
+            return None;
+        };
+
+        if self.dropping {
+            if !ok_to_drop {
+                self.dropping = false;
+                return Some(packet);
+            }
+            while self.dropping && self.drop_due(now) {
+                self.count += 1;
+                let drop_next = self.drop_next.unwrap_or(now);
+                // A mark lets the packet through, only a drop has to look at the next head
+                match events.apply(Action::signal(&packet), packet) {
+                    Some(marked) => {
+                        self.drop_next = Some(Self::control_law(drop_next, interval, self.count));
+                        return Some(marked);
+                    }
+                    None => {
+                        let Some(next) = self.pop_head(queue, target, interval, now) else {
+                            self.dropping = false;
+                            return None;
+                        };
+                        (packet, ok_to_drop) = next;
+                        if ok_to_drop {
+                            self.drop_next = Some(Self::control_law(drop_next, interval, self.count));
+                        } else {
+                            self.dropping = false;
+                        }
+                    }
+                }
+            }
+            return Some(packet);
+        }
+
+        if ok_to_drop {
+            let next = match events.apply(Action::signal(&packet), packet) {
+                Some(marked) => Some(marked),
+                None => self.pop_head(queue, target, interval, now).map(|(next, _)| next),
+            };
+            self.enter_dropping(interval, now);
+            return next;
+        }
+        Some(packet)
+    }
+
+    // Picks the count up where the last dropping spell left it if that was recent, so a flow that
+    // keeps coming back gets hit harder right away
+    fn enter_dropping(&mut self, interval: Duration, now: Instant) {
+        self.dropping = true;
+        let delta = self.count.saturating_sub(self.lastcount);
+        let was_recent = self.drop_next.is_some_and(|next| now.saturating_duration_since(next) < 16 * interval);
+        self.count = if delta > 1 && was_recent { delta } else { 1 };
+        self.lastcount = self.count;
+        self.drop_next = Some(Self::control_law(now, interval, self.count));
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Flow {
+    queue: PacketQueue,
+    deficit: i64,
+    // Which list the flow is on, None while it's idle
+    list: Option<List>,
+    codel: CoDelState,
+}
+
+#[derive(Debug, Clone)]
+pub struct FqCoDelRfc {
+    flows: Vec<Flow>,
+    new_flows: VecDeque<usize>,
+    old_flows: VecDeque<usize>,
+    queued: usize,
+    buffer_size: usize,
+    quantum: i64,
+    target: Duration,
+    interval: Duration,
+    // Same as in the legacy variant, a fixed value or the run's seed
+    perturbation: u64,
+    fixed_perturbation: bool,
+}
+
+pub const PARAMS: &[StrategyParam] = &[
+    super::fq_codel::PARAMS[0],
+    super::codel::PARAMS[0],
+    super::codel::PARAMS[1],
+    StrategyParam { name: "quantum", default: Some(1514.0), min: 64.0, max: 65_536.0, description: "Bytes a flow may send per round" },
+    super::fq_codel::PARAMS[3],
+];
+
+impl FqCoDelRfc {
+    pub fn new(buffer_size: usize) -> Self {
+        Self::with_params(buffer_size, &Params::defaults(PARAMS))
+    }
+
+    pub fn with_params(buffer_size: usize, params: &Params) -> Self {
+        Self {
+            flows: vec![Flow::default(); params.get("flows") as usize],
+            new_flows: VecDeque::new(),
+            old_flows: VecDeque::new(),
+            queued: 0,
+            buffer_size,
+            quantum: params.get("quantum") as i64,
+            target: Duration::from_secs_f64(params.get("target_ms") / 1000.0),
+            interval: Duration::from_secs_f64(params.get("interval_ms") / 1000.0),
+            perturbation: params.get_opt("perturbation").unwrap_or(0.0) as u64,
+            fixed_perturbation: params.get_opt("perturbation").is_some(),
+        }
+    }
+
+    fn hash_flow(&self, packet: &Packet) -> usize {
+        (packet.flow.hash(self.perturbation) % self.flows.len() as u64) as usize
+    }
+
+    // Takes the flow at the front of `list` off it
+    fn pop_list(&mut self, list: List) {
+        match list {
+            List::New => self.new_flows.pop_front(),
+            List::Old => self.old_flows.pop_front(),
+        };
+    }
+
+    // Overflow: the head of the flow with the most bytes queued goes
+    fn drop_from_fattest(&mut self, events: &mut QueueEvents) {
+        let Some(fattest) = self.new_flows.iter()
+            .chain(&self.old_flows)
+            .copied()
+            .max_by_key(|&f| self.flows[f].queue.bytes())
+        else {
+            return;
+        };
+        if let Some(head) = self.flows[fattest].queue.pop() {
+            self.queued -= 1;
+            events.drop(head.packet);
+        }
+    }
+}
+
+impl Strategy for FqCoDelRfc {
+    fn enqueue(&mut self, packet: Packet, events: &mut QueueEvents) {
+        let flow = self.hash_flow(&packet);
+        self.flows[flow].queue.push(packet);
+        self.queued += 1;
+
+        if self.flows[flow].list.is_none() {
+            self.flows[flow].list = Some(List::New);
+            self.flows[flow].deficit = self.quantum;
+            self.new_flows.push_back(flow);
+        }
+
+        if self.queued > self.buffer_size {
+            self.drop_from_fattest(events);
+        }
+    }
+
+    fn dequeue(&mut self, events: &mut QueueEvents) -> Option<Packet> {
+        loop {
+            // New flows first, that's what keeps sparse flows (a ping, a command) fast
+            let (flow, list) = match self.new_flows.front() {
+                Some(&flow) => (flow, List::New),
+                None => (*self.old_flows.front()?, List::Old),
+            };
+
+            if self.flows[flow].deficit <= 0 {
+                // Out of quantum, back of the old list with a fresh one
+                self.flows[flow].deficit += self.quantum;
+                self.pop_list(list);
+                self.flows[flow].list = Some(List::Old);
+                self.old_flows.push_back(flow);
+                continue;
+            }
+
+            let Flow { queue, codel, .. } = &mut self.flows[flow];
+            let before = queue.len();
+            let packet = codel.dequeue(queue, self.target, self.interval, events);
+            self.queued -= before - queue.len();
+
+            let Some(packet) = packet else {
+                // Emptied out. A new flow goes to the old list once so it can't game its way to the front,
+                // an old flow becomes idle
+                self.pop_list(list);
+                if list == List::New && !self.old_flows.is_empty() {
+                    self.flows[flow].list = Some(List::Old);
+                    self.old_flows.push_back(flow);
+                } else {
+                    self.flows[flow].list = None;
+                }
+                continue;
+            };
+
+            self.flows[flow].deficit -= packet.payload_size as i64;
+            return Some(packet);
+        }
+    }
+
+    fn len(&self) -> usize { self.queued }
+    fn bytes(&self) -> u64 {
+        self.new_flows.iter().chain(&self.old_flows).map(|&f| self.flows[f].queue.bytes()).sum()
+    }
+    fn update(&mut self, _avg_sojourn_ms: f64) { }
+    fn name(&self) -> &str { "FQ-CoDel (RFC 8290)" }
+
+    fn reset(&mut self) {
+        self.flows.iter_mut().for_each(|f| *f = Flow::default());
+        self.new_flows.clear();
+        self.old_flows.clear();
+        self.queued = 0;
+    }
+
+    fn clone_box(&self) -> Box<dyn Strategy> {
+        // Fresh state, same parameters
+        let mut fresh = self.clone();
+        fresh.reset();
+        Box::new(fresh)
+    }
+
+    fn reseed(&mut self, seed: u64) {
+        if !self.fixed_perturbation {
+            self.perturbation = seed;
+        }
+    }
+}
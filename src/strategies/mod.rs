@@ -4,66 +4,169 @@ pub mod blue;
 pub mod codel;
 pub mod pie;
 pub mod fq_codel;
+pub mod fq_codel_rfc;
+pub mod cake;
+pub mod dualpi2;
+pub mod priority;
 pub mod template;
+pub mod queue;
+pub mod params;
 
+pub use queue::{PacketQueue, QueueEvents, QueuedPacket};
+pub use params::{ParamOverride, Params, StrategyParam};
+
+use crate::metrics::QueueStats;
 use crate::network::Packet;
+use anyhow::Result;
 use std::fmt;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Action {
     Accept,
     Drop,
-    Mark,
+    Mark, // Set CE instead of dropping, only valid for ECN-capable packets
+}
+
+impl Action {
+    /// Congestion signal for a packet, a mark if it can carry one and a drop if it can't
+    pub fn signal(packet: &Packet) -> Self {
+        if packet.ecn.is_ect() {
+            Action::Mark
+        } else {
+            Action::Drop
+        }
+    }
 }
 
+/// A qdisc: the strategy owns the queue. The server hands packets to `enqueue` and sends
+/// whatever `dequeue` gives back, anything dropped or marked along the way goes through `events`.
 pub trait Strategy: Send + Sync + fmt::Debug {
-    fn on_enqueue(&mut self, packet: &Packet, queue_len: usize) -> Action;
-    fn on_dequeue(&mut self, queue_len: usize);
-    fn update(&mut self, queue_len: usize, avg_sojourn_ms: f64);
+    fn enqueue(&mut self, packet: Packet, events: &mut QueueEvents);
+    
+    /// Next packet for the wire, None if there's nothing to send. Sojourn based AQMs (CoDel)
+    /// may drop a few heads before returning one.
+    fn dequeue(&mut self, events: &mut QueueEvents) -> Option<Packet>;
+    fn len(&self) -> usize;
+    fn bytes(&self) -> u64;
+    fn is_empty(&self) -> bool { self.len() == 0 }
+    
+    /// Called every few transmission slots with the recent average sojourn time
+    fn update(&mut self, avg_sojourn_ms: f64);
     fn name(&self) -> &str;
     fn reset(&mut self);
     fn clone_box(&self) -> Box<dyn Strategy>;
+    
+    /// Hands the strategy its own RNG stream, only matters for strategies that roll dice
+    fn reseed(&mut self, _seed: u64) { }
+    
+    /// Rate of the link the queue drains into, only matters for strategies with their own shaper
+    fn set_link_rate(&mut self, _bandwidth_bps: u64) { }
+    
+    /// Counters per internal queue for strategies that keep several side by side, empty for the rest
+    fn queue_stats(&self) -> Vec<QueueStats> { Vec::new() }
+}
+
+type StrategyFactory = Box<dyn Fn(usize, &Params) -> Box<dyn Strategy> + Send + Sync>;
+
+struct Entry {
+    params: &'static [StrategyParam],
+    factory: StrategyFactory,
 }
 
 pub struct StrategyRegistry {
-    strategies: HashMap<String, Box<dyn Fn(usize) -> Box<dyn Strategy> + Send + Sync>>,
+    strategies: HashMap<String, Entry>,
+    // Alternative spellings, alias -> name
+    aliases: HashMap<String, String>,
 }
 
 impl StrategyRegistry {
     pub fn new() -> Self {
         let mut registry = Self {
             strategies: HashMap::new(),
+            aliases: HashMap::new(),
         };
         registry.register_builtin();
         registry
     }
     
     fn register_builtin(&mut self) {
-        self.register("drop-tail", |size| Box::new(static_strategies::DropTail::new(size)));
-        self.register("droptail", |size| Box::new(static_strategies::DropTail::new(size)));
-        self.register("fifo", |size| Box::new(static_strategies::Fifo::new(size)));
-        self.register("red", |size| Box::new(red::Red::new(size)));
-        self.register("adaptive-red", |size| Box::new(red::AdaptiveRed::new(size)));
-        self.register("ared", |size| Box::new(red::AdaptiveRed::new(size)));
-        self.register("blue", |size| Box::new(blue::Blue::new(size)));
-        self.register("codel", |buffer_size| Box::new(codel::CoDel::new(buffer_size)));
-        self.register("pie", |_| Box::new(pie::Pie::new()));
-        self.register("fq-codel", |size| Box::new(fq_codel::FqCoDel::new(size)));
-        self.register("fqcodel", |size| Box::new(fq_codel::FqCoDel::new(size)));
-    }
-    
-    pub fn register<F>(&mut self, name: &str, factory: F)
+        self.register("drop-tail", &[], |size, _| Box::new(static_strategies::DropTail::new(size)));
+        self.alias("droptail", "drop-tail");
+        self.register("fifo", &[], |size, _| Box::new(static_strategies::Fifo::new(size)));
+        self.register("red", red::PARAMS, |size, params| Box::new(red::Red::with_params(size, params)));
+        self.register("adaptive-red", red::ADAPTIVE_PARAMS, |size, params| Box::new(red::AdaptiveRed::with_params(size, params)));
+        self.alias("ared", "adaptive-red");
+        self.register("blue", blue::PARAMS, |size, params| Box::new(blue::Blue::with_params(size, params)));
+        self.register("codel", codel::PARAMS, |buffer_size, params| Box::new(codel::CoDel::with_params(buffer_size, params)));
+        self.register("pie", pie::PARAMS, |_, params| Box::new(pie::Pie::with_params(params)));
+        self.register("fq-codel", fq_codel::PARAMS, |size, params| Box::new(fq_codel::FqCoDel::with_params(size, params)));
+        self.alias("fqcodel", "fq-codel");
+        self.register("fq-codel-rfc", fq_codel_rfc::PARAMS, |size, params| Box::new(fq_codel_rfc::FqCoDelRfc::with_params(size, params)));
+        self.alias("rfc8290", "fq-codel-rfc");
+        self.register("cake", cake::PARAMS, |size, params| Box::new(cake::Cake::with_params(size, params)));
+        self.register("dualpi2", dualpi2::PARAMS, |size, params| Box::new(dualpi2::DualPi2::with_params(size, params)));
+        self.alias("l4s", "dualpi2");
+        self.register("prio", priority::PRIO_PARAMS, |size, params| Box::new(priority::StrictPriority::with_params(size, params)));
+        self.alias("strict-priority", "prio");
+        self.register("drr", priority::DRR_PARAMS, |size, params| Box::new(priority::Drr::with_params(size, params)));
+        self.alias("wrr", "drr");
+        self.register("drop-precedence", priority::DROP_PRECEDENCE_PARAMS, |size, params| Box::new(priority::DropPrecedence::with_params(size, params)));
+        self.alias("dp", "drop-precedence");
+    }
+    
+    /// `params` is the schema, the factory only ever sees values that passed it
+    pub fn register<F>(&mut self, name: &str, params: &'static [StrategyParam], factory: F)
     where
-        F: Fn(usize) -> Box<dyn Strategy> + Send + Sync + 'static,
+        F: Fn(usize, &Params) -> Box<dyn Strategy> + Send + Sync + 'static,
     {
-        self.strategies.insert(name.to_lowercase(), Box::new(factory));
+        self.strategies.insert(name.to_lowercase(), Entry { params, factory: Box::new(factory) });
+    }
+    
+    pub fn alias(&mut self, alias: &str, name: &str) {
+        self.aliases.insert(alias.to_lowercase(), name.to_lowercase());
     }
     
+    /// The registered name behind `name`, resolving aliases
+    pub fn canonical(&self, name: &str) -> Option<&str> {
+        let name = name.to_lowercase();
+        let name = self.aliases.get(&name).unwrap_or(&name);
+        self.strategies.get_key_value(name).map(|(key, _)| key.as_str())
+    }
+    
+    fn entry(&self, name: &str) -> Option<&Entry> {
+        self.canonical(name).and_then(|name| self.strategies.get(name))
+    }
+    
+    /// With every parameter at its default
     pub fn create(&self, name: &str, buffer_size: usize) -> Option<Box<dyn Strategy>> {
-        self.strategies
-            .get(&name.to_lowercase())
-            .map(|factory| factory(buffer_size))
+        self.create_with(name, buffer_size, &BTreeMap::new()).ok()
+    }
+    
+    /// Fails on unknown strategies, unknown parameters and values out of range
+    pub fn create_with(
+        &self,
+        name: &str,
+        buffer_size: usize,
+        params: &BTreeMap<String, f64>,
+    ) -> Result<Box<dyn Strategy>> {
+        let entry = self.entry(name).ok_or_else(|| anyhow::anyhow!("Unknown strategy: {}", name))?;
+        let params = Params::resolve(name, entry.params, params)?;
+        Ok((entry.factory)(buffer_size, &params))
+    }
+    
+    pub fn params(&self, name: &str) -> Option<&'static [StrategyParam]> {
+        self.entry(name).map(|entry| entry.params)
+    }
+    
+    /// Other names `name` is known by
+    pub fn aliases(&self, name: &str) -> Vec<String> {
+        let mut aliases: Vec<String> = self.aliases.iter()
+            .filter(|(_, target)| target.as_str() == name)
+            .map(|(alias, _)| alias.clone())
+            .collect();
+        aliases.sort();
+        aliases
     }
     
     pub fn list(&self) -> Vec<String> {
@@ -79,10 +182,16 @@ impl StrategyRegistry {
     }
 }
 
+impl Default for StrategyRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct StrategyBuilder {
     name: String,
     buffer_size: usize,
-    params: HashMap<String, f64>,
+    params: BTreeMap<String, f64>,
 }
 
 impl StrategyBuilder {
@@ -90,7 +199,7 @@ impl StrategyBuilder {
         Self {
             name: name.into(),
             buffer_size: 1024,
-            params: HashMap::new(),
+            params: BTreeMap::new(),
         }
     }
     
@@ -104,7 +213,12 @@ impl StrategyBuilder {
         self
     }
     
-    pub fn build(self) -> Option<Box<dyn Strategy>> {
-        StrategyRegistry::global().create(&self.name, self.buffer_size)
+    pub fn params(mut self, params: &BTreeMap<String, f64>) -> Self {
+        self.params.extend(params.iter().map(|(k, v)| (k.clone(), *v)));
+        self
+    }
+    
+    pub fn build(self) -> Result<Box<dyn Strategy>> {
+        StrategyRegistry::global().create_with(&self.name, self.buffer_size, &self.params)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,105 @@
+// Tunable strategy parameters. Every strategy publishes a schema (name, default, range, description),
+// the registry checks whatever the user passed against it before the factory ever sees the values.
+
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+/// One knob of a strategy. Durations are in milliseconds, rates in Mbps
+#[derive(Debug, Clone, Copy)]
+pub struct StrategyParam {
+    pub name: &'static str,
+    // None when the default depends on something else, usually the buffer size
+    pub default: Option<f64>,
+    pub min: f64,
+    pub max: f64,
+    pub description: &'static str,
+}
+
+impl fmt::Display for StrategyParam {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let default = self.default.map(|d| d.to_string()).unwrap_or_else(|| "auto".to_string());
+        write!(f, "{:<14} {:>8}  [{}, {}]  {}", self.name, default, self.min, self.max, self.description)
+    }
+}
+
+/// Checked parameter values, what a factory gets
+#[derive(Debug, Clone, Default)]
+pub struct Params {
+    values: HashMap<&'static str, f64>,
+}
+
+impl Params {
+    /// Unknown names and values out of range are errors, the rest falls back to the schema defaults
+    pub fn resolve(strategy: &str, schema: &[StrategyParam], given: &BTreeMap<String, f64>) -> Result<Self> {
+        for (key, value) in given {
+            let Some(param) = schema.iter().find(|p| p.name == key) else {
+                let known: Vec<&str> = schema.iter().map(|p| p.name).collect();
+                if known.is_empty() {
+                    anyhow::bail!("Strategy {} has no parameters, got {}", strategy, key);
+                }
+                anyhow::bail!("Unknown parameter {} for {} (known: {})", key, strategy, known.join(", "));
+            };
+            anyhow::ensure!(
+                (param.min..=param.max).contains(value),
+                "{}.{} = {} is outside [{}, {}]",
+                strategy,
+                key,
+                value,
+                param.min,
+                param.max
+            );
+        }
+
+        let values = schema.iter()
+            .filter_map(|p| given.get(p.name).copied().or(p.default).map(|v| (p.name, v)))
+            .collect();
+        Ok(Self { values })
+    }
+
+    /// Every default in the schema, what `Strategy::new` constructors use
+    pub fn defaults(schema: &[StrategyParam]) -> Self {
+        Self {
+            values: schema.iter().filter_map(|p| p.default.map(|v| (p.name, v))).collect(),
+        }
+    }
+
+    // Only for values that come from the code itself, anything user supplied goes through resolve
+    pub(crate) fn set(&mut self, name: &'static str, value: f64) {
+        self.values.insert(name, value);
+    }
+
+    /// A parameter with a fixed default, panics on names that aren't in the schema
+    pub fn get(&self, name: &str) -> f64 {
+        self.values[name]
+    }
+
+    /// For parameters with an `auto` default, None if the user didn't set it
+    pub fn get_opt(&self, name: &str) -> Option<f64> {
+        self.values.get(name).copied()
+    }
+}
+
+/// `red.max_p=0.2` from the command line
+#[derive(Debug, Clone)]
+pub struct ParamOverride {
+    pub strategy: String,
+    pub key: String,
+    pub value: f64,
+}
+
+impl FromStr for ParamOverride {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || anyhow::anyhow!("Bad parameter: {} (expected <strategy>.<name>=<value>, e.g. red.max_p=0.2)", s);
+        let (path, value) = s.split_once('=').ok_or_else(bad)?;
+        let (strategy, key) = path.trim().split_once('.').ok_or_else(bad)?;
+        Ok(Self {
+            strategy: strategy.to_string(),
+            key: key.to_string(),
+            value: value.trim().parse().map_err(|_| bad())?,
+        })
+    }
+}
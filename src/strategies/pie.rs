@@ -1,6 +1,8 @@
-use super::{Action, Strategy};
+use super::{Action, PacketQueue, Params, QueueEvents, Strategy, StrategyParam};
 use crate::network::Packet;
-use rand::Rng;
+use crate::simulation::clock;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 use std::time::{Duration, Instant};
 
 #[derive(Debug, Clone)]
@@ -15,21 +17,43 @@ pub struct Pie {
     burst_allowance: Duration,
     burst_start: Option<Instant>,
     bandwidth_bps: f64,
+    mark_ecn_threshold: f64,
+    rng: StdRng,
+    queue: PacketQueue,
 }
 
+pub const PARAMS: &[StrategyParam] = &[
+    StrategyParam { name: "target_ms", default: Some(15.0), min: 0.0, max: 10_000.0, description: "Queue delay the controller aims for" },
+    StrategyParam { name: "alpha", default: Some(0.125), min: 0.0, max: 10.0, description: "Weight of the distance to target" },
+    StrategyParam { name: "beta", default: Some(1.25), min: 0.0, max: 100.0, description: "Weight of the delay trend" },
+    StrategyParam { name: "update_ms", default: Some(30.0), min: 0.001, max: 10_000.0, description: "How often the drop probability gets recalculated" },
+    StrategyParam { name: "burst_ms", default: Some(150.0), min: 0.0, max: 10_000.0, description: "Burst allowance, no drops this long after the queue was short" },
+    StrategyParam { name: "mark_threshold", default: Some(0.1), min: 0.0, max: 1.0, description: "Above this drop probability ECN packets get dropped instead of marked" },
+    StrategyParam { name: "bandwidth_mbps", default: Some(100.0), min: 0.001, max: 1e6, description: "Link rate for the queue delay estimate when there's no sojourn time yet" },
+];
+
 impl Pie {
     pub fn new_with_bandwidth(bandwidth_mbps: f64) -> Self {
+        let mut params = Params::defaults(PARAMS);
+        params.set("bandwidth_mbps", bandwidth_mbps);
+        Self::with_params(&params)
+    }
+
+    pub fn with_params(params: &Params) -> Self {
         Self {
-            target_delay: Duration::from_millis(15),
+            target_delay: Duration::from_secs_f64(params.get("target_ms") / 1000.0),
             drop_prob: 0.0,
-            alpha: 0.125,
-            beta: 1.25,
-            last_update: Instant::now(),
-            update_interval: Duration::from_millis(30),
+            alpha: params.get("alpha"),
+            beta: params.get("beta"),
+            last_update: clock::now(),
+            update_interval: Duration::from_secs_f64(params.get("update_ms") / 1000.0),
             qdelay_old: 0.0,
-            burst_allowance: Duration::from_millis(150),
+            burst_allowance: Duration::from_secs_f64(params.get("burst_ms") / 1000.0),
             burst_start: None,
-            bandwidth_bps: bandwidth_mbps * 1_000_000.0,
+            bandwidth_bps: params.get("bandwidth_mbps") * 1_000_000.0,
+            mark_ecn_threshold: params.get("mark_threshold"),
+            rng: StdRng::from_entropy(),
+            queue: PacketQueue::new(),
         }
     }
 
@@ -37,37 +61,62 @@ impl Pie {
         Self::new_with_bandwidth(100.0)
     }
 
-    fn estimate_queue_delay(&self, queue_len: usize) -> f64 {
-        let packet_delay_ms = (1500.0 * 8.0) / self.bandwidth_bps * 1000.0;
-        queue_len as f64 * packet_delay_ms
+    // Time to drain what's queued at line rate
+    fn estimate_queue_delay(&self, queue_bytes: u64) -> f64 {
+        (queue_bytes as f64 * 8.0) / self.bandwidth_bps * 1000.0
     }
-}
 
-impl Strategy for Pie {
-    fn on_enqueue(&mut self, _packet: &Packet, queue_len: usize) -> Action {
-        let now = Instant::now();
+    fn admit(&mut self, packet: &Packet) -> Action {
+        let now = clock::now();
         
         // allow bursts within allowance window
-        if let Some(burst_start) = self.burst_start {
-            if now.duration_since(burst_start) < self.burst_allowance {
-                return Action::Accept;
-            }
+        if let Some(burst_start) = self.burst_start
+            && now.duration_since(burst_start) < self.burst_allowance
+        {
+            return Action::Accept;
         }
         
-        if queue_len < 10 {
+        if self.queue.len() < 10 {
             self.burst_start = Some(now);
         }
         
         // Probabilistic dropping based on drop_prob
-        if self.drop_prob > 0.0 && rand::thread_rng().r#gen::<f64>() < self.drop_prob {
-            Action::Drop
+        if self.drop_prob > 0.0 && self.rng.r#gen::<f64>() < self.drop_prob {
+            // RFC 8033: only mark while the probability is low, past that the senders clearly aren't listening
+            if self.drop_prob <= self.mark_ecn_threshold {
+                Action::signal(packet)
+            } else {
+                Action::Drop
+            }
         } else {
             Action::Accept
         }
     }
+}
 
-    fn update(&mut self, queue_len: usize, avg_sojourn_ms: f64) {
-        let now = Instant::now();
+impl Default for Pie {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Strategy for Pie {
+    fn enqueue(&mut self, packet: Packet, events: &mut QueueEvents) {
+        let action = self.admit(&packet);
+        if let Some(packet) = events.apply(action, packet) {
+            self.queue.push(packet);
+        }
+    }
+
+    fn dequeue(&mut self, _events: &mut QueueEvents) -> Option<Packet> {
+        self.queue.pop().map(|q| q.packet)
+    }
+
+    fn len(&self) -> usize { self.queue.len() }
+    fn bytes(&self) -> u64 { self.queue.bytes() }
+
+    fn update(&mut self, avg_sojourn_ms: f64) {
+        let now = clock::now();
         if now.duration_since(self.last_update) < self.update_interval {
             return;
         }
@@ -76,7 +125,7 @@ impl Strategy for Pie {
         let qdelay = if avg_sojourn_ms > 0.0 {
             avg_sojourn_ms
         } else {
-            self.estimate_queue_delay(queue_len)
+            self.estimate_queue_delay(self.queue.bytes())
         };
 
         let target_ms = self.target_delay.as_secs_f64() * 1000.0;
@@ -97,12 +146,15 @@ impl Strategy for Pie {
         self.drop_prob = 0.0;
         self.qdelay_old = 0.0;
         self.burst_start = None;
-        self.last_update = Instant::now();
+        self.last_update = clock::now();
+        self.queue.clear();
     }
 
     fn clone_box(&self) -> Box<dyn Strategy> {
         Box::new(self.clone())
     }
 
-    fn on_dequeue(&mut self, _queue_len: usize) { }
+    fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
 }
\ No newline at end of file
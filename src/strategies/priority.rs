@@ -0,0 +1,261 @@
+// Schedulers that look at Packet::priority. One band per priority class, all sharing the buffer.
+// Strict priority always serves the highest band first and lets the rest wait (or starve, that's what it's
+// compared for), DRR shares the link by weight so every class gets through, and drop precedence keeps a
+// single FIFO but stops admitting the lower classes earlier as the queue fills up.
+
+use super::{PacketQueue, Params, QueueEvents, Strategy, StrategyParam};
+use crate::network::{Packet, Priority};
+use std::collections::VecDeque;
+
+const BANDS: usize = Priority::ALL.len();
+
+/// A queue per priority, index is Priority::index
+#[derive(Debug, Clone, Default)]
+struct Bands {
+    queues: [PacketQueue; BANDS],
+}
+
+impl Bands {
+    fn push(&mut self, packet: Packet) {
+        self.queues[packet.priority.index()].push(packet);
+    }
+
+    fn len(&self) -> usize {
+        self.queues.iter().map(|q| q.len()).sum()
+    }
+
+    fn bytes(&self) -> u64 {
+        self.queues.iter().map(|q| q.bytes()).sum()
+    }
+
+    fn clear(&mut self) {
+        self.queues.iter_mut().for_each(|q| q.clear());
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StrictPriority {
+    buffer_size: usize,
+    push_out: bool,
+    bands: Bands,
+}
+
+pub const PRIO_PARAMS: &[StrategyParam] = &[
+    StrategyParam { name: "push_out", default: Some(1.0), min: 0.0, max: 1.0, description: "1 lets a packet into a full buffer by dropping the newest one of a lower class" },
+];
+
+impl StrictPriority {
+    pub fn new(buffer_size: usize) -> Self {
+        Self::with_params(buffer_size, &Params::defaults(PRIO_PARAMS))
+    }
+
+    pub fn with_params(buffer_size: usize, params: &Params) -> Self {
+        Self {
+            buffer_size,
+            push_out: params.get("push_out") >= 0.5,
+            bands: Bands::default(),
+        }
+    }
+}
+
+impl Strategy for StrictPriority {
+    fn enqueue(&mut self, packet: Packet, events: &mut QueueEvents) {
+        if self.bands.len() >= self.buffer_size {
+            // Lowest class first, and only classes below the one that wants in
+            let victim = self.push_out
+                .then(|| self.bands.queues[..packet.priority.index()].iter_mut().find_map(|q| q.pop_back()))
+                .flatten();
+            match victim {
+                Some(victim) => events.drop(victim.packet),
+                None => {
+                    events.drop(packet);
+                    return;
+                }
+            }
+        }
+        self.bands.push(packet);
+    }
+
+    fn dequeue(&mut self, _events: &mut QueueEvents) -> Option<Packet> {
+        self.bands.queues.iter_mut().rev().find_map(|q| q.pop()).map(|q| q.packet)
+    }
+
+    fn len(&self) -> usize { self.bands.len() }
+    fn bytes(&self) -> u64 { self.bands.bytes() }
+    fn update(&mut self, _avg_sojourn_ms: f64) { }
+    fn name(&self) -> &str { "Strict Priority" }
+    fn reset(&mut self) { self.bands.clear(); }
+
+    fn clone_box(&self) -> Box<dyn Strategy> {
+        Box::new(self.clone())
+    }
+}
+
+/// Deficit round robin over the bands, each band's quantum is `quantum` times its weight.
+/// With equal packet sizes that's plain weighted round robin
+#[derive(Debug, Clone)]
+pub struct Drr {
+    buffer_size: usize,
+    // Bytes per round per band
+    quanta: [i64; BANDS],
+    weights: [f64; BANDS],
+    deficits: [i64; BANDS],
+    // Bands with packets, in the order they get served
+    active: VecDeque<usize>,
+    bands: Bands,
+}
+
+pub const DRR_PARAMS: &[StrategyParam] = &[
+    StrategyParam { name: "quantum", default: Some(1500.0), min: 64.0, max: 65_536.0, description: "Bytes per round for a band of weight 1" },
+    StrategyParam { name: "weight_low", default: Some(1.0), min: 1.0, max: 1000.0, description: "Share of the low band" },
+    StrategyParam { name: "weight_normal", default: Some(2.0), min: 1.0, max: 1000.0, description: "Share of the normal band" },
+    StrategyParam { name: "weight_high", default: Some(4.0), min: 1.0, max: 1000.0, description: "Share of the high band" },
+    StrategyParam { name: "weight_critical", default: Some(8.0), min: 1.0, max: 1000.0, description: "Share of the critical band" },
+];
+
+impl Drr {
+    pub fn new(buffer_size: usize) -> Self {
+        Self::with_params(buffer_size, &Params::defaults(DRR_PARAMS))
+    }
+
+    pub fn with_params(buffer_size: usize, params: &Params) -> Self {
+        let weights = [
+            params.get("weight_low"),
+            params.get("weight_normal"),
+            params.get("weight_high"),
+            params.get("weight_critical"),
+        ];
+        let quantum = params.get("quantum");
+        Self {
+            buffer_size,
+            quanta: weights.map(|w| (quantum * w) as i64),
+            weights,
+            deficits: [0; BANDS],
+            active: VecDeque::new(),
+            bands: Bands::default(),
+        }
+    }
+
+    // A band that ran empty leaves the round and loses what it had saved up
+    fn retire_if_empty(&mut self, band: usize) {
+        if self.bands.queues[band].is_empty() {
+            self.active.retain(|&b| b != band);
+            self.deficits[band] = 0;
+        }
+    }
+}
+
+impl Strategy for Drr {
+    fn enqueue(&mut self, packet: Packet, events: &mut QueueEvents) {
+        let band = packet.priority.index();
+        if self.bands.queues[band].is_empty() {
+            self.active.push_back(band);
+        }
+        self.bands.push(packet);
+
+        // Full: the band furthest over its weighted share of the buffer loses its head, like FQ-CoDel
+        // does with the fattest flow. A flood of telemetry can't push command and control out this way
+        if self.bands.len() > self.buffer_size {
+            let fattest = (0..BANDS)
+                .max_by(|&a, &b| {
+                    let share = |i: usize| self.bands.queues[i].bytes() as f64 / self.weights[i];
+                    share(a).total_cmp(&share(b))
+                })
+                .expect("there are bands");
+            if let Some(head) = self.bands.queues[fattest].pop() {
+                events.drop(head.packet);
+            }
+            self.retire_if_empty(fattest);
+        }
+    }
+
+    fn dequeue(&mut self, _events: &mut QueueEvents) -> Option<Packet> {
+        loop {
+            let band = *self.active.front()?;
+            let size = self.bands.queues[band].front()?.packet.payload_size as i64;
+            if self.deficits[band] < size {
+                // Not enough saved up, top up and let the next band have a go
+                self.deficits[band] += self.quanta[band];
+                self.active.rotate_left(1);
+                continue;
+            }
+
+            self.deficits[band] -= size;
+            let queued = self.bands.queues[band].pop()?;
+            self.retire_if_empty(band);
+            return Some(queued.packet);
+        }
+    }
+
+    fn len(&self) -> usize { self.bands.len() }
+    fn bytes(&self) -> u64 { self.bands.bytes() }
+    fn update(&mut self, _avg_sojourn_ms: f64) { }
+    fn name(&self) -> &str { "DRR" }
+
+    fn reset(&mut self) {
+        self.bands.clear();
+        self.active.clear();
+        self.deficits = [0; BANDS];
+    }
+
+    fn clone_box(&self) -> Box<dyn Strategy> {
+        Box::new(self.clone())
+    }
+}
+
+/// One FIFO, every class gets admitted up to its own fill level. Critical only ever sees a full buffer
+#[derive(Debug, Clone)]
+pub struct DropPrecedence {
+    // Packets per class, index is Priority::index
+    limits: [usize; BANDS],
+    queue: PacketQueue,
+}
+
+pub const DROP_PRECEDENCE_PARAMS: &[StrategyParam] = &[
+    StrategyParam { name: "low_threshold", default: Some(0.5), min: 0.0, max: 1.0, description: "Fill level (fraction of the buffer) where low priority packets stop getting in" },
+    StrategyParam { name: "normal_threshold", default: Some(0.7), min: 0.0, max: 1.0, description: "Same for normal priority" },
+    StrategyParam { name: "high_threshold", default: Some(0.9), min: 0.0, max: 1.0, description: "Same for high priority" },
+];
+
+impl DropPrecedence {
+    pub fn new(buffer_size: usize) -> Self {
+        Self::with_params(buffer_size, &Params::defaults(DROP_PRECEDENCE_PARAMS))
+    }
+
+    pub fn with_params(buffer_size: usize, params: &Params) -> Self {
+        let limit = |name: &str| (buffer_size as f64 * params.get(name)).round() as usize;
+        Self {
+            limits: [
+                limit("low_threshold"),
+                limit("normal_threshold"),
+                limit("high_threshold"),
+                buffer_size,
+            ],
+            queue: PacketQueue::new(),
+        }
+    }
+}
+
+impl Strategy for DropPrecedence {
+    fn enqueue(&mut self, packet: Packet, events: &mut QueueEvents) {
+        if self.queue.len() >= self.limits[packet.priority.index()] {
+            events.drop(packet);
+        } else {
+            self.queue.push(packet);
+        }
+    }
+
+    fn dequeue(&mut self, _events: &mut QueueEvents) -> Option<Packet> {
+        self.queue.pop().map(|q| q.packet)
+    }
+
+    fn len(&self) -> usize { self.queue.len() }
+    fn bytes(&self) -> u64 { self.queue.bytes() }
+    fn update(&mut self, _avg_sojourn_ms: f64) { }
+    fn name(&self) -> &str { "Drop Precedence" }
+    fn reset(&mut self) { self.queue.clear(); }
+
+    fn clone_box(&self) -> Box<dyn Strategy> {
+        Box::new(self.clone())
+    }
+}
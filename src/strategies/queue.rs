@@ -0,0 +1,123 @@
+// The actual packet queue, owned by the strategy since the qdisc rework.
+// Most strategies are a single FIFO with an admission check in front, FQ-CoDel keeps one per flow.
+
+use super::Action;
+use crate::network::{Ecn, Packet};
+use crate::simulation::clock;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct QueuedPacket {
+    pub packet: Packet,
+    pub enqueued_at: Instant,
+}
+
+impl QueuedPacket {
+    /// Time spent in this queue only, unlike Packet::sojourn_time which starts at creation
+    pub fn sojourn_time(&self) -> Duration {
+        clock::elapsed(self.enqueued_at)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PacketQueue {
+    packets: VecDeque<QueuedPacket>,
+    bytes: u64,
+}
+
+impl PacketQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, packet: Packet) {
+        self.bytes += packet.payload_size as u64;
+        self.packets.push_back(QueuedPacket {
+            packet,
+            enqueued_at: clock::now(),
+        });
+    }
+
+    pub fn pop(&mut self) -> Option<QueuedPacket> {
+        let queued = self.packets.pop_front()?;
+        self.bytes -= queued.packet.payload_size as u64;
+        Some(queued)
+    }
+
+    /// Newest packet, for pushing out the last one in when a more important one needs the room
+    pub fn pop_back(&mut self) -> Option<QueuedPacket> {
+        let queued = self.packets.pop_back()?;
+        self.bytes -= queued.packet.payload_size as u64;
+        Some(queued)
+    }
+
+    pub fn front(&self) -> Option<&QueuedPacket> {
+        self.packets.front()
+    }
+
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    /// Payload bytes waiting, packets vary in size so len() alone says little about delay
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    /// How long the head has been waiting, zero for an empty queue
+    pub fn head_sojourn(&self) -> Duration {
+        self.front().map(|q| q.sojourn_time()).unwrap_or(Duration::ZERO)
+    }
+
+    pub fn clear(&mut self) {
+        self.packets.clear();
+        self.bytes = 0;
+    }
+}
+
+/// What a strategy did to packets besides queueing them, handed in by the server on every call
+/// so drops and marks end up in the metrics and go back to the sender
+#[derive(Debug, Default)]
+pub struct QueueEvents {
+    pub dropped: Vec<Packet>,
+    pub marked: u64,
+}
+
+impl QueueEvents {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn drop(&mut self, packet: Packet) {
+        self.dropped.push(packet);
+    }
+
+    /// Carries out a decision. Returns the packet if it should stay in (or leave) the queue,
+    /// None if it got dropped. A mark on a non-ECT packet is a drop, RFC 3168
+    pub fn apply(&mut self, action: Action, mut packet: Packet) -> Option<Packet> {
+        match action {
+            Action::Accept => Some(packet),
+            Action::Drop => {
+                self.drop(packet);
+                None
+            }
+            Action::Mark => {
+                if packet.mark_ce() {
+                    self.marked += 1;
+                    Some(packet)
+                } else if packet.ecn == Ecn::Ce {
+                    // Already CE from somewhere upstream is fine, NotEct isn't
+                    Some(packet)
+                } else {
+                    self.drop(packet);
+                    None
+                }
+            }
+        }
+    }
+}
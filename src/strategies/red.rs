@@ -1,6 +1,8 @@
-use super::{Action, Strategy};
+use super::{Action, PacketQueue, Params, QueueEvents, Strategy, StrategyParam};
 use crate::network::Packet;
-use rand::Rng;
+use crate::simulation::clock;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 
 #[derive(Debug, Clone)]
 pub struct Red {
@@ -10,19 +12,34 @@ pub struct Red {
     w_q: f64,
     pub avg_queue: f64,
     count: usize,
+    rng: StdRng,
+    queue: PacketQueue,
 }
 
+pub const PARAMS: &[StrategyParam] = &[
+    StrategyParam { name: "min_th", default: None, min: 0.0, max: 1e6, description: "Average queue (packets) where dropping starts, auto is 30% of the buffer (at least 5)" },
+    StrategyParam { name: "max_th", default: None, min: 0.0, max: 1e6, description: "Average queue (packets) where every packet gets dropped, auto is 90% of the buffer" },
+    StrategyParam { name: "max_p", default: Some(0.1), min: 0.0, max: 1.0, description: "Drop probability at max_th" },
+    StrategyParam { name: "w_q", default: Some(0.02), min: 0.0, max: 1.0, description: "EWMA weight of the average queue length" },
+];
+
 impl Red {
     pub fn new(buffer_size: usize) -> Self {
-        let min_th = (buffer_size as f64 * 0.3).max(5.0);
-        let max_th = buffer_size as f64 * 0.9;
+        Self::with_params(buffer_size, &Params::defaults(PARAMS))
+    }
+
+    pub fn with_params(buffer_size: usize, params: &Params) -> Self {
+        let min_th = params.get_opt("min_th").unwrap_or((buffer_size as f64 * 0.3).max(5.0));
+        let max_th = params.get_opt("max_th").unwrap_or(buffer_size as f64 * 0.9);
         Self {
             min_th,
             max_th,
-            max_p: 0.1,
-            w_q: 0.02,
+            max_p: params.get("max_p"),
+            w_q: params.get("w_q"),
             avg_queue: 0.0,
             count: 0,
+            rng: StdRng::from_entropy(),
+            queue: PacketQueue::new(),
         }
     }
 
@@ -37,33 +54,45 @@ impl Red {
             p_b / (1.0 - (self.count as f64) * p_b)
         }
     }
-}
 
-impl Strategy for Red {
-    fn on_enqueue(&mut self, _packet: &Packet, queue_len: usize) -> Action {
+    fn admit(&mut self, packet: &Packet) -> Action {
         // Update EWMA (Exponentially Weighted Moving Average) of queue length
-        self.avg_queue = (1.0 - self.w_q) * self.avg_queue + self.w_q * (queue_len as f64);
+        self.avg_queue = (1.0 - self.w_q) * self.avg_queue + self.w_q * (self.queue.len() as f64);
         let drop_prob = self.calc_probability(self.avg_queue);
     
         if drop_prob >= 1.0 {
             // Force drop when above max_th
             self.count = 0;
             Action::Drop
-        } else if drop_prob > 0.0 && rand::thread_rng().r#gen::<f64>() < drop_prob {
-            // Probabilistic drop between min_th and max_th
+        } else if drop_prob > 0.0 && self.rng.r#gen::<f64>() < drop_prob {
+            // Probabilistic drop (or mark) between min_th and max_th
             self.count = 0;
-            Action::Drop
+            Action::signal(packet)
         } else {
             self.count += 1;
             Action::Accept
         }
     }
+}
+
+impl Strategy for Red {
+    fn enqueue(&mut self, packet: Packet, events: &mut QueueEvents) {
+        let action = self.admit(&packet);
+        if let Some(packet) = events.apply(action, packet) {
+            self.queue.push(packet);
+        }
+    }
 
-    fn on_dequeue(&mut self, _queue_len: usize) { }
+    fn dequeue(&mut self, _events: &mut QueueEvents) -> Option<Packet> {
+        self.queue.pop().map(|q| q.packet)
+    }
+
+    fn len(&self) -> usize { self.queue.len() }
+    fn bytes(&self) -> u64 { self.queue.bytes() }
 
-    fn update(&mut self, queue_len: usize, _avg_sojourn_ms: f64) {
+    fn update(&mut self, _avg_sojourn_ms: f64) {
         // Update EWMA periodically
-        self.avg_queue = (1.0 - self.w_q) * self.avg_queue + self.w_q * (queue_len as f64);
+        self.avg_queue = (1.0 - self.w_q) * self.avg_queue + self.w_q * (self.queue.len() as f64);
     }
 
     fn name(&self) -> &str { "RED" }
@@ -71,11 +100,16 @@ impl Strategy for Red {
     fn reset(&mut self) {
         self.avg_queue = 0.0;
         self.count = 0;
+        self.queue.clear();
     }
 
     fn clone_box(&self) -> Box<dyn Strategy> {
         Box::new(self.clone())
     }
+
+    fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -87,39 +121,57 @@ pub struct AdaptiveRed {
     last_update: std::time::Instant,
 }
 
+// RED's own knobs plus the adaptation, max_p is only the starting point here
+pub const ADAPTIVE_PARAMS: &[StrategyParam] = &[
+    PARAMS[0],
+    PARAMS[1],
+    PARAMS[2],
+    PARAMS[3],
+    StrategyParam { name: "target", default: None, min: 0.0, max: 1e6, description: "Average queue (packets) max_p steers towards, auto is halfway between the thresholds" },
+    StrategyParam { name: "alpha", default: Some(0.01), min: 0.0, max: 1.0, description: "Additive max_p increase while below target" },
+    StrategyParam { name: "beta", default: Some(0.9), min: 0.0, max: 1.0, description: "Multiplicative max_p decrease while above target" },
+];
+
 impl AdaptiveRed {
     pub fn new(buffer_size: usize) -> Self {
-        let red = Red::new(buffer_size);
-        let target = 0.5 * (red.min_th + red.max_th);
+        Self::with_params(buffer_size, &Params::defaults(ADAPTIVE_PARAMS))
+    }
+
+    pub fn with_params(buffer_size: usize, params: &Params) -> Self {
+        let red = Red::with_params(buffer_size, params);
+        let target = params.get_opt("target").unwrap_or(0.5 * (red.min_th + red.max_th));
         Self {
             red,
             target,
-            alpha: 0.01,
-            beta: 0.9,
-            last_update: std::time::Instant::now(),
+            alpha: params.get("alpha"),
+            beta: params.get("beta"),
+            last_update: clock::now(),
         }
     }
 }
 
 impl Strategy for AdaptiveRed {
-    fn on_enqueue(&mut self, packet: &Packet, queue_len: usize) -> Action {
-        self.red.on_enqueue(packet, queue_len)
+    fn enqueue(&mut self, packet: Packet, events: &mut QueueEvents) {
+        self.red.enqueue(packet, events);
     }
 
-    fn on_dequeue(&mut self, queue_len: usize) {
-        self.red.on_dequeue(queue_len);
+    fn dequeue(&mut self, events: &mut QueueEvents) -> Option<Packet> {
+        self.red.dequeue(events)
     }
 
-    fn update(&mut self, queue_len: usize, avg_sojourn_ms: f64) {
-        self.red.update(queue_len, avg_sojourn_ms);
+    fn len(&self) -> usize { self.red.len() }
+    fn bytes(&self) -> u64 { self.red.bytes() }
+
+    fn update(&mut self, avg_sojourn_ms: f64) {
+        self.red.update(avg_sojourn_ms);
         
-        if self.last_update.elapsed().as_millis() >= 500 {
+        if clock::elapsed(self.last_update).as_millis() >= 500 {
             if self.red.avg_queue < self.target && self.red.max_p < 0.5 {
                 self.red.max_p += self.alpha.min(self.red.max_p / 4.0);
             } else if self.red.avg_queue > self.target && self.red.max_p > 0.01 {
                 self.red.max_p *= self.beta;
             }
-            self.last_update = std::time::Instant::now();
+            self.last_update = clock::now();
         }
     }
 
@@ -127,10 +179,14 @@ impl Strategy for AdaptiveRed {
 
     fn reset(&mut self) {
         self.red.reset();
-        self.last_update = std::time::Instant::now();
+        self.last_update = clock::now();
     }
 
     fn clone_box(&self) -> Box<dyn Strategy> {
         Box::new(self.clone())
     }
-}
\ No newline at end of file
+
+    fn reseed(&mut self, seed: u64) {
+        self.red.reseed(seed);
+    }
+}
@@ -1,32 +1,41 @@
 // A catch all for FIFO and DropTail, they're both pretty simplistic anyway
 
-use super::{Action, Strategy};
+use super::{PacketQueue, QueueEvents, Strategy};
 use crate::network::Packet;
 
 #[derive(Debug, Clone)]
 pub struct DropTail {
     buffer_size: usize,
+    queue: PacketQueue,
 }
 
 impl DropTail {
     pub fn new(buffer_size: usize) -> Self {
-        Self { buffer_size }
+        Self {
+            buffer_size,
+            queue: PacketQueue::new(),
+        }
     }
 }
 
 impl Strategy for DropTail {
-    fn on_enqueue(&mut self, _packet: &Packet, queue_len: usize) -> Action {
-        if queue_len >= self.buffer_size {
-            Action::Drop
+    fn enqueue(&mut self, packet: Packet, events: &mut QueueEvents) {
+        if self.queue.len() >= self.buffer_size {
+            events.drop(packet);
         } else {
-            Action::Accept
+            self.queue.push(packet);
         }
     }
 
-    fn on_dequeue(&mut self, _queue_len: usize) { }
-    fn update(&mut self, _queue_len: usize, _avg_sojourn_ms: f64) { }
+    fn dequeue(&mut self, _events: &mut QueueEvents) -> Option<Packet> {
+        self.queue.pop().map(|q| q.packet)
+    }
+
+    fn len(&self) -> usize { self.queue.len() }
+    fn bytes(&self) -> u64 { self.queue.bytes() }
+    fn update(&mut self, _avg_sojourn_ms: f64) { }
     fn name(&self) -> &str { "DropTail" }
-    fn reset(&mut self) { }
+    fn reset(&mut self) { self.queue.clear(); }
 
     fn clone_box(&self) -> Box<dyn Strategy> {
         Box::new(self.clone())
@@ -36,29 +45,38 @@ impl Strategy for DropTail {
 #[derive(Debug, Clone)]
 pub struct Fifo {
     buffer_size: usize,
+    queue: PacketQueue,
 }
 
 impl Fifo {
     pub fn new(buffer_size: usize) -> Self {
-        Self { buffer_size }
+        Self {
+            buffer_size,
+            queue: PacketQueue::new(),
+        }
     }
 }
 
 impl Strategy for Fifo {
-    fn on_enqueue(&mut self, _packet: &Packet, queue_len: usize) -> Action {
-        if queue_len >= self.buffer_size {
-            Action::Drop
+    fn enqueue(&mut self, packet: Packet, events: &mut QueueEvents) {
+        if self.queue.len() >= self.buffer_size {
+            events.drop(packet);
         } else {
-            Action::Accept
+            self.queue.push(packet);
         }
     }
 
-    fn on_dequeue(&mut self, _queue_len: usize) { }
-    fn update(&mut self, _queue_len: usize, _avg_sojourn_ms: f64) { }
+    fn dequeue(&mut self, _events: &mut QueueEvents) -> Option<Packet> {
+        self.queue.pop().map(|q| q.packet)
+    }
+
+    fn len(&self) -> usize { self.queue.len() }
+    fn bytes(&self) -> u64 { self.queue.bytes() }
+    fn update(&mut self, _avg_sojourn_ms: f64) { }
     fn name(&self) -> &str { "FIFO" }
-    fn reset(&mut self) { }
+    fn reset(&mut self) { self.queue.clear(); }
 
     fn clone_box(&self) -> Box<dyn Strategy> {
         Box::new(self.clone())
     }
-}
\ No newline at end of file
+}
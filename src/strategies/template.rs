@@ -1,7 +1,7 @@
 // Template strategy, there are examples included but they were AI written
 // I will try to write a proper guide ASAP 
 
-use super::{Action, Strategy};
+use super::{Action, PacketQueue, Params, QueueEvents, Strategy, StrategyParam};
 use crate::network::Packet;
 
 #[derive(Debug, Clone)]
@@ -11,6 +11,9 @@ pub struct MyStrategy { // Replace MyStrategy with your strategy name
     threshold: f64,
     drop_count: u64,
     
+    // The strategy owns its queue, PacketQueue remembers when each packet went in
+    queue: PacketQueue,
+    
     // Add any other parameters you need:
     // - Counters (packets sent/received/dropped)
     // - Timers (last update time, intervals)
@@ -18,13 +21,24 @@ pub struct MyStrategy { // Replace MyStrategy with your strategy name
     // - Algorithm specific state
 }
 
+/// Knobs users can set with --param mystrategy.threshold=0.9, checked against min/max before you see them
+pub const PARAMS: &[StrategyParam] = &[
+    StrategyParam { name: "threshold", default: Some(0.8), min: 0.0, max: 1.0, description: "Drop above this buffer fill" },
+];
+
 impl MyStrategy {
     // Constructor to initialize your strategy
     pub fn new(buffer_size: usize) -> Self {
+        Self::with_params(buffer_size, &Params::defaults(PARAMS))
+    }
+
+    /// What the registry calls: register("mystrategy", template::PARAMS, |size, p| Box::new(MyStrategy::with_params(size, p)))
+    pub fn with_params(buffer_size: usize, params: &Params) -> Self {
         Self {
             buffer_size,
-            threshold: 0.8,  // Example: 80% buffer threshold
+            threshold: params.get("threshold"),  // Example: 80% buffer threshold
             drop_count: 0,
+            queue: PacketQueue::new(),
         }
     }
     
@@ -36,7 +50,7 @@ impl MyStrategy {
     }
     
     /// Optional: Add more builder methods as needed
-    pub fn with_custom_param(mut self, _param: f64) -> Self {
+    pub fn with_custom_param(self, _param: f64) -> Self {
         // self.custom_param = param;
         self
     }
@@ -44,60 +58,41 @@ impl MyStrategy {
 
 impl Strategy for MyStrategy {
     /// Called when a packet arrives at the queue
-    /// Return Action::Accept to enqueue, Action::Drop to drop
-    fn on_enqueue(&mut self, packet: &Packet, queue_len: usize) -> Action {
-        // IMPLEMENT YOUR ENQUEUE LOGIC HERE
-        
-        // Example: Simple threshold based dropping
-        let utilization = queue_len as f64 / self.buffer_size as f64;
-        
-        if utilization > self.threshold {
-            self.drop_count += 1;
-            Action::Drop
-        } else {
-            Action::Accept
+    /// Decide with an Action, events.apply() drops or marks it, whatever comes back goes in the queue
+    fn enqueue(&mut self, packet: Packet, events: &mut QueueEvents) {
+        let action = self.admit(&packet);
+        if let Some(packet) = events.apply(action, packet) {
+            self.queue.push(packet);
         }
-        
-        // Other common patterns:
-        //
-        // 1. Probabilistic dropping (like with RED):
-        // if rand::thread_rng().gen::<f64>() < drop_probability {
-        //     Action::Drop
-        // } else {
-        //     Action::Accept
-        // }
-        //
-        // 2. Priority-based:
-        // match packet.priority {
-        //     Priority::Critical => Action::Accept,
-        //     Priority::Low if queue_len > threshold => Action::Drop,
-        //     _ => Action::Accept,
-        // }
-        //
-        // 3. Time-based (check packet.timestamp):
-        // if packet.sojourn_time() > max_delay {
-        //     Action::Drop
-        // } else {
-        //     Action::Accept
-        // }
     }
     
-    /// Called when a packet is removed from the queue
-    /// Use this to update state after a dequeue
-    fn on_dequeue(&mut self, queue_len: usize) {
+    /// Called when the server has room to send a packet
+    /// Return the packet to send, you can also drop heads here (events.drop) like CoDel does
+    fn dequeue(&mut self, _events: &mut QueueEvents) -> Option<Packet> {
+        let queued = self.queue.pop()?;
+        
         // optional: Implement dequeue logic here
+        // queued.sojourn_time() is how long it waited in this queue
         
         // Detect idle link
-        // if queue_len == 0 {
+        // if self.queue.is_empty() {
         //     self.link_idle_count += 1;
         // }
         
-        // Most strategies don't need this.
+        Some(queued.packet)
+    }
+    
+    fn len(&self) -> usize {
+        self.queue.len()
     }
     
-    /// Called once in a while (~100ms) to update strategy state
+    fn bytes(&self) -> u64 {
+        self.queue.bytes()
+    }
+    
+    /// Called once in a while (every few transmission slots) to update strategy state
     /// Use this for adaptive algorithms
-    fn update(&mut self, queue_len: usize, avg_sojourn_ms: f64) {
+    fn update(&mut self, _avg_sojourn_ms: f64) {
         // OPTIONAL: Implement periodic update logic
         
         // Example: Adaptive threshold adjustment
@@ -108,7 +103,7 @@ impl Strategy for MyStrategy {
         // }
         
         // Example: Moving average update
-        // self.avg_queue = 0.9 * self.avg_queue + 0.1 * (queue_len as f64);
+        // self.avg_queue = 0.9 * self.avg_queue + 0.1 * (self.queue.len() as f64);
     }
     
     fn name(&self) -> &str {
@@ -119,6 +114,7 @@ impl Strategy for MyStrategy {
     fn reset(&mut self) {
         // Reset all state variables to initial values
         self.drop_count = 0;
+        self.queue.clear();
         // Reset other state variables as needed
     }
     
@@ -127,3 +123,45 @@ impl Strategy for MyStrategy {
         Box::new(self.clone())
     }
 }
+
+impl MyStrategy {
+    /// Your admission decision
+    /// Return Action::Accept to enqueue, Action::Drop to drop, Action::signal(packet) to mark if possible
+    fn admit(&mut self, _packet: &Packet) -> Action {
+        // IMPLEMENT YOUR ENQUEUE LOGIC HERE
+        
+        // Example: Simple threshold based dropping
+        let utilization = self.queue.len() as f64 / self.buffer_size as f64;
+        
+        if utilization > self.threshold {
+            self.drop_count += 1;
+            Action::Drop
+        } else {
+            Action::Accept
+        }
+        
+        // Other common patterns:
+        //
+        // 1. Probabilistic dropping (like with RED):
+        // (keep a StdRng field and override reseed() so runs stay reproducible)
+        // if self.rng.gen::<f64>() < drop_probability {
+        //     Action::Drop
+        // } else {
+        //     Action::Accept
+        // }
+        //
+        // 2. Priority-based:
+        // match packet.priority {
+        //     Priority::Critical => Action::Accept,
+        //     Priority::Low if self.queue.len() > threshold => Action::Drop,
+        //     _ => Action::Accept,
+        // }
+        //
+        // 3. Time-based (time in this queue, needs the QueuedPacket so this fits in dequeue):
+        // if queued.sojourn_time() > max_delay {
+        //     Action::Drop
+        // } else {
+        //     Action::Accept
+        // }
+    }
+}